@@ -60,7 +60,7 @@ async fn main() {
 
     #[transaction_handler]
     async fn transaction_handler(
-        context: TransactionHandlerContext<State>,
+        context: TransactionHandlerContext<State, TransactionContext>,
     ) -> Result<(), TransactionHandlerError> {
         let tx = context.state.pool.begin().await.unwrap();
         let mut transaction_context = TransactionContext { transaction: tx };
@@ -97,9 +97,9 @@ async fn main() {
 }
 
 async fn run_from_file(
-    handler_registry: HandlerRegistry,
+    handler_registry: HandlerRegistry<State, TransactionContext>,
     pool: Pool<Sqlite>,
-    transaction_handler: impl TransactionHandler<State> + 'static,
+    transaction_handler: impl TransactionHandler<State, TransactionContext>,
 ) {
     // Create a new transaction stream from a file, which the processor will use
     // as a source of transactions.
@@ -114,17 +114,21 @@ async fn run_from_file(
     };
 
     // Start with parameters.
-    TransactionStreamProcessor::new(stream, handler_registry, state)
-        .transaction_handler(transaction_handler)
+    TransactionStreamProcessor::with_transaction_handler(
+        stream,
+        handler_registry,
+        state,
+        transaction_handler,
+    )
         .run()
         .await
         .unwrap();
 }
 
 async fn run_from_gateway(
-    handler_registry: HandlerRegistry,
+    handler_registry: HandlerRegistry<State, TransactionContext>,
     pool: Pool<Sqlite>,
-    transaction_handler: impl TransactionHandler<State> + 'static,
+    transaction_handler: impl TransactionHandler<State, TransactionContext>,
 ) {
     // Create a new transaction stream, which the processor will use
     // as a source of transactions.
@@ -141,17 +145,21 @@ async fn run_from_gateway(
     };
 
     // Start with parameters.
-    TransactionStreamProcessor::new(stream, handler_registry, state)
-        .transaction_handler(transaction_handler)
+    TransactionStreamProcessor::with_transaction_handler(
+        stream,
+        handler_registry,
+        state,
+        transaction_handler,
+    )
         .run()
         .await
         .unwrap();
 }
 
 async fn run_from_database(
-    handler_registry: HandlerRegistry,
+    handler_registry: HandlerRegistry<State, TransactionContext>,
     pool: Pool<Sqlite>,
-    transaction_handler: impl TransactionHandler<State> + 'static,
+    transaction_handler: impl TransactionHandler<State, TransactionContext>,
 ) {
     // Create a new transaction stream, which the processor will use
     // as a source of transactions.
@@ -172,8 +180,12 @@ async fn run_from_database(
     };
 
     // Start with parameters.
-    TransactionStreamProcessor::new(stream, handler_registry, state)
-        .transaction_handler(transaction_handler)
+    TransactionStreamProcessor::with_transaction_handler(
+        stream,
+        handler_registry,
+        state,
+        transaction_handler,
+    )
         .default_logger_with_report_interval(Duration::from_millis(500))
         .run()
         .await
@@ -0,0 +1,59 @@
+//! A checkpoint store that keeps the last finished state version in a file.
+
+use super::CheckpointStore;
+use async_trait::async_trait;
+use std::{io::ErrorKind, path::PathBuf};
+
+/// A checkpoint store that writes the last finished state version
+/// to a plain text file. The file is replaced atomically on each save,
+/// so a crash while saving never leaves a corrupted checkpoint behind.
+#[derive(Debug)]
+pub struct FileCheckpointStore {
+    file_path: PathBuf,
+}
+
+impl FileCheckpointStore {
+    /// Creates a new FileCheckpointStore which stores the checkpoint
+    /// at the given path. The file is created on the first save.
+    pub fn new(file_path: String) -> Self {
+        Self {
+            file_path: PathBuf::from(file_path),
+        }
+    }
+
+    fn temporary_path(&self) -> PathBuf {
+        let mut path = self.file_path.clone().into_os_string();
+        path.push(".tmp");
+        PathBuf::from(path)
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for FileCheckpointStore {
+    async fn load(&self) -> Result<Option<u64>, anyhow::Error> {
+        match tokio::fs::read_to_string(&self.file_path).await {
+            Ok(contents) => {
+                let state_version =
+                    contents.trim().parse::<u64>().map_err(|err| {
+                        anyhow::anyhow!(
+                            "Invalid checkpoint in {:?}: {}",
+                            self.file_path,
+                            err
+                        )
+                    })?;
+                Ok(Some(state_version))
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn save(&self, state_version: u64) -> Result<(), anyhow::Error> {
+        // Write to a temporary file first and rename it afterwards,
+        // which replaces the checkpoint atomically.
+        let temporary_path = self.temporary_path();
+        tokio::fs::write(&temporary_path, state_version.to_string()).await?;
+        tokio::fs::rename(&temporary_path, &self.file_path).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+//! Persistent checkpoints which allow a [`TransactionStreamProcessor`][crate::processor::TransactionStreamProcessor]
+//! to resume where it stopped after a restart.
+//!
+//! The processor writes the state version of the last finished transaction
+//! to a [`CheckpointStore`] after each transaction, and passes the stored
+//! checkpoint on to the [`TransactionStream`][crate::stream::TransactionStream]
+//! when it starts.
+//!
+//! Like the sources, the implementations provided by the framework
+//! are behind feature flags.
+
+use async_trait::async_trait;
+
+#[cfg(feature = "file")]
+pub mod file;
+#[cfg(any(feature = "database", feature = "sqlite"))]
+#[macro_use]
+mod sql;
+#[cfg(feature = "database")]
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// A trait that abstracts a persistent store for the state version
+/// of the last transaction a processor has finished.
+#[async_trait]
+pub trait CheckpointStore: Send + Sync {
+    /// Loads the state version of the last finished transaction,
+    /// or `None` if no checkpoint was stored yet.
+    async fn load(&self) -> Result<Option<u64>, anyhow::Error>;
+
+    /// Stores the state version of the last finished transaction.
+    async fn save(&self, state_version: u64) -> Result<(), anyhow::Error>;
+}
@@ -0,0 +1,12 @@
+//! A checkpoint store that keeps the last finished state version in a PostgreSQL database.
+
+sql_checkpoint_store! {
+    database: Postgres,
+    database_name: "PostgreSQL",
+    connection: PgConnection,
+    big_integer: "BIGINT",
+    store: PostgresCheckpointStore,
+    transaction_handler: PostgresTransactionHandler,
+    transaction_context: PostgresTransactionContext,
+    registration_store: crate::registrations::postgres::PostgresRegistrationStore,
+}
@@ -0,0 +1,389 @@
+//! The implementation which is shared by the PostgreSQL and SQLite
+//! checkpoint stores and transaction handlers.
+//!
+//! The queries use `$1`-style placeholders, which both databases understand.
+//! Only the names, the connection types and the column type of
+//! the state version differ, and these are passed to the macro.
+
+macro_rules! sql_checkpoint_store {
+    (
+        database: $database:ident,
+        database_name: $database_name:literal,
+        connection: $connection:ident,
+        big_integer: $big_integer:literal,
+        store: $store:ident,
+        transaction_handler: $transaction_handler:ident,
+        transaction_context: $transaction_context:ident,
+        registration_store: $registration_store:path,
+    ) => {
+        use super::CheckpointStore;
+        use crate::{
+            error::TransactionHandlerError,
+            event_handler::{HandlerRegistry, State},
+            registrations::Registration,
+            transaction_handler::{
+                BatchTransactionHandler, BatchTransactionHandlerContext,
+                Savepoints, TransactionHandler, TransactionHandlerContext,
+            },
+        };
+        use async_trait::async_trait;
+        use sqlx::{$connection, $database, Pool};
+        use std::sync::Arc;
+        use tokio::sync::OnceCell;
+
+        #[doc = concat!("The transaction context which is passed to event handlers
+by a [`", stringify!($transaction_handler), "`]. It is the database transaction
+in which the handlers should do their writes.")]
+        pub type $transaction_context = sqlx::Transaction<'static, $database>;
+
+        #[async_trait]
+        impl Savepoints for $transaction_context {
+            async fn savepoint(&mut self) -> Result<(), anyhow::Error> {
+                sqlx::query("SAVEPOINT event_handler")
+                    .execute(&mut **self)
+                    .await?;
+                Ok(())
+            }
+
+            async fn rollback_to_savepoint(
+                &mut self,
+            ) -> Result<(), anyhow::Error> {
+                sqlx::query("ROLLBACK TO SAVEPOINT event_handler")
+                    .execute(&mut **self)
+                    .await?;
+                Ok(())
+            }
+
+            async fn release_savepoint(&mut self) -> Result<(), anyhow::Error> {
+                sqlx::query("RELEASE SAVEPOINT event_handler")
+                    .execute(&mut **self)
+                    .await?;
+                Ok(())
+            }
+        }
+
+        #[doc = concat!("A checkpoint store that keeps checkpoints in a table of a ", $database_name, " database.
+The table is created on first use if it doesn't exist yet.
+
+Each checkpoint is identified by a name, so that multiple processors
+can share the same table.")]
+        #[derive(Debug)]
+        pub struct $store {
+            pool: Pool<$database>,
+            name: String,
+            table_name: String,
+            table_created: OnceCell<()>,
+        }
+
+        impl $store {
+            #[doc = concat!("Creates a new ", stringify!($store), " with default settings
+which uses the given connection pool.")]
+            pub fn new(pool: Pool<$database>) -> Self {
+                Self {
+                    pool,
+                    name: "default".to_string(),
+                    table_name: "checkpoints".to_string(),
+                    table_created: OnceCell::new(),
+                }
+            }
+
+            /// Sets the name which identifies the checkpoint of this processor.
+            pub fn name(mut self, name: String) -> Self {
+                self.name = name;
+                self
+            }
+
+            /// Sets the name of the table in which checkpoints are stored.
+            pub fn table_name(mut self, table_name: String) -> Self {
+                self.table_name = table_name;
+                self
+            }
+
+            #[doc = concat!("Creates a [`", stringify!($transaction_handler), "`] which writes checkpoints
+to the same table as this store.")]
+            pub fn transaction_handler(&self) -> $transaction_handler {
+                $transaction_handler {
+                    checkpoint_store: Arc::new(Self {
+                        pool: self.pool.clone(),
+                        name: self.name.clone(),
+                        table_name: self.table_name.clone(),
+                        table_created: OnceCell::new(),
+                    }),
+                    registration_store: None,
+                }
+            }
+
+            async fn create_table(&self) -> Result<(), anyhow::Error> {
+                self.table_created
+                    .get_or_try_init(|| async {
+                        sqlx::query(&format!(
+                            r#"
+                                CREATE TABLE IF NOT EXISTS {} (
+                                    name TEXT PRIMARY KEY,
+                                    state_version {} NOT NULL
+                                )
+                            "#,
+                            self.table_name, $big_integer
+                        ))
+                        .execute(&self.pool)
+                        .await
+                        .map(|_| ())
+                    })
+                    .await?;
+                Ok(())
+            }
+
+            /// Stores the checkpoint using the given connection,
+            /// which may be inside a database transaction.
+            async fn save_with(
+                &self,
+                connection: &mut $connection,
+                state_version: u64,
+            ) -> Result<(), anyhow::Error> {
+                sqlx::query(&format!(
+                    r#"
+                        INSERT INTO {} (name, state_version) VALUES ($1, $2)
+                        ON CONFLICT (name) DO UPDATE SET state_version = excluded.state_version
+                    "#,
+                    self.table_name
+                ))
+                .bind(&self.name)
+                .bind(state_version as i64)
+                .execute(connection)
+                .await?;
+                Ok(())
+            }
+        }
+
+        #[async_trait]
+        impl CheckpointStore for $store {
+            async fn load(&self) -> Result<Option<u64>, anyhow::Error> {
+                self.create_table().await?;
+                let state_version: Option<i64> = sqlx::query_scalar(&format!(
+                    "SELECT state_version FROM {} WHERE name = $1",
+                    self.table_name
+                ))
+                .bind(&self.name)
+                .fetch_optional(&self.pool)
+                .await?;
+                Ok(state_version.map(|state_version| state_version as u64))
+            }
+
+            async fn save(
+                &self,
+                state_version: u64,
+            ) -> Result<(), anyhow::Error> {
+                self.create_table().await?;
+                let mut connection = self.pool.acquire().await?;
+                self.save_with(&mut connection, state_version).await
+            }
+        }
+
+        #[doc = concat!("A [`TransactionHandler`] which processes the events of each transaction
+inside a single database transaction, and writes the checkpoint
+in that same database transaction before committing it.
+
+This makes the writes of the event handlers and the progress of the processor
+atomic: after a crash, a transaction is either fully applied and checkpointed,
+or not applied at all. Event handlers receive the database transaction
+as their transaction context ([`", stringify!($transaction_context), "`]) and should do
+all their writes through it.
+
+Create one using [`", stringify!($store), "::transaction_handler`], and also pass
+the store to the processor, so that it resumes from the checkpoint on start.
+
+It also implements [`BatchTransactionHandler`], in which case a whole batch
+of transactions is processed in one database transaction, and the checkpoint
+is set to the last transaction of the batch.
+
+Handlers which are registered while processing are stored by the processor
+after the database transaction is committed, so a crash in between loses them.
+Set a registration store with [`", stringify!($transaction_handler), "::registration_store`]
+to write them in the same database transaction as the checkpoint instead.
+
+Each attempt of an event handler runs inside a savepoint. When a handler
+fails and its event is dead-lettered or skipped, only its own writes are
+rolled back, and the rest of the transaction is still committed.")]
+        #[derive(Clone)]
+        pub struct $transaction_handler {
+            checkpoint_store: Arc<$store>,
+            registration_store: Option<Arc<$registration_store>>,
+        }
+
+        impl $transaction_handler {
+            /// Writes the handlers which are registered while handling
+            /// a transaction to this store, in the same database transaction
+            /// as the checkpoint. The processor then has none left to store
+            /// itself. Pass a store with the same name and table to the processor
+            /// as well, so that it adds the handlers again when it starts.
+            pub fn registration_store(
+                self,
+                registration_store: $registration_store,
+            ) -> Self {
+                Self {
+                    registration_store: Some(Arc::new(registration_store)),
+                    ..self
+                }
+            }
+
+            /// Creates the tables if needed, and begins a database transaction.
+            async fn begin(
+                &self,
+            ) -> Result<$transaction_context, TransactionHandlerError> {
+                self.checkpoint_store
+                    .create_table()
+                    .await
+                    .map_err(TransactionHandlerError::TransactionRetryError)?;
+                if let Some(registration_store) = &self.registration_store {
+                    registration_store.create_table().await.map_err(
+                        TransactionHandlerError::TransactionRetryError,
+                    )?;
+                }
+                self.checkpoint_store.pool.begin().await.map_err(|err| {
+                    TransactionHandlerError::TransactionRetryError(err.into())
+                })
+            }
+
+            /// Takes the handlers which were registered while handling,
+            /// if they are written with the checkpoint.
+            fn take_registrations<STATE: State>(
+                &self,
+                handler_registry: &mut HandlerRegistry<
+                    STATE,
+                    $transaction_context,
+                >,
+            ) -> Vec<Registration> {
+                match self.registration_store {
+                    Some(_) => handler_registry.take_registrations(),
+                    None => Vec::new(),
+                }
+            }
+
+            /// Writes the checkpoint and the registrations, if there is
+            /// a checkpoint to write, and commits the database transaction.
+            async fn commit(
+                &self,
+                mut transaction_context: $transaction_context,
+                checkpoint: Option<(u64, Vec<Registration>)>,
+            ) -> Result<(), TransactionHandlerError> {
+                if let Some((state_version, registrations)) = checkpoint {
+                    self.save_checkpoint(
+                        &mut transaction_context,
+                        state_version,
+                        &registrations,
+                    )
+                    .await
+                    .map_err(TransactionHandlerError::TransactionRetryError)?;
+                }
+                transaction_context.commit().await.map_err(|err| {
+                    TransactionHandlerError::TransactionRetryError(err.into())
+                })
+            }
+
+            async fn save_checkpoint(
+                &self,
+                transaction_context: &mut $transaction_context,
+                state_version: u64,
+                registrations: &[Registration],
+            ) -> Result<(), anyhow::Error> {
+                if let Some(registration_store) = &self.registration_store {
+                    for registration in registrations {
+                        registration_store
+                            .save_with(transaction_context, registration)
+                            .await?;
+                    }
+                }
+                self.checkpoint_store
+                    .save_with(transaction_context, state_version)
+                    .await
+            }
+        }
+
+        #[async_trait]
+        impl<STATE> TransactionHandler<STATE, $transaction_context>
+            for $transaction_handler
+        where
+            STATE: State,
+        {
+            async fn handle(
+                &self,
+                input: TransactionHandlerContext<
+                    '_,
+                    STATE,
+                    $transaction_context,
+                >,
+            ) -> Result<(), TransactionHandlerError> {
+                let mut transaction_context = self.begin().await?;
+
+                // If any of the event handlers fail, the database transaction
+                // is dropped and rolled back. The writes of a handler which
+                // is dead-lettered or skipped are rolled back to its savepoint.
+                input
+                    .event_processor
+                    .process_events_with_savepoints(
+                        input.state,
+                        input.handler_registry,
+                        &mut transaction_context,
+                    )
+                    .await?;
+
+                // The checkpoint must not move backwards for replays,
+                // and is kept by the processor itself in partitioned mode,
+                // which also stores the registrations of the shards itself.
+                let checkpoint = input.store_checkpoint.then(|| {
+                    (
+                        input.transaction.state_version,
+                        self.take_registrations(input.handler_registry),
+                    )
+                });
+                self.commit(transaction_context, checkpoint).await
+            }
+
+            fn stores_checkpoint(&self) -> bool {
+                true
+            }
+        }
+
+        #[async_trait]
+        impl<STATE> BatchTransactionHandler<STATE, $transaction_context>
+            for $transaction_handler
+        where
+            STATE: State,
+        {
+            async fn handle(
+                &self,
+                input: BatchTransactionHandlerContext<
+                    '_,
+                    STATE,
+                    $transaction_context,
+                >,
+            ) -> Result<(), TransactionHandlerError> {
+                let Some(last_transaction) = input.transactions.last() else {
+                    return Ok(());
+                };
+                let mut transaction_context = self.begin().await?;
+
+                input
+                    .event_processor
+                    .process_events_with_savepoints(
+                        input.state,
+                        input.handler_registry,
+                        &mut transaction_context,
+                    )
+                    .await?;
+
+                let registrations =
+                    self.take_registrations(input.handler_registry);
+                self.commit(
+                    transaction_context,
+                    Some((last_transaction.state_version, registrations)),
+                )
+                .await
+            }
+
+            fn stores_checkpoint(&self) -> bool {
+                true
+            }
+        }
+    };
+}
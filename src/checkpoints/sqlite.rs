@@ -0,0 +1,12 @@
+//! A checkpoint store that keeps the last finished state version in a SQLite database.
+
+sql_checkpoint_store! {
+    database: Sqlite,
+    database_name: "SQLite",
+    connection: SqliteConnection,
+    big_integer: "INTEGER",
+    store: SqliteCheckpointStore,
+    transaction_handler: SqliteTransactionHandler,
+    transaction_context: SqliteTransactionContext,
+    registration_store: crate::registrations::sqlite::SqliteRegistrationStore,
+}
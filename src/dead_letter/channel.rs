@@ -0,0 +1,28 @@
+//! A dead-letter sink that sends dead letters to a [`tokio::sync::mpsc::channel`].
+
+use super::{DeadLetter, DeadLetterSink};
+use async_trait::async_trait;
+use tokio::sync::mpsc::{Receiver, Sender};
+
+/// A dead-letter sink that sends dead letters to a channel,
+/// so that they can be handled by another task, or inspected in tests.
+#[derive(Debug, Clone)]
+pub struct ChannelDeadLetterSink {
+    sender: Sender<DeadLetter>,
+}
+
+impl ChannelDeadLetterSink {
+    pub fn new(capacity: u64) -> (Self, Receiver<DeadLetter>) {
+        let (sender, receiver) = tokio::sync::mpsc::channel(capacity as usize);
+        (Self { sender }, receiver)
+    }
+}
+
+#[async_trait]
+impl DeadLetterSink for ChannelDeadLetterSink {
+    async fn send(&self, dead_letter: DeadLetter) -> Result<(), anyhow::Error> {
+        self.sender.send(dead_letter).await.map_err(|_| {
+            anyhow::anyhow!("Dead letter channel receiver was dropped")
+        })
+    }
+}
@@ -0,0 +1,63 @@
+//! A dead-letter sink that appends dead letters to a JSON Lines file.
+
+use super::{DeadLetter, DeadLetterSink};
+use async_trait::async_trait;
+use std::{io::ErrorKind, path::PathBuf};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+/// A dead-letter sink that appends each dead letter as a line of JSON
+/// to a file. The file is created on the first dead letter.
+#[derive(Debug)]
+pub struct FileDeadLetterSink {
+    file_path: PathBuf,
+}
+
+impl FileDeadLetterSink {
+    /// Creates a new FileDeadLetterSink which writes to the given path.
+    pub fn new(file_path: String) -> Self {
+        Self {
+            file_path: PathBuf::from(file_path),
+        }
+    }
+
+    /// Reads all dead letters from the file, in the order
+    /// they were written.
+    pub async fn load(&self) -> Result<Vec<DeadLetter>, anyhow::Error> {
+        let contents = match tokio::fs::read_to_string(&self.file_path).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Ok(Vec::new())
+            }
+            Err(err) => return Err(err.into()),
+        };
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(Into::into))
+            .collect()
+    }
+
+    /// Removes all dead letters, for example after replaying them.
+    pub async fn clear(&self) -> Result<(), anyhow::Error> {
+        match tokio::fs::remove_file(&self.file_path).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl DeadLetterSink for FileDeadLetterSink {
+    async fn send(&self, dead_letter: DeadLetter) -> Result<(), anyhow::Error> {
+        let mut line = serde_json::to_string(&dead_letter)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file_path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
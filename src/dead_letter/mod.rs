@@ -0,0 +1,99 @@
+//! A dead-letter queue for events and transactions which could not be processed.
+//!
+//! By default, a handler which keeps failing either blocks the processor
+//! through endless retries, or stops it with an unrecoverable error.
+//! When a [`DeadLetterSink`] is set on the processor, it runs in dead-letter mode
+//! instead: an event or transaction which fails with an `UnrecoverableError`,
+//! or which runs out of attempts of its [`RetryPolicy`][crate::retry::RetryPolicy],
+//! is sent to the sink as a [`DeadLetter`], and processing continues.
+//!
+//! Dead letters can be replayed later through the same handlers using
+//! [`TransactionProcessor::replay_dead_letters`][crate::processor::TransactionProcessor::replay_dead_letters].
+//!
+//! Like the sources, the implementations provided by the framework
+//! are behind feature flags.
+
+use crate::models::{Event, Transaction};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "channel")]
+pub mod channel;
+#[cfg(feature = "file")]
+pub mod file;
+#[cfg(any(feature = "database", feature = "sqlite"))]
+#[macro_use]
+mod sql;
+#[cfg(feature = "database")]
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// An event or transaction which could not be processed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// The transaction which failed, or which contains the failed event.
+    pub transaction: Transaction,
+    /// Zero-based index of the failed event in the transaction,
+    /// or `None` if the whole transaction failed.
+    pub event_index: Option<u16>,
+    /// The name of the event handler which failed, or `None` if the whole
+    /// transaction failed. Only this handler runs when the event is replayed.
+    #[serde(default)]
+    pub handler: Option<String>,
+    /// The error chain, from the outermost error to the root cause.
+    pub errors: Vec<String>,
+    /// The moment the item was dead-lettered.
+    pub dead_lettered_at: chrono::DateTime<Utc>,
+}
+
+impl DeadLetter {
+    /// Creates a dead letter for a whole transaction.
+    pub fn transaction(
+        transaction: &Transaction,
+        error: &anyhow::Error,
+    ) -> Self {
+        Self {
+            transaction: transaction.clone(),
+            event_index: None,
+            handler: None,
+            errors: error.chain().map(|cause| cause.to_string()).collect(),
+            dead_lettered_at: Utc::now(),
+        }
+    }
+
+    /// Creates a dead letter for a handler of a single event of a transaction.
+    pub fn event(
+        transaction: &Transaction,
+        event_index: u16,
+        handler: &str,
+        error: &anyhow::Error,
+    ) -> Self {
+        Self {
+            event_index: Some(event_index),
+            handler: Some(handler.to_string()),
+            ..Self::transaction(transaction, error)
+        }
+    }
+
+    /// The state version of the transaction.
+    pub fn state_version(&self) -> u64 {
+        self.transaction.state_version
+    }
+
+    /// The failed event, or `None` if the whole transaction failed.
+    pub fn failed_event(&self) -> Option<&Event> {
+        self.event_index
+            .and_then(|index| self.transaction.events.get(index as usize))
+    }
+}
+
+/// A trait that abstracts a destination for events and transactions
+/// which could not be processed.
+#[async_trait]
+pub trait DeadLetterSink: Send + Sync {
+    /// Stores a dead letter. If this fails, the processor stops
+    /// with an unrecoverable error, so that nothing is lost.
+    async fn send(&self, dead_letter: DeadLetter) -> Result<(), anyhow::Error>;
+}
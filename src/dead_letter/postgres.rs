@@ -0,0 +1,9 @@
+//! A dead-letter sink that stores dead letters in a PostgreSQL database.
+
+sql_dead_letter_sink! {
+    database: Postgres,
+    database_name: "PostgreSQL",
+    id_column: "BIGSERIAL PRIMARY KEY",
+    big_integer: "BIGINT",
+    sink: PostgresDeadLetterSink,
+}
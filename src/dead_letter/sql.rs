@@ -0,0 +1,121 @@
+//! The implementation which is shared by the PostgreSQL and SQLite
+//! dead-letter sinks.
+//!
+//! The queries use `$1`-style placeholders, which both databases understand.
+//! Only the names and some column types differ, and these are passed to the macro.
+
+macro_rules! sql_dead_letter_sink {
+    (
+        database: $database:ident,
+        database_name: $database_name:literal,
+        id_column: $id_column:literal,
+        big_integer: $big_integer:literal,
+        sink: $sink:ident,
+    ) => {
+        use super::{DeadLetter, DeadLetterSink};
+        use async_trait::async_trait;
+        use sqlx::{$database, Pool};
+        use tokio::sync::OnceCell;
+
+        #[doc = concat!("A dead-letter sink that stores dead letters in a table of a ", $database_name, " database.
+The table is created on first use if it doesn't exist yet.
+
+Besides the full dead letter as JSON, the table has columns for the state version,
+the event index and the error, which makes it easy to inspect failures with SQL.")]
+        #[derive(Debug)]
+        pub struct $sink {
+            pool: Pool<$database>,
+            table_name: String,
+            table_created: OnceCell<()>,
+        }
+
+        impl $sink {
+            #[doc = concat!("Creates a new ", stringify!($sink), " with default settings
+which uses the given connection pool.")]
+            pub fn new(pool: Pool<$database>) -> Self {
+                Self {
+                    pool,
+                    table_name: "dead_letters".to_string(),
+                    table_created: OnceCell::new(),
+                }
+            }
+
+            /// Sets the name of the table in which dead letters are stored.
+            pub fn table_name(mut self, table_name: String) -> Self {
+                self.table_name = table_name;
+                self
+            }
+
+            async fn create_table(&self) -> Result<(), anyhow::Error> {
+                self.table_created
+                    .get_or_try_init(|| async {
+                        sqlx::query(&format!(
+                            r#"
+                                CREATE TABLE IF NOT EXISTS {} (
+                                    id {},
+                                    state_version {} NOT NULL,
+                                    event_index INTEGER,
+                                    error TEXT NOT NULL,
+                                    dead_letter TEXT NOT NULL
+                                )
+                            "#,
+                            self.table_name, $id_column, $big_integer
+                        ))
+                        .execute(&self.pool)
+                        .await
+                        .map(|_| ())
+                    })
+                    .await?;
+                Ok(())
+            }
+
+            /// Reads all dead letters from the table, in the order
+            /// they were written.
+            pub async fn load(&self) -> Result<Vec<DeadLetter>, anyhow::Error> {
+                self.create_table().await?;
+                let rows: Vec<String> = sqlx::query_scalar(&format!(
+                    "SELECT dead_letter FROM {} ORDER BY id",
+                    self.table_name
+                ))
+                .fetch_all(&self.pool)
+                .await?;
+                rows.iter()
+                    .map(|row| serde_json::from_str(row).map_err(Into::into))
+                    .collect()
+            }
+
+            /// Removes all dead letters, for example after replaying them.
+            pub async fn clear(&self) -> Result<(), anyhow::Error> {
+                self.create_table().await?;
+                sqlx::query(&format!("DELETE FROM {}", self.table_name))
+                    .execute(&self.pool)
+                    .await?;
+                Ok(())
+            }
+        }
+
+        #[async_trait]
+        impl DeadLetterSink for $sink {
+            async fn send(
+                &self,
+                dead_letter: DeadLetter,
+            ) -> Result<(), anyhow::Error> {
+                self.create_table().await?;
+                sqlx::query(&format!(
+                    r#"
+                        INSERT INTO {} (state_version, event_index, error, dead_letter)
+                        VALUES ($1, $2, $3, $4)
+                    "#,
+                    self.table_name
+                ))
+                .bind(dead_letter.state_version() as i64)
+                .bind(dead_letter.event_index.map(|index| index as i32))
+                .bind(dead_letter.errors.join(": "))
+                .bind(serde_json::to_string(&dead_letter)?)
+                .execute(&self.pool)
+                .await?;
+                Ok(())
+            }
+        }
+    };
+}
@@ -0,0 +1,9 @@
+//! A dead-letter sink that stores dead letters in a SQLite database.
+
+sql_dead_letter_sink! {
+    database: Sqlite,
+    database_name: "SQLite",
+    id_column: "INTEGER PRIMARY KEY AUTOINCREMENT",
+    big_integer: "INTEGER",
+    sink: SqliteDeadLetterSink,
+}
@@ -1,5 +1,7 @@
 //! Error types for event handlers, transaction handlers, and processors.
 
+use std::fmt::{self, Display};
+
 /// Error type which is returned from an event
 /// handler by the user on failure.
 #[derive(Debug)]
@@ -34,10 +36,15 @@ pub enum TransactionHandlerError {
 }
 
 impl From<EventHandlerError> for TransactionHandlerError {
+    /// Event retries are handled by the [`EventProcessor`][crate::processor::EventProcessor],
+    /// so an `EventRetryError` only gets here when an event handler is called
+    /// some other way. It retries the whole transaction then.
     fn from(e: EventHandlerError) -> Self {
         match e {
-            EventHandlerError::EventRetryError(_) => {
-                panic!("Event retries should be handled at the event level, not the transaction level")
+            EventHandlerError::EventRetryError(e) => {
+                Self::TransactionRetryError(e.context(
+                    "event retry error outside of the event processor",
+                ))
             }
             EventHandlerError::TransactionRetryError(e) => {
                 Self::TransactionRetryError(e)
@@ -49,6 +56,66 @@ impl From<EventHandlerError> for TransactionHandlerError {
     }
 }
 
+/// A panic in an event handler or transaction handler, which the processor
+/// caught and turned into an error, according to its [`PanicAction`].
+/// Get it back from the `anyhow::Error` with `error.downcast_ref::<HandlerPanic>()`.
+#[derive(Debug, Clone)]
+pub struct HandlerPanic {
+    /// The message the handler panicked with.
+    pub message: String,
+    pub state_version: u64,
+    pub intent_hash: String,
+    /// The index of the event in the transaction, and the address of its emitter.
+    /// They are `None` when the transaction handler panicked outside of an
+    /// event handler. A batch is reported with its first transaction.
+    pub event_index: Option<u16>,
+    pub emitter: Option<String>,
+    /// The [`EventHandler::name`][crate::event_handler::EventHandler::name]
+    /// of the event handler, or `None` for a transaction handler.
+    pub handler: Option<String>,
+}
+
+impl Display for HandlerPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.handler {
+            Some(handler) => write!(f, "{} panicked", handler)?,
+            None => write!(f, "transaction handler panicked")?,
+        }
+        write!(
+            f,
+            " on transaction {} ({})",
+            self.state_version, self.intent_hash
+        )?;
+        if let Some(event_index) = self.event_index {
+            write!(f, ", event {}", event_index)?;
+        }
+        if let Some(emitter) = &self.emitter {
+            write!(f, " from {}", emitter)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for HandlerPanic {}
+
+/// What the processor does when a handler panics. The [`HandlerPanic`]
+/// is reported like any other error of the handler: with the retry hooks
+/// of the [`Logger`][crate::logger::Logger] while it is retried, and once
+/// when it is dead-lettered or becomes unrecoverable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PanicAction {
+    /// Turn the panic into an `UnrecoverableError`, which dead-letters
+    /// the event or transaction, or stops the processor without a
+    /// dead-letter sink.
+    #[default]
+    Unrecoverable,
+    /// Turn the panic into an `EventRetryError` for an event handler, or a
+    /// `TransactionRetryError` for a transaction handler, so it is retried
+    /// according to the retry policy. Changes the handler made to the state
+    /// before it panicked are kept.
+    Retry,
+}
+
 /// Error type which is returned from a processor.
 /// When the processor finishes successfully, it returns Ok(()),
 /// otherwise it returns the UnrecoverableError variant here.
@@ -54,18 +54,24 @@ async fn event_handler_name(
 
 */
 
+use anyhow::anyhow;
 use async_trait::async_trait;
 use dyn_clone::DynClone;
 use radix_client::gateway::models::{EntityType, ModuleId};
+use radix_common::data::scrypto::{scrypto_decode, ScryptoDecode};
 use std::{
-    any::{Any, TypeId},
-    collections::HashMap,
+    cmp::Reverse, collections::HashMap, future::Future, marker::PhantomData,
+    pin::Pin, sync::Arc, time::Duration,
 };
 
 use crate::{
     error::EventHandlerError,
+    handle::ProcessorHandle,
+    layer::HandlerLayer,
     models::{Event, EventEmitter, Transaction},
     native_events::NativeEventType,
+    registrations::{HandlerCatalog, Registration},
+    retry::HandlerTimeout,
 };
 
 /// A shorthand trait for a state type that can be used in event handlers.
@@ -76,42 +82,292 @@ pub trait State: Send + Sync + 'static {}
 // Implement the State trait for all types that are Send + Sync + 'static.
 impl<T> State for T where T: Send + Sync + 'static {}
 
-/// A type-erased registry of event handlers. It is not parametrized by the
-/// state and transaction context types, which is a nice property
-/// that allows some other types to be a bit simpler.
-/// It can only contain event handlers of one specific type, which is
-/// implicitly determined by the first handler that is added to the registry.
-#[derive(Default)]
-pub struct HandlerRegistry {
-    handlers: HashMap<(String, String), Box<dyn Any + Send + Sync>>,
-    native_handlers: HashMap<NativeEventType, Box<dyn Any + Send + Sync>>,
-    type_id: Option<TypeId>,
+/// A registry of event handlers, parametrized by the state and transaction
+/// context types of its handlers. Only handlers with that signature can be
+/// added, which is checked at compile time, and the processor uses the same
+/// types, so handlers are called without any runtime type checks.
+/// The transaction context defaults to the unit type, like in [`EventHandlerContext`].
+///
+/// Besides handlers for an exact emitter and event name, handlers can be
+/// registered for broader patterns. When several of them match an event,
+/// the handlers of all of them handle it. These are the patterns,
+/// from the most specific to the least specific:
+///
+/// 1. A handler for the emitter and event name, added with [`add_handler`][Self::add_handler],
+///    or for a native event type. Native handlers scoped to the emitter, added with
+///    [`add_native_entity_handler`][Self::add_native_entity_handler], come first,
+///    then the ones scoped to the resource of a vault, added with
+///    [`add_native_resource_handler`][Self::add_native_resource_handler],
+///    and then the ones for every emitter, added with [`set_native_handler`][Self::set_native_handler].
+///    Only the first of these three which has handlers for the event is used,
+///    so the handlers for every emitter are a fallback.
+/// 2. A handler for every event of the emitter, see [`add_emitter_handler`][Self::add_emitter_handler].
+/// 3. A handler for the event name from any component of a blueprint,
+///    see [`add_blueprint_event_handler`][Self::add_blueprint_event_handler].
+/// 4. A handler for every event of a blueprint, see [`add_blueprint_handler`][Self::add_blueprint_handler].
+/// 5. A handler for every event of a package, see [`add_package_handler`][Self::add_package_handler].
+/// 6. A handler for the event name from any emitter, see [`add_name_handler`][Self::add_name_handler].
+/// 7. The handlers of the predicates that match, in the order they were added,
+///    see [`add_predicate_handler`][Self::add_predicate_handler].
+///
+/// All but the predicates are looked up in a hash map, and
+/// [`handler_exists`][Self::handler_exists] only evaluates predicates
+/// for events without another handler.
+///
+/// Each pattern can have several handlers, so that independent modules can
+/// handle the same event. The handlers of an event run one after the other,
+/// from the highest [`EventHandler::priority`] to the lowest. Handlers with
+/// the same priority run from the most specific pattern to the least specific
+/// one, and in the order they were added within a pattern. Adding a handler
+/// with the same [`EventHandler::name`] as one that is already registered
+/// for the pattern replaces it.
+///
+/// Every handler in the registry can be wrapped in shared logic, like a timeout,
+/// with [`add_layer`][Self::add_layer].
+#[allow(non_camel_case_types)]
+pub struct HandlerRegistry<STATE, TRANSACTION_CONTEXT = ()> {
+    handlers: HashMap<(String, String), Entries<STATE, TRANSACTION_CONTEXT>>,
+    native_handlers:
+        HashMap<NativeEventType, Entries<STATE, TRANSACTION_CONTEXT>>,
+    entity_native_handlers: HashMap<
+        NativeEventType,
+        HashMap<String, Entries<STATE, TRANSACTION_CONTEXT>>,
+    >,
+    resource_native_handlers: HashMap<
+        NativeEventType,
+        HashMap<String, Entries<STATE, TRANSACTION_CONTEXT>>,
+    >,
+    emitter_handlers: HashMap<String, Entries<STATE, TRANSACTION_CONTEXT>>,
+    blueprint_event_handlers:
+        HashMap<(String, String, String), Entries<STATE, TRANSACTION_CONTEXT>>,
+    blueprint_handlers:
+        HashMap<(String, String), Entries<STATE, TRANSACTION_CONTEXT>>,
+    package_handlers: HashMap<String, Entries<STATE, TRANSACTION_CONTEXT>>,
+    name_handlers: HashMap<String, Entries<STATE, TRANSACTION_CONTEXT>>,
+    predicate_handlers:
+        Vec<(EventPredicate, Entry<STATE, TRANSACTION_CONTEXT>)>,
+    catalog: Arc<HandlerCatalog<STATE, TRANSACTION_CONTEXT>>,
+    registrations: Vec<Registration>,
+    layers: Vec<Arc<dyn HandlerLayer<STATE, TRANSACTION_CONTEXT>>>,
+    /// Whether the processor started, after which added handlers
+    /// are marked as added while processing.
+    processing: bool,
+}
+
+#[allow(non_camel_case_types)]
+impl<STATE, TRANSACTION_CONTEXT> Default
+    for HandlerRegistry<STATE, TRANSACTION_CONTEXT>
+{
+    fn default() -> Self {
+        Self {
+            handlers: HashMap::new(),
+            native_handlers: HashMap::new(),
+            entity_native_handlers: HashMap::new(),
+            resource_native_handlers: HashMap::new(),
+            emitter_handlers: HashMap::new(),
+            blueprint_event_handlers: HashMap::new(),
+            blueprint_handlers: HashMap::new(),
+            package_handlers: HashMap::new(),
+            name_handlers: HashMap::new(),
+            predicate_handlers: Vec::new(),
+            catalog: Arc::new(HandlerCatalog::default()),
+            registrations: Vec::new(),
+            layers: Vec::new(),
+            processing: false,
+        }
+    }
+}
+
+/// Decides whether a predicate handler handles an event.
+type EventPredicate = Box<dyn Fn(&Event, &Transaction) -> bool + Send + Sync>;
+
+/// A handler, with what is needed to order it among
+/// the other handlers of its pattern.
+#[allow(non_camel_case_types)]
+struct Entry<STATE, TRANSACTION_CONTEXT> {
+    name: String,
+    priority: i32,
+    handler: Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>,
+    added_while_processing: bool,
 }
 
+/// The handlers of a pattern, from the highest priority to the lowest.
+#[allow(non_camel_case_types)]
+type Entries<STATE, TRANSACTION_CONTEXT> =
+    Vec<Entry<STATE, TRANSACTION_CONTEXT>>;
+
 #[allow(non_camel_case_types)]
-impl HandlerRegistry {
+impl<STATE, TRANSACTION_CONTEXT> Entry<STATE, TRANSACTION_CONTEXT> {
+    fn new(
+        handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
+    ) -> Self {
+        Self {
+            name: handler.name().to_string(),
+            priority: handler.priority(),
+            handler: Box::new(handler),
+            added_while_processing: false,
+        }
+    }
+
+    /// Wraps the handler in a layer, keeping its name and priority.
+    fn layered(
+        self,
+        layer: &dyn HandlerLayer<STATE, TRANSACTION_CONTEXT>,
+    ) -> Self {
+        Self {
+            handler: layer.layer(self.handler),
+            ..self
+        }
+    }
+}
+
+/// Adds a handler to the handlers of a pattern, which are kept sorted
+/// from the highest priority to the lowest. A handler with the same name
+/// is replaced.
+#[allow(non_camel_case_types)]
+fn insert_entry<STATE, TRANSACTION_CONTEXT>(
+    entries: &mut Entries<STATE, TRANSACTION_CONTEXT>,
+    entry: Entry<STATE, TRANSACTION_CONTEXT>,
+) {
+    entries.retain(|existing| existing.name != entry.name);
+    let index =
+        entries.partition_point(|existing| existing.priority >= entry.priority);
+    entries.insert(index, entry);
+}
+
+#[allow(non_camel_case_types)]
+impl<STATE: State, TRANSACTION_CONTEXT: 'static>
+    HandlerRegistry<STATE, TRANSACTION_CONTEXT>
+{
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn handler_exists(&self, event: &Event) -> bool {
-        let native_event_case =
-            |entity_type: EntityType| match NativeEventType::resolve(
-                &event.name,
-                entity_type,
-            ) {
-                Ok(event_type) => {
-                    self.native_handlers.contains_key(&event_type)
-                }
-                Err(_) => false,
-            };
+    /// Creates the entry of a handler, wrapped in the layers of the registry.
+    fn entry(
+        &self,
+        handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
+    ) -> Entry<STATE, TRANSACTION_CONTEXT> {
+        let entry = Entry {
+            added_while_processing: self.processing,
+            ..Entry::new(handler)
+        };
+        self.layers
+            .iter()
+            .fold(entry, |entry, layer| entry.layered(layer.as_ref()))
+    }
+
+    /// Marks the handlers which are added from now on, by handlers or from
+    /// a [`RegistrationStore`][crate::registrations::RegistrationStore],
+    /// as added while processing.
+    /// See [`event_handlers_added_while_processing`][Self::event_handlers_added_while_processing].
+    pub(crate) fn start_processing(&mut self) {
+        self.processing = true;
+    }
+
+    /// Wraps every handler in the registry in a layer, including the handlers
+    /// which are added later. A layer which is added later wraps the ones
+    /// added before, and they all wrap the layers of a single handler,
+    /// which were added with [`EventHandlerExt::with_layer`][crate::layer::EventHandlerExt::with_layer].
+    pub fn add_layer(
+        &mut self,
+        layer: impl HandlerLayer<STATE, TRANSACTION_CONTEXT> + 'static,
+    ) {
+        let layer: Arc<dyn HandlerLayer<STATE, TRANSACTION_CONTEXT>> =
+            Arc::new(layer);
+        let patterns = self
+            .handlers
+            .values_mut()
+            .chain(self.native_handlers.values_mut())
+            .chain(
+                self.entity_native_handlers
+                    .values_mut()
+                    .flat_map(HashMap::values_mut),
+            )
+            .chain(
+                self.resource_native_handlers
+                    .values_mut()
+                    .flat_map(HashMap::values_mut),
+            )
+            .chain(self.emitter_handlers.values_mut())
+            .chain(self.blueprint_event_handlers.values_mut())
+            .chain(self.blueprint_handlers.values_mut())
+            .chain(self.package_handlers.values_mut())
+            .chain(self.name_handlers.values_mut());
+        for entries in patterns {
+            *entries = std::mem::take(entries)
+                .into_iter()
+                .map(|entry| entry.layered(layer.as_ref()))
+                .collect();
+        }
+        self.predicate_handlers = std::mem::take(&mut self.predicate_handlers)
+            .into_iter()
+            .map(|(predicate, entry)| {
+                (predicate, entry.layered(layer.as_ref()))
+            })
+            .collect();
+        self.layers.push(layer);
+    }
+
+    /// Returns whether any handler in the registry handles the event.
+    /// Predicates are only evaluated if no other pattern matches.
+    pub fn handler_exists(
+        &self,
+        event: &Event,
+        transaction: &Transaction,
+    ) -> bool {
+        !self.indexed_handlers(event).is_empty()
+            || self
+                .predicate_handlers
+                .iter()
+                .any(|(predicate, _)| predicate(event, transaction))
+    }
+
+    /// Finds the handlers of every pattern which matches the event,
+    /// in the order they run.
+    fn find_handlers(
+        &self,
+        event: &Event,
+        transaction: &Transaction,
+    ) -> Vec<&Entry<STATE, TRANSACTION_CONTEXT>> {
+        let mut entries = self.indexed_handlers(event);
+        entries.extend(
+            self.predicate_handlers
+                .iter()
+                .filter(|(predicate, _)| predicate(event, transaction))
+                .map(|(_, entry)| entry),
+        );
+        // The sort is stable, so handlers with the same priority keep
+        // the precedence of their patterns, and the order they were added.
+        entries.sort_by_key(|entry| Reverse(entry.priority));
+        entries
+    }
+
+    /// Gets the handlers of the patterns which are looked up in a hash map,
+    /// from the most specific pattern to the least specific one.
+    fn indexed_handlers(
+        &self,
+        event: &Event,
+    ) -> Vec<&Entry<STATE, TRANSACTION_CONTEXT>> {
+        let native_event_case = |entity_type: EntityType| {
+            let event_type =
+                NativeEventType::resolve(&event.name, entity_type).ok()?;
+            self.entity_native_handlers
+                .get(&event_type)
+                .and_then(|handlers| handlers.get(event.emitter.address()))
+                .or_else(|| {
+                    let resource_address = event.emitter.resource_address()?;
+                    self.resource_native_handlers
+                        .get(&event_type)?
+                        .get(resource_address)
+                })
+                .or_else(|| self.native_handlers.get(&event_type))
+        };
         let userspace_event_case = |entity_address: &str| {
-            self.handlers.contains_key(&(
-                entity_address.to_string(),
-                event.name.to_string(),
-            ))
+            self.handlers
+                .get(&(entity_address.to_string(), event.name.to_string()))
         };
-        match &event.emitter {
+        let exact = match &event.emitter {
             EventEmitter::Method {
                 entity_address,
                 entity_type,
@@ -135,117 +391,335 @@ impl HandlerRegistry {
             EventEmitter::Function {
                 package_address, ..
             } => userspace_event_case(package_address),
-        }
+        };
+        let blueprint = event.emitter.blueprint();
+        [
+            exact,
+            self.emitter_handlers.get(event.emitter.address()),
+            blueprint.and_then(|(package_address, blueprint_name)| {
+                self.blueprint_event_handlers.get(&(
+                    package_address.to_string(),
+                    blueprint_name.to_string(),
+                    event.name.to_string(),
+                ))
+            }),
+            blueprint.and_then(|(package_address, blueprint_name)| {
+                self.blueprint_handlers.get(&(
+                    package_address.to_string(),
+                    blueprint_name.to_string(),
+                ))
+            }),
+            blueprint.and_then(|(package_address, _)| {
+                self.package_handlers.get(package_address)
+            }),
+            self.name_handlers.get(&event.name),
+        ]
+        .into_iter()
+        .flatten()
+        .flatten()
+        .collect()
     }
 
     /// Add an event handler to the registry.
-    /// It is only possible to add handlers with the same signature.
-    /// The signature is determined by the first handler that is added to the registry.
-    ///
-    /// # Panics
     ///
-    /// Panics if the added handler has a different signature than the
-    /// handlers already in the registry.
-    pub fn add_handler<STATE: State, TRANSACTION_CONTEXT: 'static>(
+    /// Other handlers for the same emitter and event name are kept,
+    /// unless they have the same [`EventHandler::name`].
+    pub fn add_handler(
         &mut self,
         emitter: &str,
         name: &str,
         handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
     ) {
-        // Get the type ID of the handler
-        let type_id =
-            TypeId::of::<Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>>();
-        match self.type_id {
-            // If there is already a type ID, we check if it matches the handler
-            // we're trying to add.
-            Some(existing_type_id) => {
-                if existing_type_id != type_id {
-                    panic!("HandlerRegistry already contains a handler with a different signature");
-                }
-            }
-            // If there is no type ID yet, we implicitly set it here.
-            None => {
-                self.type_id = Some(type_id);
-            }
-        }
-        // Box the handler and insert it into the registry.
-        let boxed: Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT> + 'static> =
-            Box::new(handler);
-        self.handlers
-            .insert((emitter.to_string(), name.to_string()), Box::new(boxed));
+        let entry = self.entry(handler);
+        insert_entry(
+            self.handlers
+                .entry((emitter.to_string(), name.to_string()))
+                .or_default(),
+            entry,
+        );
+    }
+
+    /// Sets the catalog of handlers which can be added by their ID
+    /// with [`register_handler`][Self::register_handler].
+    pub fn set_catalog(
+        &mut self,
+        catalog: HandlerCatalog<STATE, TRANSACTION_CONTEXT>,
+    ) {
+        self.catalog = Arc::new(catalog);
     }
 
-    /// Get an event handler from the registry.
-    /// The handler is downcast to the correct type.
+    /// Add the handler with the given ID in the [`HandlerCatalog`] for an emitter
+    /// and event name, like [`add_handler`][Self::add_handler] does, and record
+    /// the registration. The processor stores it in its
+    /// [`RegistrationStore`][crate::registrations::RegistrationStore],
+    /// so that the handler is added again after a restart.
     ///
-    /// # Panics
+    /// `state_version` is the state version of the transaction being handled.
+    /// Fails if the catalog has no handler with the ID.
+    pub fn register_handler(
+        &mut self,
+        emitter: &str,
+        event_name: &str,
+        handler_id: &str,
+        state_version: u64,
+    ) -> Result<(), anyhow::Error> {
+        let registration = Registration {
+            emitter: emitter.to_string(),
+            event_name: event_name.to_string(),
+            handler_id: handler_id.to_string(),
+            added_at_state_version: state_version,
+        };
+        self.restore_registration(&registration)?;
+        self.registrations.push(registration);
+        Ok(())
+    }
+
+    /// Add the handler of a stored registration from the [`HandlerCatalog`],
+    /// without recording the registration again.
+    /// Fails if the catalog has no handler with its ID.
+    pub fn restore_registration(
+        &mut self,
+        registration: &Registration,
+    ) -> Result<(), anyhow::Error> {
+        let catalog = self.catalog.clone();
+        catalog.add_to(self, registration)
+    }
+
+    /// Takes the registrations recorded since the last call.
+    pub(crate) fn take_registrations(&mut self) -> Vec<Registration> {
+        std::mem::take(&mut self.registrations)
+    }
+
+    /// Add a handler for every event emitted by `emitter`, whatever its name,
+    /// like all events of a component.
+    pub fn add_emitter_handler(
+        &mut self,
+        emitter: &str,
+        handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
+    ) {
+        let entry = self.entry(handler);
+        insert_entry(
+            self.emitter_handlers
+                .entry(emitter.to_string())
+                .or_default(),
+            entry,
+        );
+    }
+
+    /// Add a handler for events with the given name, emitted by a blueprint,
+    /// by its functions or by any of the components instantiated from it.
+    /// Unlike [`add_handler`][Self::add_handler], this covers components
+    /// without registering a handler for each of them when they are instantiated.
     ///
-    /// This function panics if the type parameters used to call it
-    /// don't match the ones used to add the handler to the registry.
+    /// Events from components are only matched if the transaction stream
+    /// resolves the blueprint of their emitter, like the gateway and
+    /// database streams do when `resolve_blueprints` is enabled.
+    pub fn add_blueprint_event_handler(
+        &mut self,
+        package_address: &str,
+        blueprint_name: &str,
+        name: &str,
+        handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
+    ) {
+        let entry = self.entry(handler);
+        insert_entry(
+            self.blueprint_event_handlers
+                .entry((
+                    package_address.to_string(),
+                    blueprint_name.to_string(),
+                    name.to_string(),
+                ))
+                .or_default(),
+            entry,
+        );
+    }
+
+    /// Add a handler for every event emitted by a blueprint, by its
+    /// functions or by the components instantiated from it.
+    pub fn add_blueprint_handler(
+        &mut self,
+        package_address: &str,
+        blueprint_name: &str,
+        handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
+    ) {
+        let entry = self.entry(handler);
+        insert_entry(
+            self.blueprint_handlers
+                .entry((
+                    package_address.to_string(),
+                    blueprint_name.to_string(),
+                ))
+                .or_default(),
+            entry,
+        );
+    }
+
+    /// Add a handler for every event emitted by any blueprint of a package.
+    pub fn add_package_handler(
+        &mut self,
+        package_address: &str,
+        handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
+    ) {
+        let entry = self.entry(handler);
+        insert_entry(
+            self.package_handlers
+                .entry(package_address.to_string())
+                .or_default(),
+            entry,
+        );
+    }
+
+    /// Add a handler for events with the given name, from any emitter.
+    pub fn add_name_handler(
+        &mut self,
+        name: &str,
+        handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
+    ) {
+        let entry = self.entry(handler);
+        insert_entry(
+            self.name_handlers.entry(name.to_string()).or_default(),
+            entry,
+        );
+    }
+
+    /// Add a handler for the events for which `predicate` returns true.
+    /// The handlers of all matching predicates run, after the handlers of
+    /// the other patterns with the same priority, in the order they were added.
+    /// Unlike the other patterns, every predicate is evaluated for every event.
+    pub fn add_predicate_handler(
+        &mut self,
+        predicate: impl Fn(&Event, &Transaction) -> bool + Send + Sync + 'static,
+        handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
+    ) {
+        let entry = self.entry(handler);
+        self.predicate_handlers.push((Box::new(predicate), entry));
+    }
+
+    /// Get the first event handler for an emitter and event name,
+    /// the one with the highest priority.
     #[allow(clippy::borrowed_box)]
-    pub fn handler<STATE: State, TRANSACTION_CONTEXT: 'static>(
+    pub fn handler(
         &self,
         emitter: &str,
         name: &str,
     ) -> Option<&Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>> {
-        self.validate_type_id::<STATE, TRANSACTION_CONTEXT>();
+        self.handlers
+            .get(&(emitter.to_string(), name.to_string()))
+            .and_then(|entries| entries.first())
+            .map(|entry| &entry.handler)
+    }
 
-        // Get the handler from the registry and downcast it to the correct type.
-        let handler =
-            self.handlers.get(&(emitter.to_string(), name.to_string()));
-        handler.map(|handler| {
-            handler
-                .downcast_ref::<Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>>()
-                .expect("Failed to downcast handler")
-        })
+    /// Get the handlers which handle the event, of every pattern which matches it,
+    /// in the order they run, as described on [`HandlerRegistry`].
+    #[allow(clippy::borrowed_box)]
+    pub fn event_handlers(
+        &self,
+        event: &Event,
+        transaction: &Transaction,
+    ) -> Vec<&Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>> {
+        self.find_handlers(event, transaction)
+            .into_iter()
+            .map(|entry| &entry.handler)
+            .collect()
     }
 
-    pub fn set_native_handler<STATE: State, TRANSACTION_CONTEXT: 'static>(
+    /// Like [`event_handlers`][Self::event_handlers], but only gets the
+    /// handlers which were added after [`start_processing`][Self::start_processing].
+    #[allow(clippy::borrowed_box)]
+    pub(crate) fn event_handlers_added_while_processing(
+        &self,
+        event: &Event,
+        transaction: &Transaction,
+    ) -> Vec<&Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>> {
+        self.find_handlers(event, transaction)
+            .into_iter()
+            .filter(|entry| entry.added_while_processing)
+            .map(|entry| &entry.handler)
+            .collect()
+    }
+
+    /// Sets the handler for a native event type,
+    /// replacing all handlers which were added for it before.
+    /// Use [`add_native_handler`][Self::add_native_handler] to keep them.
+    pub fn set_native_handler(
         &mut self,
         event_type: NativeEventType,
         handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
     ) {
-        let type_id =
-            TypeId::of::<Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>>();
-        match self.type_id {
-            Some(existing_type_id) => {
-                if existing_type_id != type_id {
-                    panic!("HandlerRegistry already contains a handler with a different signature");
-                }
-            }
-            None => {
-                self.type_id = Some(type_id);
-            }
-        }
-        let boxed: Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT> + 'static> =
-            Box::new(handler);
-        self.native_handlers.insert(event_type, Box::new(boxed));
+        let entry = self.entry(handler);
+        self.native_handlers.insert(event_type, vec![entry]);
+    }
+
+    /// Add a handler for a native event type, next to the handlers
+    /// which were already added for it, unless they have the same
+    /// [`EventHandler::name`].
+    pub fn add_native_handler(
+        &mut self,
+        event_type: NativeEventType,
+        handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
+    ) {
+        let entry = self.entry(handler);
+        insert_entry(
+            self.native_handlers.entry(event_type).or_default(),
+            entry,
+        );
+    }
+
+    /// Add a handler for a native event type, which only handles the events
+    /// emitted by one entity, like an account, a pool or a validator.
+    /// For these events, it takes precedence over the handlers which were
+    /// added for every emitter with [`set_native_handler`][Self::set_native_handler].
+    pub fn add_native_entity_handler(
+        &mut self,
+        event_type: NativeEventType,
+        entity_address: &str,
+        handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
+    ) {
+        let entry = self.entry(handler);
+        insert_entry(
+            self.entity_native_handlers
+                .entry(event_type)
+                .or_default()
+                .entry(entity_address.to_string())
+                .or_default(),
+            entry,
+        );
+    }
+
+    /// Add a handler for a native vault event type, like a fungible vault
+    /// deposit, which only handles the events of vaults holding the given
+    /// resource. For these events, it takes precedence over the handlers which
+    /// were added for every emitter with [`set_native_handler`][Self::set_native_handler].
+    ///
+    /// Events are only matched if the transaction stream resolves the resource
+    /// of their vault, like the gateway and database streams do when
+    /// `resolve_vault_resources` is enabled.
+    pub fn add_native_resource_handler(
+        &mut self,
+        event_type: NativeEventType,
+        resource_address: &str,
+        handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
+    ) {
+        let entry = self.entry(handler);
+        insert_entry(
+            self.resource_native_handlers
+                .entry(event_type)
+                .or_default()
+                .entry(resource_address.to_string())
+                .or_default(),
+            entry,
+        );
     }
 
     #[allow(clippy::borrowed_box)]
-    pub fn native_handler<STATE: State, TRANSACTION_CONTEXT: 'static>(
+    pub fn native_handler(
         &self,
         event_type: NativeEventType,
     ) -> Option<&Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>> {
-        self.validate_type_id::<STATE, TRANSACTION_CONTEXT>();
-        let handler = self.native_handlers.get(&event_type);
-        handler.map(|handler| {
-            handler
-                .downcast_ref::<Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>>()
-                .expect("Failed to downcast handler")
-        })
-    }
-
-    fn validate_type_id<STATE: State, TRANSACTION_CONTEXT: 'static>(&self) {
-        // Get the type id of the handler we're trying to get.
-        let type_id =
-            TypeId::of::<Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>>();
-
-        // Check if the type ID matches the ones stored in the registry.
-        // If they don't match, we can't downcast the handler and there must be a bug somewhere.
-        if self.type_id != Some(type_id) {
-            panic!("Trying to get handler with different signature than the ones stored in the registry");
-        }
+        self.native_handlers
+            .get(&event_type)
+            .and_then(|entries| entries.first())
+            .map(|entry| &entry.handler)
     }
 }
 
@@ -260,6 +734,443 @@ pub trait EventHandler<STATE, TRANSACTION_CONTEXT>:
         input: EventHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
         event: &[u8],
     ) -> Result<(), EventHandlerError>;
+
+    /// The name of the handler, used in logs and to tell the handlers
+    /// of the same event apart. Defaults to the name of the type.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// The priority of the handler among the handlers of the same event.
+    /// Handlers with a higher priority run first. Defaults to 0.
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    /// A timeout for each attempt of this handler, which takes precedence
+    /// over the event handler timeout of the processor. Like that one,
+    /// it is reported to [`Logger::handler_timeout`][crate::logger::Logger::handler_timeout].
+    /// Defaults to `None`. Handlers which wrap another one should return
+    /// the timeout of the wrapped handler.
+    fn timeout(&self) -> Option<HandlerTimeout<EventHandlerError>> {
+        None
+    }
+}
+
+/// Wraps a handler to give it another [`EventHandler::priority`],
+/// like one that has to run before the other handlers of an event.
+#[derive(Clone)]
+pub struct Prioritized<H> {
+    priority: i32,
+    handler: H,
+}
+
+impl<H> Prioritized<H> {
+    pub fn new(priority: i32, handler: H) -> Self {
+        Self { priority, handler }
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[async_trait]
+impl<STATE, TRANSACTION_CONTEXT, H> EventHandler<STATE, TRANSACTION_CONTEXT>
+    for Prioritized<H>
+where
+    STATE: Send,
+    TRANSACTION_CONTEXT: Send,
+    H: EventHandler<STATE, TRANSACTION_CONTEXT> + Clone,
+{
+    async fn handle(
+        &self,
+        input: EventHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
+        event: &[u8],
+    ) -> Result<(), EventHandlerError> {
+        self.handler.handle(input, event).await
+    }
+
+    fn name(&self) -> &str {
+        self.handler.name()
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn timeout(&self) -> Option<HandlerTimeout<EventHandlerError>> {
+        self.handler.timeout()
+    }
+}
+
+/// Wraps a handler to give it another [`EventHandler::name`], like
+/// a second instance of a handler type for the same event.
+#[derive(Clone)]
+pub struct Named<H> {
+    name: String,
+    handler: H,
+}
+
+impl<H> Named<H> {
+    pub fn new(name: impl Into<String>, handler: H) -> Self {
+        Self {
+            name: name.into(),
+            handler,
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[async_trait]
+impl<STATE, TRANSACTION_CONTEXT, H> EventHandler<STATE, TRANSACTION_CONTEXT>
+    for Named<H>
+where
+    STATE: Send,
+    TRANSACTION_CONTEXT: Send,
+    H: EventHandler<STATE, TRANSACTION_CONTEXT> + Clone,
+{
+    async fn handle(
+        &self,
+        input: EventHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
+        event: &[u8],
+    ) -> Result<(), EventHandlerError> {
+        self.handler.handle(input, event).await
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn priority(&self) -> i32 {
+        self.handler.priority()
+    }
+
+    fn timeout(&self) -> Option<HandlerTimeout<EventHandlerError>> {
+        self.handler.timeout()
+    }
+}
+
+/// Decodes the raw data of an event into the type a handler takes.
+fn decode_event<EVENT: ScryptoDecode>(
+    event: &[u8],
+) -> Result<EVENT, EventHandlerError> {
+    scrypto_decode(event).map_err(|error| {
+        EventHandlerError::UnrecoverableError(anyhow!(
+            "Failed to decode event: {:?}",
+            error
+        ))
+    })
+}
+
+/// An event handler which takes the decoded event, like the ones generated
+/// by the `#[event_handler]` macro, but which can be implemented for a struct
+/// with fields. This gives a handler its own configuration, like a table name,
+/// a network definition or a client, instead of keeping it in the global state.
+/// Wrap it in [`Typed`] to add it to a [`HandlerRegistry`].
+///
+/// ```ignore
+/// #[derive(Clone)]
+/// struct ArchiveSwaps {
+///     table: String,
+/// }
+///
+/// #[async_trait]
+/// impl TypedEventHandler<State> for ArchiveSwaps {
+///     type Event = SwapEvent;
+///
+///     async fn handle(
+///         &self,
+///         context: EventHandlerContext<'_, State>,
+///         event: SwapEvent,
+///     ) -> Result<(), EventHandlerError> {
+///         context.state.archive(&self.table, event).await
+///     }
+/// }
+///
+/// handler_registry.add_handler(
+///     component_address,
+///     "SwapEvent",
+///     Typed(ArchiveSwaps { table: "swaps".to_string() }),
+/// );
+/// ```
+#[allow(non_camel_case_types)]
+#[async_trait]
+pub trait TypedEventHandler<STATE, TRANSACTION_CONTEXT = ()>:
+    Clone + Send + Sync
+{
+    /// The type of the event, as defined in the smart contract.
+    type Event: ScryptoDecode + Send;
+
+    async fn handle(
+        &self,
+        context: EventHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
+        event: Self::Event,
+    ) -> Result<(), EventHandlerError>;
+}
+
+/// Turns a [`TypedEventHandler`] into an [`EventHandler`], by decoding
+/// the event before handling it. The handler is named after its type,
+/// so two instances of the same type for one event replace each other.
+/// Give them their own name with [`Typed::named`].
+#[derive(Clone)]
+pub struct Typed<H>(pub H);
+
+impl<H> Typed<H> {
+    /// Sets the [`EventHandler::name`] of the handler.
+    pub fn named(self, name: impl Into<String>) -> Named<Self> {
+        Named::new(name, self)
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[async_trait]
+impl<STATE, TRANSACTION_CONTEXT, H> EventHandler<STATE, TRANSACTION_CONTEXT>
+    for Typed<H>
+where
+    STATE: Send,
+    TRANSACTION_CONTEXT: Send,
+    H: TypedEventHandler<STATE, TRANSACTION_CONTEXT>,
+{
+    async fn handle(
+        &self,
+        input: EventHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
+        event: &[u8],
+    ) -> Result<(), EventHandlerError> {
+        let event = decode_event(event)?;
+        self.0.handle(input, event).await
+    }
+
+    fn name(&self) -> &str {
+        std::any::type_name::<H>()
+    }
+}
+
+/// The future returned by the closures of [`typed_handler`]. Closures
+/// can't return an `async` block which borrows the context directly,
+/// so they return it boxed: `Box::pin(async move { ... })`.
+pub type HandlerFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<(), EventHandlerError>> + Send + 'a>>;
+
+/// Creates an event handler from an async closure, which takes the context
+/// and the decoded event. The closure can capture its environment, like
+/// a client or a table name, as long as it can be cloned. The types of its
+/// arguments are usually needed to infer the event, state and transaction
+/// context types:
+///
+/// ```ignore
+/// let table = "swaps".to_string();
+/// handler_registry.add_handler(
+///     component_address,
+///     "SwapEvent",
+///     typed_handler(move |context: EventHandlerContext<State>, event: SwapEvent| {
+///         let table = table.clone();
+///         Box::pin(async move { context.state.archive(&table, event).await })
+///     }),
+/// );
+/// ```
+///
+/// The handler is named after the type of the closure, which is the same
+/// for all closures in a function. Give it a name with [`TypedHandler::named`]
+/// when several closures from one function handle the same event,
+/// so they don't replace each other.
+#[allow(non_camel_case_types)]
+pub fn typed_handler<EVENT, STATE, TRANSACTION_CONTEXT, F>(
+    handler: F,
+) -> TypedHandler<EVENT, F>
+where
+    F: for<'a> Fn(
+            EventHandlerContext<'a, STATE, TRANSACTION_CONTEXT>,
+            EVENT,
+        ) -> HandlerFuture<'a>
+        + Clone
+        + Send
+        + Sync,
+{
+    TypedHandler {
+        name: std::any::type_name::<F>().to_string(),
+        handler,
+        event: PhantomData,
+    }
+}
+
+/// An event handler made from an async closure with [`typed_handler`].
+pub struct TypedHandler<EVENT, F> {
+    name: String,
+    handler: F,
+    event: PhantomData<fn() -> EVENT>,
+}
+
+impl<EVENT, F> TypedHandler<EVENT, F> {
+    /// Sets the [`EventHandler::name`] of the handler.
+    pub fn named(self, name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..self
+        }
+    }
+}
+
+impl<EVENT, F: Clone> Clone for TypedHandler<EVENT, F> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            handler: self.handler.clone(),
+            event: PhantomData,
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[async_trait]
+impl<EVENT, STATE, TRANSACTION_CONTEXT, F>
+    EventHandler<STATE, TRANSACTION_CONTEXT> for TypedHandler<EVENT, F>
+where
+    EVENT: ScryptoDecode + Send,
+    STATE: Send,
+    TRANSACTION_CONTEXT: Send,
+    F: for<'a> Fn(
+            EventHandlerContext<'a, STATE, TRANSACTION_CONTEXT>,
+            EVENT,
+        ) -> HandlerFuture<'a>
+        + Clone
+        + Send
+        + Sync,
+{
+    async fn handle(
+        &self,
+        input: EventHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
+        event: &[u8],
+    ) -> Result<(), EventHandlerError> {
+        let event = decode_event(event)?;
+        (self.handler)(input, event).await
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Creates an event handler from a synchronous closure, for handlers
+/// which don't need to await anything, like ones that only update the state.
+/// It runs on the processor's task, so it shouldn't block for long.
+///
+/// ```ignore
+/// handler_registry.add_handler(
+///     component_address,
+///     "SwapEvent",
+///     sync_handler(|context: EventHandlerContext<State>, event: SwapEvent| {
+///         context.state.volume += event.amount;
+///         Ok(())
+///     }),
+/// );
+/// ```
+///
+/// Like with [`typed_handler`], the handler is named after the type of
+/// the closure, unless it is given a name with [`SyncHandler::named`].
+#[allow(non_camel_case_types)]
+pub fn sync_handler<EVENT, STATE, TRANSACTION_CONTEXT, F>(
+    handler: F,
+) -> SyncHandler<EVENT, F>
+where
+    F: Fn(
+            EventHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
+            EVENT,
+        ) -> Result<(), EventHandlerError>
+        + Clone
+        + Send
+        + Sync,
+{
+    SyncHandler {
+        name: std::any::type_name::<F>().to_string(),
+        handler,
+        event: PhantomData,
+    }
+}
+
+/// An event handler made from a synchronous closure with [`sync_handler`].
+pub struct SyncHandler<EVENT, F> {
+    name: String,
+    handler: F,
+    event: PhantomData<fn() -> EVENT>,
+}
+
+impl<EVENT, F> SyncHandler<EVENT, F> {
+    /// Sets the [`EventHandler::name`] of the handler.
+    pub fn named(self, name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..self
+        }
+    }
+}
+
+impl<EVENT, F: Clone> Clone for SyncHandler<EVENT, F> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            handler: self.handler.clone(),
+            event: PhantomData,
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[async_trait]
+impl<EVENT, STATE, TRANSACTION_CONTEXT, F>
+    EventHandler<STATE, TRANSACTION_CONTEXT> for SyncHandler<EVENT, F>
+where
+    EVENT: ScryptoDecode + Send,
+    STATE: Send,
+    TRANSACTION_CONTEXT: Send,
+    F: Fn(
+            EventHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
+            EVENT,
+        ) -> Result<(), EventHandlerError>
+        + Clone
+        + Send
+        + Sync,
+{
+    async fn handle(
+        &self,
+        input: EventHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
+        event: &[u8],
+    ) -> Result<(), EventHandlerError> {
+        let event = decode_event(event)?;
+        (self.handler)(input, event)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// A boxed handler is a handler too, like the ones returned by a
+/// [`HandlerLayer`], so they can be added to a registry.
+#[allow(non_camel_case_types)]
+#[async_trait]
+impl<STATE, TRANSACTION_CONTEXT> EventHandler<STATE, TRANSACTION_CONTEXT>
+    for Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>
+where
+    STATE: Send,
+    TRANSACTION_CONTEXT: Send,
+{
+    async fn handle(
+        &self,
+        input: EventHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
+        event: &[u8],
+    ) -> Result<(), EventHandlerError> {
+        (**self).handle(input, event).await
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn priority(&self) -> i32 {
+        (**self).priority()
+    }
+
+    fn timeout(&self) -> Option<HandlerTimeout<EventHandlerError>> {
+        (**self).timeout()
+    }
 }
 
 #[allow(non_camel_case_types)]
@@ -287,8 +1198,78 @@ pub struct EventHandlerContext<'a, STATE, TRANSACTION_CONTEXT = ()> {
     pub event: &'a Event,
     /// Zero-based index of the event in the transaction.
     pub event_index: u16,
+    /// The current attempt at handling this event, starting at 1.
+    /// It increases each time the event is retried.
+    pub attempt: u32,
+    /// Time since the first attempt at handling this event started.
+    pub elapsed: Duration,
     /// Context of the current transaction, like a database transaction handle.
     pub transaction_context: &'a mut TRANSACTION_CONTEXT,
     /// Handler registry of event handlers.
-    pub handler_registry: &'a mut HandlerRegistry,
+    pub handler_registry: &'a mut HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+    /// A handle to control the processor, for example to pause it.
+    pub handle: &'a ProcessorHandle,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct Handler;
+
+    #[async_trait]
+    impl EventHandler<(), ()> for Handler {
+        async fn handle(
+            &self,
+            _input: EventHandlerContext<'_, (), ()>,
+            _event: &[u8],
+        ) -> Result<(), EventHandlerError> {
+            Ok(())
+        }
+    }
+
+    fn handler_names(
+        registry: &HandlerRegistry<()>,
+        event: &Event,
+    ) -> Vec<String> {
+        registry
+            .event_handlers(event, &Transaction::default())
+            .iter()
+            .map(|handler| handler.name().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn handlers_of_every_matching_pattern_run_by_priority() {
+        let event = Event {
+            name: "SwapEvent".to_string(),
+            binary_sbor_data: Vec::new(),
+            emitter: EventEmitter::Function {
+                package_address: "package".to_string(),
+                blueprint_name: "Pool".to_string(),
+            },
+        };
+        let mut registry = HandlerRegistry::new();
+        registry.add_name_handler("SwapEvent", Named::new("name", Handler));
+        registry.add_predicate_handler(
+            |event, _| event.name.ends_with("Event"),
+            Named::new("predicate", Handler),
+        );
+        registry.add_handler(
+            "package",
+            "SwapEvent",
+            Named::new("exact", Handler),
+        );
+        registry.add_name_handler(
+            "SwapEvent",
+            Prioritized::new(10, Named::new("first", Handler)),
+        );
+
+        assert!(registry.handler_exists(&event, &Transaction::default()));
+        assert_eq!(
+            handler_names(&registry, &event),
+            ["first", "exact", "name", "predicate"]
+        );
+    }
 }
@@ -0,0 +1,550 @@
+//! An adapter which feeds the transactions of one [`TransactionStream`]
+//! to multiple processors, so that they don't each have to fetch
+//! the same transactions from the source.
+//!
+//! ```ignore
+//! let mut fan_out = FanOut::new(GatewayTransactionStream::new());
+//! let pools = fan_out.subscribe(Backpressure::Block(1000));
+//! let swaps = fan_out.subscribe(Backpressure::Unbounded);
+//! tokio::join!(
+//!     TransactionStreamProcessor::new(pools, pool_registry, pool_state).run(),
+//!     TransactionStreamProcessor::new(swaps, swap_registry, swap_state).run(),
+//! );
+//! ```
+
+use crate::{models::Transaction, stream::TransactionStream};
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::{
+    mpsc::{Receiver, Sender, UnboundedSender},
+    watch,
+};
+
+/// Determines what happens when a subscriber of a [`FanOut`]
+/// processes transactions slower than the source produces them.
+/// No transactions are lost either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Buffer up to the given number of transactions for the subscriber.
+    /// When the buffer is full, the source waits for this subscriber,
+    /// which slows down all other subscribers as well.
+    Block(u64),
+    /// Buffer any number of transactions for the subscriber, so that it never
+    /// slows down the others. Memory usage grows while it falls behind.
+    Unbounded,
+}
+
+/// Shares one [`TransactionStream`] between multiple subscribers,
+/// which are streams themselves and can be passed to separate processors.
+///
+/// Each subscriber keeps its own cursor: when a processor starts its subscriber
+/// from a checkpoint, it only receives transactions from that state version on.
+/// The underlying stream is started once all subscribers have been started,
+/// from the lowest state version a subscriber was started from, and stopped
+/// when all subscribers are gone. A subscriber is gone once it is stopped,
+/// or once the receiver it returned is dropped. Subscribers which were started without
+/// a state version receive everything from there. If none of them had one,
+/// the stream starts where it was configured to.
+///
+/// Every subscriber that is created must be started or dropped,
+/// otherwise the other subscribers never receive any transactions.
+#[derive(Debug)]
+pub struct FanOut<STREAM: TransactionStream + 'static> {
+    shared: Arc<Mutex<Shared<STREAM>>>,
+}
+
+#[derive(Debug)]
+struct Shared<STREAM> {
+    stream: Option<STREAM>,
+    subscriber_count: usize,
+    subscriptions: Vec<Subscription>,
+}
+
+impl<STREAM> Shared<STREAM> {
+    /// Takes the stream and the subscriptions once every subscriber
+    /// which wasn't dropped is started.
+    fn take_when_ready(&mut self) -> Option<(STREAM, Vec<Subscription>)> {
+        if self.subscriptions.is_empty()
+            || self.subscriptions.len() < self.subscriber_count
+        {
+            return None;
+        }
+        let stream = self.stream.take()?;
+        Some((stream, std::mem::take(&mut self.subscriptions)))
+    }
+}
+
+/// A started subscriber, waiting for transactions.
+#[derive(Debug)]
+struct Subscription {
+    from_state_version: Option<u64>,
+    sender: SubscriptionSender,
+    stopped: watch::Receiver<bool>,
+}
+
+impl Subscription {
+    /// Sends a transaction, returning `false` if the subscriber is gone.
+    /// A subscriber which is stopped while the source waits for
+    /// space in its buffer doesn't hold up the others.
+    async fn send(&mut self, transaction: Transaction) -> bool {
+        tokio::select! {
+            sent = self.sender.send(transaction) => sent,
+            () = stopped(&mut self.stopped) => false,
+        }
+    }
+
+    /// Completes once the subscriber is gone.
+    async fn closed(&mut self) {
+        tokio::select! {
+            () = self.sender.closed() => {},
+            () = stopped(&mut self.stopped) => {},
+        }
+    }
+}
+
+/// Completes once the subscriber is stopped. A subscriber which is dropped
+/// without being stopped is only gone once its receiver is dropped too.
+async fn stopped(stopped: &mut watch::Receiver<bool>) {
+    if stopped.wait_for(|stopped| *stopped).await.is_err() {
+        std::future::pending().await
+    }
+}
+
+#[derive(Debug)]
+enum SubscriptionSender {
+    Bounded(Sender<Transaction>),
+    Unbounded(UnboundedSender<Transaction>),
+}
+
+impl SubscriptionSender {
+    /// Sends a transaction, returning `false` if the subscriber is gone.
+    async fn send(&self, transaction: Transaction) -> bool {
+        match self {
+            Self::Bounded(sender) => sender.send(transaction).await.is_ok(),
+            Self::Unbounded(sender) => sender.send(transaction).is_ok(),
+        }
+    }
+
+    /// Completes once the receiving end is dropped.
+    async fn closed(&self) {
+        match self {
+            Self::Bounded(sender) => sender.closed().await,
+            Self::Unbounded(sender) => sender.closed().await,
+        }
+    }
+}
+
+impl<STREAM: TransactionStream + 'static> FanOut<STREAM> {
+    pub fn new(stream: STREAM) -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(Shared {
+                stream: Some(stream),
+                subscriber_count: 0,
+                subscriptions: Vec::new(),
+            })),
+        }
+    }
+
+    /// Creates a new subscriber with the given backpressure policy.
+    /// All subscribers should be created before any of them is started.
+    pub fn subscribe(
+        &mut self,
+        backpressure: Backpressure,
+    ) -> FanOutSubscriber<STREAM> {
+        self.shared
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .subscriber_count += 1;
+        FanOutSubscriber {
+            shared: self.shared.clone(),
+            backpressure,
+            started: false,
+            stopped: watch::channel(false).0,
+        }
+    }
+}
+
+/// A subscriber of a [`FanOut`], which can be used
+/// as the [`TransactionStream`] of a processor.
+///
+/// Dropping a subscriber which wasn't started removes it from the fan out,
+/// so that the others don't wait for it.
+#[derive(Debug)]
+pub struct FanOutSubscriber<STREAM: TransactionStream + 'static> {
+    shared: Arc<Mutex<Shared<STREAM>>>,
+    backpressure: Backpressure,
+    started: bool,
+    stopped: watch::Sender<bool>,
+}
+
+impl<STREAM: TransactionStream + 'static> FanOutSubscriber<STREAM> {
+    async fn subscribe(
+        &mut self,
+        from_state_version: Option<u64>,
+    ) -> Result<Receiver<Transaction>, anyhow::Error> {
+        // The other subscribers have moved on, so there is no going back.
+        if self.started {
+            anyhow::bail!("FanOut subscribers can't be restarted");
+        }
+        self.started = true;
+        let (sender, receiver) = match self.backpressure {
+            Backpressure::Block(capacity) => {
+                let (sender, receiver) =
+                    tokio::sync::mpsc::channel(capacity.max(1) as usize);
+                (SubscriptionSender::Bounded(sender), receiver)
+            }
+            Backpressure::Unbounded => {
+                // Buffer in an unbounded channel, and forward from there
+                // to the channel the processor receives from.
+                let (unbounded_sender, mut unbounded_receiver) =
+                    tokio::sync::mpsc::unbounded_channel();
+                let (sender, receiver) = tokio::sync::mpsc::channel(1);
+                // It stops as soon as the processor's receiver is dropped,
+                // which closes the unbounded channel as well.
+                tokio::spawn(async move {
+                    loop {
+                        let transaction = tokio::select! {
+                            transaction = unbounded_receiver.recv() => transaction,
+                            () = sender.closed() => break,
+                        };
+                        let Some(transaction) = transaction else {
+                            break;
+                        };
+                        if sender.send(transaction).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+                (SubscriptionSender::Unbounded(unbounded_sender), receiver)
+            }
+        };
+
+        let (stream, subscriptions) = {
+            let mut shared =
+                self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+            if shared.stream.is_none() {
+                anyhow::bail!("FanOut was already started");
+            }
+            shared.subscriptions.push(Subscription {
+                from_state_version,
+                sender,
+                stopped: self.stopped.subscribe(),
+            });
+            match shared.take_when_ready() {
+                Some(ready) => ready,
+                None => return Ok(receiver),
+            }
+        };
+        start_source(stream, subscriptions).await?;
+        Ok(receiver)
+    }
+}
+
+impl<STREAM: TransactionStream + 'static> Drop for FanOutSubscriber<STREAM> {
+    fn drop(&mut self) {
+        if self.started {
+            return;
+        }
+        let ready = {
+            let mut shared =
+                self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+            shared.subscriber_count -= 1;
+            shared.take_when_ready()
+        };
+        // The other subscribers may all be waiting for this one.
+        let Some((stream, subscriptions)) = ready else {
+            return;
+        };
+        // Without a runtime, the subscriptions are dropped,
+        // which closes the channels of the other subscribers.
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                if let Err(err) = start_source(stream, subscriptions).await {
+                    log::warn!("Error starting the FanOut stream: {:?}", err);
+                }
+            });
+        }
+    }
+}
+
+/// Starts the underlying stream once all subscribers are started,
+/// and spawns a task which sends its transactions to them.
+async fn start_source<STREAM: TransactionStream + 'static>(
+    mut stream: STREAM,
+    subscriptions: Vec<Subscription>,
+) -> Result<(), anyhow::Error> {
+    // Start from the lowest state version a subscriber was started from.
+    // If none was, the stream starts where it was configured to.
+    let from_state_version = subscriptions
+        .iter()
+        .filter_map(|subscription| subscription.from_state_version)
+        .min();
+    // If this fails, the subscriptions are dropped,
+    // which closes the channels of the other subscribers.
+    let mut source = match from_state_version {
+        Some(state_version) => stream.start_from(state_version).await?,
+        None => stream.start().await?,
+    };
+    tokio::spawn(async move {
+        let mut subscriptions = subscriptions;
+        loop {
+            // Stop waiting for the source once all subscribers are gone.
+            let transaction = tokio::select! {
+                transaction = source.recv() => transaction,
+                () = all_closed(&mut subscriptions) => break,
+            };
+            let Some(transaction) = transaction else {
+                break;
+            };
+            let mut open = Vec::with_capacity(subscriptions.len());
+            for mut subscription in subscriptions {
+                let wanted = subscription
+                    .from_state_version
+                    .map_or(true, |from| transaction.state_version >= from);
+                if !wanted || subscription.send(transaction.clone()).await {
+                    open.push(subscription);
+                }
+            }
+            subscriptions = open;
+            if subscriptions.is_empty() {
+                break;
+            }
+        }
+        stream.stop().await;
+    });
+    Ok(())
+}
+
+/// Completes once all subscribers are gone.
+async fn all_closed(subscriptions: &mut [Subscription]) {
+    for subscription in subscriptions {
+        subscription.closed().await;
+    }
+}
+
+#[async_trait]
+impl<STREAM: TransactionStream + 'static> TransactionStream
+    for FanOutSubscriber<STREAM>
+{
+    async fn start(&mut self) -> Result<Receiver<Transaction>, anyhow::Error> {
+        self.subscribe(None).await
+    }
+
+    async fn start_from(
+        &mut self,
+        state_version: u64,
+    ) -> Result<Receiver<Transaction>, anyhow::Error> {
+        self.subscribe(Some(state_version)).await
+    }
+
+    // The fan out stops sending to this subscriber, and stops
+    // the underlying stream once all subscribers are gone.
+    async fn stop(&mut self) {
+        self.stopped.send_replace(true);
+    }
+
+    fn can_restart(&self) -> bool {
+        !self.started
+    }
+}
+
+#[cfg(all(test, feature = "channel"))]
+mod tests {
+    use super::*;
+    use crate::sources::channel::ChannelTransactionStream;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn transaction(state_version: u64) -> Transaction {
+        Transaction {
+            intent_hash: format!("txid_{}", state_version),
+            state_version,
+            confirmed_at: None,
+            events: Vec::new(),
+        }
+    }
+
+    async fn send(
+        sender: &Sender<Transaction>,
+        state_versions: impl IntoIterator<Item = u64>,
+    ) {
+        for state_version in state_versions {
+            sender.send(transaction(state_version)).await.unwrap();
+        }
+    }
+
+    /// Receives transactions until none arrive for a while.
+    async fn received(receiver: &mut Receiver<Transaction>) -> Vec<u64> {
+        let mut state_versions = Vec::new();
+        while let Ok(Some(transaction)) =
+            timeout(Duration::from_millis(100), receiver.recv()).await
+        {
+            state_versions.push(transaction.state_version);
+        }
+        state_versions
+    }
+
+    /// Receives the next transaction, failing the test if none arrives.
+    async fn next(receiver: &mut Receiver<Transaction>) -> u64 {
+        timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .expect("no transaction arrived in time")
+            .expect("channel was closed")
+            .state_version
+    }
+
+    /// Records the state version the stream was started from,
+    /// and whether it was stopped.
+    #[derive(Debug)]
+    struct RecordingStream {
+        stream: ChannelTransactionStream,
+        started_from: Arc<Mutex<Option<Option<u64>>>>,
+        stopped: Arc<Mutex<bool>>,
+    }
+
+    #[async_trait]
+    impl TransactionStream for RecordingStream {
+        async fn start(
+            &mut self,
+        ) -> Result<Receiver<Transaction>, anyhow::Error> {
+            *self.started_from.lock().unwrap() = Some(None);
+            self.stream.start().await
+        }
+
+        async fn start_from(
+            &mut self,
+            state_version: u64,
+        ) -> Result<Receiver<Transaction>, anyhow::Error> {
+            *self.started_from.lock().unwrap() = Some(Some(state_version));
+            self.stream.start().await
+        }
+
+        async fn stop(&mut self) {
+            *self.stopped.lock().unwrap() = true;
+            self.stream.stop().await
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribers_only_receive_transactions_from_their_cursor() {
+        let (stream, sender) = ChannelTransactionStream::new(10);
+        let mut fan_out = FanOut::new(stream);
+        let mut from_start = fan_out.subscribe(Backpressure::Unbounded);
+        let mut from_three = fan_out.subscribe(Backpressure::Unbounded);
+        let mut from_start = from_start.start().await.unwrap();
+        let mut from_three = from_three.start_from(3).await.unwrap();
+        send(&sender, 1..=5).await;
+        assert_eq!(received(&mut from_start).await, [1, 2, 3, 4, 5]);
+        assert_eq!(received(&mut from_three).await, [3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn source_starts_from_the_lowest_state_version() {
+        let (stream, _sender) = ChannelTransactionStream::new(10);
+        let started_from = Arc::new(Mutex::new(None));
+        let mut fan_out = FanOut::new(RecordingStream {
+            stream,
+            started_from: started_from.clone(),
+            stopped: Arc::default(),
+        });
+        let mut without_checkpoint = fan_out.subscribe(Backpressure::Unbounded);
+        let mut from_seven = fan_out.subscribe(Backpressure::Unbounded);
+        let mut from_four = fan_out.subscribe(Backpressure::Unbounded);
+        let _receivers = (
+            without_checkpoint.start().await.unwrap(),
+            from_seven.start_from(7).await.unwrap(),
+            from_four.start_from(4).await.unwrap(),
+        );
+        assert_eq!(*started_from.lock().unwrap(), Some(Some(4)));
+    }
+
+    #[tokio::test]
+    async fn source_starts_once_an_unstarted_subscriber_is_dropped() {
+        let (stream, sender) = ChannelTransactionStream::new(10);
+        let mut fan_out = FanOut::new(stream);
+        let mut started = fan_out.subscribe(Backpressure::Unbounded);
+        let dropped = fan_out.subscribe(Backpressure::Unbounded);
+        let mut receiver = started.start().await.unwrap();
+        send(&sender, 1..=2).await;
+        assert!(received(&mut receiver).await.is_empty());
+        drop(dropped);
+        assert_eq!(received(&mut receiver).await, [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn blocking_subscriber_slows_down_the_others() {
+        let (stream, sender) = ChannelTransactionStream::new(10);
+        let mut fan_out = FanOut::new(stream);
+        let mut fast = fan_out.subscribe(Backpressure::Unbounded);
+        let mut slow = fan_out.subscribe(Backpressure::Block(1));
+        let mut fast = fast.start().await.unwrap();
+        let mut slow = slow.start().await.unwrap();
+        send(&sender, 1..=5).await;
+        // Each transaction is sent to the fast subscriber first, so once it
+        // has the second one, the first one fills the buffer of the slow
+        // subscriber, and the source waits until there is space again.
+        assert_eq!(next(&mut fast).await, 1);
+        assert_eq!(next(&mut fast).await, 2);
+        assert_eq!(slow.len(), 1);
+        assert!(fast.try_recv().is_err());
+        for state_version in 1..=5 {
+            assert_eq!(next(&mut slow).await, state_version);
+        }
+        for state_version in 3..=5 {
+            assert_eq!(next(&mut fast).await, state_version);
+        }
+    }
+
+    #[tokio::test]
+    async fn stopped_subscriber_doesnt_slow_down_the_others() {
+        let (stream, sender) = ChannelTransactionStream::new(10);
+        let mut fan_out = FanOut::new(stream);
+        let mut running = fan_out.subscribe(Backpressure::Block(1));
+        let mut stopped = fan_out.subscribe(Backpressure::Block(1));
+        let mut running = running.start().await.unwrap();
+        let _receiver = stopped.start().await.unwrap();
+        // The receiver is kept, so the source only knows from the stop.
+        stopped.stop().await;
+        send(&sender, 1..=3).await;
+        for state_version in 1..=3 {
+            assert_eq!(next(&mut running).await, state_version);
+        }
+    }
+
+    #[tokio::test]
+    async fn source_stops_once_all_subscribers_are_gone() {
+        let (stream, _sender) = ChannelTransactionStream::new(10);
+        let stopped = Arc::new(Mutex::new(false));
+        let mut fan_out = FanOut::new(RecordingStream {
+            stream,
+            started_from: Arc::default(),
+            stopped: stopped.clone(),
+        });
+        let mut dropped = fan_out.subscribe(Backpressure::Unbounded);
+        let mut blocking = fan_out.subscribe(Backpressure::Block(1));
+        drop(dropped.start().await.unwrap());
+        drop(blocking.start().await.unwrap());
+        // No transactions arrive, so the source only notices
+        // that the subscribers are gone while it waits.
+        timeout(Duration::from_secs(5), async {
+            while !*stopped.lock().unwrap() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the source wasn't stopped in time");
+    }
+
+    #[tokio::test]
+    async fn unbounded_subscriber_doesnt_slow_down_the_others() {
+        let (stream, sender) = ChannelTransactionStream::new(10);
+        let mut fan_out = FanOut::new(stream);
+        let mut fast = fan_out.subscribe(Backpressure::Block(1));
+        let mut slow = fan_out.subscribe(Backpressure::Unbounded);
+        let mut fast = fast.start().await.unwrap();
+        let mut slow = slow.start().await.unwrap();
+        send(&sender, 1..=5).await;
+        assert_eq!(received(&mut fast).await, [1, 2, 3, 4, 5]);
+        assert_eq!(received(&mut slow).await, [1, 2, 3, 4, 5]);
+    }
+}
@@ -0,0 +1,202 @@
+//! A handle to control a running [`TransactionStreamProcessor`][crate::processor::TransactionStreamProcessor]
+//! from other tasks, or from inside handlers.
+
+use chrono::{DateTime, Utc};
+use std::{
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+use tokio::sync::watch;
+
+/// The requests of a [`ProcessorHandle`] which the processor
+/// hasn't acted on yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Controls {
+    pub paused: bool,
+    pub stop: bool,
+    pub seek: Option<u64>,
+}
+
+/// A one-off request of a [`ProcessorHandle`], which is taken
+/// by the processor when it acts on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Command {
+    Stop,
+    Seek(u64),
+}
+
+/// A snapshot of what a processor is doing, returned by [`ProcessorHandle::status`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessorStatus {
+    /// Whether the processor is running, which is from when
+    /// `run` is called until it returns.
+    pub running: bool,
+    /// The state version of the last finished transaction, if any.
+    pub last_state_version: Option<u64>,
+    /// When the last finished transaction was confirmed on ledger, if known.
+    pub last_confirmed_at: Option<chrono::DateTime<Utc>>,
+    /// Whether the processor is paused.
+    pub paused: bool,
+    /// Whether the processor is waiting to retry a transaction or event.
+    pub retrying: bool,
+    /// Whether the processor is waiting for new transactions from the stream,
+    /// with none left in the stream's channel. It processed everything
+    /// the stream fetched, so it is caught up with the source.
+    pub waiting: bool,
+    /// The number of transactions waiting in the stream's channel,
+    /// as of the last transaction the processor received.
+    pub queue_depth: usize,
+    /// The number of transactions that fit in the stream's channel.
+    pub queue_capacity: usize,
+}
+
+impl ProcessorStatus {
+    /// Returns how far the processor is behind the ledger, which is the time
+    /// since the last finished transaction was confirmed.
+    pub fn lag(&self) -> Option<Duration> {
+        self.last_confirmed_at.map(|confirmed_at| {
+            (Utc::now() - confirmed_at).to_std().unwrap_or_default()
+        })
+    }
+}
+
+/// A cloneable handle to control a [`TransactionStreamProcessor`][crate::processor::TransactionStreamProcessor]
+/// while it runs. Get one from [`TransactionStreamProcessor::handle`][crate::processor::TransactionStreamProcessor::handle]
+/// or [`PartitionedTransactionStreamProcessor::handle`][crate::partitioned::PartitionedTransactionStreamProcessor::handle]
+/// before calling `run`, or from the `handle` field of a handler context.
+///
+/// Requests are picked up between transactions, so the transaction which
+/// is being processed, including its retries, is always finished first.
+#[derive(Debug, Clone)]
+pub struct ProcessorHandle {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    controls: watch::Sender<Controls>,
+    /// The status, of which `paused` is taken from the controls instead.
+    status: Mutex<ProcessorStatus>,
+}
+
+impl Default for ProcessorHandle {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                controls: watch::Sender::new(Controls::default()),
+                status: Mutex::new(ProcessorStatus::default()),
+            }),
+        }
+    }
+}
+
+impl ProcessorHandle {
+    /// Makes the processor wait before the next transaction,
+    /// until [`ProcessorHandle::resume`] is called.
+    pub fn pause(&self) {
+        self.inner
+            .controls
+            .send_modify(|controls| controls.paused = true);
+    }
+
+    /// Makes a paused processor continue.
+    pub fn resume(&self) {
+        self.inner
+            .controls
+            .send_modify(|controls| controls.paused = false);
+    }
+
+    /// Makes the processor stop after the current transaction, after which `run`
+    /// returns [`StopReason::Stopped`][crate::processor::StopReason::Stopped].
+    /// This also works while it is paused.
+    pub fn stop(&self) {
+        self.inner
+            .controls
+            .send_modify(|controls| controls.stop = true);
+    }
+
+    /// Makes the processor continue from the given state version, which is
+    /// inclusive, after the current transaction. This can be used to rewind and
+    /// process transactions again, or to skip ahead. The processor stops its
+    /// [`TransactionStream`][crate::stream::TransactionStream] and starts it
+    /// again from this state version. If the stream can't be restarted, like a
+    /// `ChannelTransactionStream` or a [`FanOutSubscriber`][crate::fanout::FanOutSubscriber],
+    /// the processor logs a warning and keeps its position instead.
+    ///
+    /// The checkpoint moves to the new position like it does for a transaction
+    /// without handlers, and is updated as transactions from there finish.
+    pub fn seek(&self, state_version: u64) {
+        self.inner
+            .controls
+            .send_modify(|controls| controls.seek = Some(state_version));
+    }
+
+    /// Returns whether the processor is paused.
+    pub fn is_paused(&self) -> bool {
+        self.inner.controls.borrow().paused
+    }
+
+    /// Returns what the processor is doing right now.
+    pub fn status(&self) -> ProcessorStatus {
+        ProcessorStatus {
+            paused: self.is_paused(),
+            ..*self.status_mut()
+        }
+    }
+
+    fn status_mut(&self) -> MutexGuard<'_, ProcessorStatus> {
+        // The status is always valid, even if a panic happened while it was locked.
+        self.inner
+            .status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns a receiver which is notified when the controls change.
+    pub(crate) fn watch(&self) -> watch::Receiver<Controls> {
+        self.inner.controls.subscribe()
+    }
+
+    /// Takes the pending stop or seek request, stop first.
+    pub(crate) fn take_command(&self) -> Option<Command> {
+        let mut command = None;
+        self.inner.controls.send_if_modified(|controls| {
+            if controls.stop {
+                controls.stop = false;
+                command = Some(Command::Stop);
+            } else if let Some(state_version) = controls.seek.take() {
+                command = Some(Command::Seek(state_version));
+            }
+            command.is_some()
+        });
+        command
+    }
+
+    pub(crate) fn set_running(&self, running: bool) {
+        self.status_mut().running = running;
+    }
+
+    pub(crate) fn set_finished(
+        &self,
+        state_version: u64,
+        confirmed_at: Option<DateTime<Utc>>,
+    ) {
+        let mut status = self.status_mut();
+        status.last_state_version = Some(state_version);
+        status.last_confirmed_at = confirmed_at;
+    }
+
+    pub(crate) fn set_retrying(&self, retrying: bool) {
+        self.status_mut().retrying = retrying;
+    }
+
+    pub(crate) fn set_waiting(&self, waiting: bool) {
+        self.status_mut().waiting = waiting;
+    }
+
+    pub(crate) fn set_queue(&self, queue_depth: usize, queue_capacity: usize) {
+        let mut status = self.status_mut();
+        status.queue_depth = queue_depth;
+        status.queue_capacity = queue_capacity;
+    }
+}
@@ -0,0 +1,127 @@
+//! A small HTTP server with health, readiness and status endpoints,
+//! for running a processor as a service with liveness and readiness probes.
+//!
+//! - `GET /health` responds with 200 while the processor is running, and 503 otherwise.
+//! - `GET /ready` responds with 200 once the processor has caught up with the ledger,
+//!   or is waiting for new transactions from an empty stream,
+//!   and 503 while it is behind or not running.
+//! - `GET /status` responds with the [`ProcessorStatus`] as JSON.
+//!
+//! ```ignore
+//! let processor = TransactionStreamProcessor::new(stream, handler_registry, state);
+//! tokio::spawn(
+//!     HealthServer::new(processor.handle())
+//!         .ready_lag(Duration::from_secs(30))
+//!         .serve("0.0.0.0:8080"),
+//! );
+//! ```
+
+use crate::{
+    handle::{ProcessorHandle, ProcessorStatus},
+    http::{self, Response},
+};
+use serde_json::json;
+use std::time::Duration;
+use tokio::net::ToSocketAddrs;
+
+/// Serves the health, readiness and status of the processor
+/// the given [`ProcessorHandle`] belongs to.
+#[derive(Debug, Clone)]
+pub struct HealthServer {
+    handle: ProcessorHandle,
+    ready_lag: Duration,
+}
+
+impl HealthServer {
+    /// Creates a new HealthServer for the processor of the given handle.
+    /// By default, the processor is ready when it is less than 60 seconds
+    /// behind the ledger.
+    pub fn new(handle: ProcessorHandle) -> Self {
+        Self {
+            handle,
+            ready_lag: Duration::from_secs(60),
+        }
+    }
+
+    /// Sets how far the processor may be behind the ledger to be ready,
+    /// measured from when the last finished transaction was confirmed.
+    pub fn ready_lag(self, ready_lag: Duration) -> Self {
+        Self { ready_lag, ..self }
+    }
+
+    /// Returns whether the processor is running and has caught up
+    /// to within the ready lag. A processor which is waiting for new
+    /// transactions is caught up too, however long ago the last one
+    /// was confirmed, like on a quiet ledger or when nothing is
+    /// processed yet after starting from a checkpoint.
+    pub fn is_ready(&self, status: &ProcessorStatus) -> bool {
+        status.running
+            && (status.waiting
+                || status.lag().is_some_and(|lag| lag <= self.ready_lag))
+    }
+
+    /// Listens on the given address and serves requests until an error occurs.
+    /// This never returns otherwise, so it is usually spawned on its own task.
+    pub async fn serve(
+        self,
+        address: impl ToSocketAddrs,
+    ) -> Result<(), anyhow::Error> {
+        http::serve(address, move |method, path| self.respond(method, path))
+            .await
+    }
+
+    fn respond(&self, method: &str, path: &str) -> Response {
+        let status = self.handle.status();
+        let ready = self.is_ready(&status);
+        match (method, path) {
+            ("GET", "/health") => json_response(
+                ok_or_unavailable(status.running),
+                json!({ "ok": status.running }),
+            ),
+            ("GET", "/ready") => json_response(
+                ok_or_unavailable(ready),
+                json!({ "ready": ready }),
+            ),
+            ("GET", "/status") => json_response(
+                "200 OK",
+                json!({
+                    "running": status.running,
+                    "ready": ready,
+                    "paused": status.paused,
+                    "retrying": status.retrying,
+                    "waiting": status.waiting,
+                    "last_state_version": status.last_state_version,
+                    "last_confirmed_at": status
+                        .last_confirmed_at
+                        .map(|confirmed_at| confirmed_at.to_rfc3339()),
+                    "lag_seconds": status.lag().map(|lag| lag.as_secs_f64()),
+                    "queue_depth": status.queue_depth,
+                    "queue_capacity": status.queue_capacity,
+                }),
+            ),
+            ("GET", _) => {
+                json_response("404 Not Found", json!({ "error": "not found" }))
+            }
+            _ => json_response(
+                "405 Method Not Allowed",
+                json!({ "error": "method not allowed" }),
+            ),
+        }
+    }
+}
+
+fn ok_or_unavailable(ok: bool) -> &'static str {
+    if ok {
+        "200 OK"
+    } else {
+        "503 Service Unavailable"
+    }
+}
+
+fn json_response(status: &'static str, body: serde_json::Value) -> Response {
+    Response {
+        status,
+        content_type: "application/json",
+        body: body.to_string(),
+    }
+}
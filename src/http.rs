@@ -0,0 +1,119 @@
+//! A minimal HTTP server for the small, read-only endpoints of the framework,
+//! so that they don't need a web framework as a dependency.
+
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    time::timeout,
+};
+
+/// How long a client may take to send the request line and headers.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// The longest request line or header line which is accepted, in bytes.
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+/// The most header lines which are accepted.
+const MAX_HEADERS: usize = 100;
+
+/// A response to write back to the client.
+pub(crate) struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+/// Listens on the given address, and answers each request with the response
+/// `respond` returns for its method and path. Each connection handles a single
+/// request. This only returns if listening fails.
+pub(crate) async fn serve<F>(
+    address: impl ToSocketAddrs,
+    respond: F,
+) -> Result<(), anyhow::Error>
+where
+    F: Fn(&str, &str) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(address).await?;
+    let respond = Arc::new(respond);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let respond = respond.clone();
+        tokio::spawn(async move {
+            // A client that goes away only affects its own connection.
+            let _ = handle_connection(stream, respond.as_ref()).await;
+        });
+    }
+}
+
+async fn handle_connection<F>(
+    stream: TcpStream,
+    respond: &F,
+) -> Result<(), anyhow::Error>
+where
+    F: Fn(&str, &str) -> Response,
+{
+    let mut stream = BufReader::new(stream);
+    let response = match timeout(READ_TIMEOUT, read_head(&mut stream)).await {
+        Ok(Ok(Some(request_line))) => {
+            let mut parts = request_line.split_whitespace();
+            respond(
+                parts.next().unwrap_or_default(),
+                parts.next().unwrap_or_default(),
+            )
+        }
+        Ok(Ok(None)) => plain_response(
+            "431 Request Header Fields Too Large",
+            "Request header fields too large",
+        ),
+        Ok(Err(e)) => return Err(e),
+        Err(_) => plain_response("408 Request Timeout", "Request timeout"),
+    };
+
+    let message = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    );
+    let stream = stream.get_mut();
+    stream.write_all(message.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Reads the request line and the headers, up to the blank line which
+/// ends them, so that the whole request is read before responding.
+/// Only the request line matters, like "GET /health HTTP/1.1", which is
+/// returned. Returns `None` if a line or the number of headers is too long.
+async fn read_head(
+    stream: &mut BufReader<TcpStream>,
+) -> Result<Option<String>, anyhow::Error> {
+    let mut request_line = String::new();
+    for index in 0..=MAX_HEADERS {
+        let mut line = String::new();
+        let read = (&mut *stream)
+            .take(MAX_LINE_LENGTH)
+            .read_line(&mut line)
+            .await?;
+        if read == 0 {
+            anyhow::bail!("Connection closed before the end of the request");
+        }
+        if !line.ends_with('\n') {
+            return Ok(None);
+        }
+        if index == 0 {
+            request_line = line;
+        } else if line.trim_end().is_empty() {
+            return Ok(Some(request_line));
+        }
+    }
+    Ok(None)
+}
+
+fn plain_response(status: &'static str, body: &str) -> Response {
+    Response {
+        status,
+        content_type: "text/plain",
+        body: body.to_string(),
+    }
+}
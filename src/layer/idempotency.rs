@@ -0,0 +1,136 @@
+use super::HandlerLayer;
+use crate::{
+    error::EventHandlerError,
+    event_handler::{EventHandler, EventHandlerContext},
+    retry::HandlerTimeout,
+};
+use async_trait::async_trait;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// A layer which skips events that a handler already handled, identified
+/// by the state version of their transaction and their index in it.
+/// This happens when a transaction is retried after a later handler
+/// returned a `TransactionRetryError`, or when a dead letter is replayed.
+///
+/// Only use it for handlers with effects outside the transaction context,
+/// like calls to other services: changes made through the transaction
+/// context are rolled back on a retry, and have to be made again.
+///
+/// Each handler it wraps remembers the last events it handled in memory,
+/// up to the capacity, so the events are handled again after a restart.
+#[derive(Debug, Clone)]
+pub struct IdempotencyLayer {
+    capacity: usize,
+}
+
+impl Default for IdempotencyLayer {
+    fn default() -> Self {
+        Self { capacity: 10_000 }
+    }
+}
+
+impl IdempotencyLayer {
+    /// Creates a layer which remembers the last 10 000 events of each handler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many of the last handled events each handler remembers.
+    pub fn capacity(self, capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+impl<STATE, TRANSACTION_CONTEXT> HandlerLayer<STATE, TRANSACTION_CONTEXT>
+    for IdempotencyLayer
+where
+    STATE: Send + 'static,
+    TRANSACTION_CONTEXT: Send + 'static,
+{
+    fn layer(
+        &self,
+        handler: Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>,
+    ) -> Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>> {
+        Box::new(Idempotent {
+            handled: Arc::new(Mutex::new(HandledEvents {
+                capacity: self.capacity,
+                order: VecDeque::new(),
+                events: HashSet::new(),
+            })),
+            handler,
+        })
+    }
+}
+
+/// The state version and index of the last events a handler handled.
+#[derive(Debug)]
+struct HandledEvents {
+    capacity: usize,
+    order: VecDeque<(u64, u16)>,
+    events: HashSet<(u64, u16)>,
+}
+
+impl HandledEvents {
+    fn insert(&mut self, event: (u64, u16)) {
+        if !self.events.insert(event) {
+            return;
+        }
+        self.order.push_back(event);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.events.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// The processor clones handlers, so the clones share what was handled.
+#[derive(Clone)]
+struct Idempotent<H> {
+    handled: Arc<Mutex<HandledEvents>>,
+    handler: H,
+}
+
+#[allow(non_camel_case_types)]
+#[async_trait]
+impl<STATE, TRANSACTION_CONTEXT, H> EventHandler<STATE, TRANSACTION_CONTEXT>
+    for Idempotent<H>
+where
+    STATE: Send,
+    TRANSACTION_CONTEXT: Send,
+    H: EventHandler<STATE, TRANSACTION_CONTEXT> + Clone,
+{
+    async fn handle(
+        &self,
+        input: EventHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
+        event: &[u8],
+    ) -> Result<(), EventHandlerError> {
+        let id = (input.transaction.state_version, input.event_index);
+        let handled =
+            || self.handled.lock().unwrap_or_else(PoisonError::into_inner);
+        if handled().events.contains(&id) {
+            return Ok(());
+        }
+        self.handler.handle(input, event).await?;
+        handled().insert(id);
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        self.handler.name()
+    }
+
+    fn priority(&self) -> i32 {
+        self.handler.priority()
+    }
+
+    fn timeout(&self) -> Option<HandlerTimeout<EventHandlerError>> {
+        self.handler.timeout()
+    }
+}
@@ -0,0 +1,113 @@
+use super::HandlerLayer;
+use crate::{
+    error::EventHandlerError,
+    event_handler::{EventHandler, EventHandlerContext},
+    retry::HandlerTimeout,
+};
+use async_trait::async_trait;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// What a [`MetricsLayer`] measured for one handler.
+/// Each attempt at handling an event counts as a call.
+#[derive(Debug, Clone, Default)]
+pub struct HandlerMetrics {
+    pub calls: u64,
+    /// Calls which returned an error.
+    pub errors: u64,
+    pub total_duration: Duration,
+    pub max_duration: Duration,
+}
+
+/// A layer which measures how often the handlers it wraps are called,
+/// how often they fail and how long they take, by [`EventHandler::name`].
+/// Clones of the layer share their metrics, so keep one to read them
+/// with [`metrics`][Self::metrics], like in a periodic report.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsLayer {
+    metrics: Arc<Mutex<HashMap<String, HandlerMetrics>>>,
+}
+
+impl MetricsLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the metrics of each handler, by its name.
+    pub fn metrics(&self) -> HashMap<String, HandlerMetrics> {
+        self.metrics
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+#[allow(non_camel_case_types)]
+impl<STATE, TRANSACTION_CONTEXT> HandlerLayer<STATE, TRANSACTION_CONTEXT>
+    for MetricsLayer
+where
+    STATE: Send + 'static,
+    TRANSACTION_CONTEXT: Send + 'static,
+{
+    fn layer(
+        &self,
+        handler: Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>,
+    ) -> Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>> {
+        Box::new(Measured {
+            metrics: self.metrics.clone(),
+            handler,
+        })
+    }
+}
+
+#[derive(Clone)]
+struct Measured<H> {
+    metrics: Arc<Mutex<HashMap<String, HandlerMetrics>>>,
+    handler: H,
+}
+
+#[allow(non_camel_case_types)]
+#[async_trait]
+impl<STATE, TRANSACTION_CONTEXT, H> EventHandler<STATE, TRANSACTION_CONTEXT>
+    for Measured<H>
+where
+    STATE: Send,
+    TRANSACTION_CONTEXT: Send,
+    H: EventHandler<STATE, TRANSACTION_CONTEXT> + Clone,
+{
+    async fn handle(
+        &self,
+        input: EventHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
+        event: &[u8],
+    ) -> Result<(), EventHandlerError> {
+        let started_at = Instant::now();
+        let result = self.handler.handle(input, event).await;
+        let duration = started_at.elapsed();
+        let mut metrics =
+            self.metrics.lock().unwrap_or_else(PoisonError::into_inner);
+        let metrics =
+            metrics.entry(self.handler.name().to_string()).or_default();
+        metrics.calls += 1;
+        if result.is_err() {
+            metrics.errors += 1;
+        }
+        metrics.total_duration += duration;
+        metrics.max_duration = metrics.max_duration.max(duration);
+        result
+    }
+
+    fn name(&self) -> &str {
+        self.handler.name()
+    }
+
+    fn priority(&self) -> i32 {
+        self.handler.priority()
+    }
+
+    fn timeout(&self) -> Option<HandlerTimeout<EventHandlerError>> {
+        self.handler.timeout()
+    }
+}
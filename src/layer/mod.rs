@@ -0,0 +1,66 @@
+//! Layers which wrap [`EventHandler`]s in logic that many handlers share,
+//! like timeouts, skipping events which were already handled, or metrics,
+//! similar to the layers of `tower`.
+//!
+//! A layer wraps a single handler with [`EventHandlerExt::with_layer`],
+//! or every handler of a registry with
+//! [`HandlerRegistry::add_layer`][crate::event_handler::HandlerRegistry::add_layer].
+//! Handlers generated by the `#[event_handler]` macro are wrapped like
+//! any other handler:
+//!
+//! ```ignore
+//! handler_registry.add_handler(
+//!     component_address,
+//!     "SwapEvent",
+//!     handle_swap.with_layer(TimeoutLayer::new(Duration::from_secs(5))),
+//! );
+//! handler_registry.add_layer(metrics_layer.clone());
+//! ```
+//!
+//! A custom layer returns a handler which calls the handler it wraps,
+//! and can change the context it passes on, or the result it gets back.
+//! It should forward [`EventHandler::name`], [`EventHandler::priority`] and
+//! [`EventHandler::timeout`] to the wrapped handler, like the layers in this
+//! module do.
+
+mod idempotency;
+mod metrics;
+mod timeout;
+
+pub use idempotency::IdempotencyLayer;
+pub use metrics::{HandlerMetrics, MetricsLayer};
+pub use timeout::TimeoutLayer;
+
+use crate::event_handler::EventHandler;
+
+/// Wraps an event handler in another handler.
+#[allow(non_camel_case_types)]
+pub trait HandlerLayer<STATE, TRANSACTION_CONTEXT = ()>: Send + Sync {
+    fn layer(
+        &self,
+        handler: Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>,
+    ) -> Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>;
+}
+
+/// Adds [`with_layer`][Self::with_layer] to every event handler.
+#[allow(non_camel_case_types)]
+pub trait EventHandlerExt<STATE, TRANSACTION_CONTEXT>:
+    EventHandler<STATE, TRANSACTION_CONTEXT> + Sized + 'static
+{
+    /// Wraps the handler in a layer. Calling it again wraps
+    /// the result in another layer, around the first one.
+    fn with_layer(
+        self,
+        layer: impl HandlerLayer<STATE, TRANSACTION_CONTEXT>,
+    ) -> Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>> {
+        layer.layer(Box::new(self))
+    }
+}
+
+#[allow(non_camel_case_types)]
+impl<STATE, TRANSACTION_CONTEXT, H> EventHandlerExt<STATE, TRANSACTION_CONTEXT>
+    for H
+where
+    H: EventHandler<STATE, TRANSACTION_CONTEXT> + 'static,
+{
+}
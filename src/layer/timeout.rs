@@ -0,0 +1,105 @@
+use super::HandlerLayer;
+use crate::{
+    error::EventHandlerError,
+    event_handler::{EventHandler, EventHandlerContext},
+    retry::HandlerTimeout,
+};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// A layer which fails a handler that takes longer than a timeout,
+/// with an [`EventHandlerError::EventRetryError`] by default, so the
+/// processor retries it according to its event retry policy.
+///
+/// It sets the [`EventHandler::timeout`] of the handler, which the processor
+/// enforces instead of its own
+/// [`event_handler_timeout`][crate::processor::TransactionStreamProcessor::event_handler_timeout],
+/// and reports to [`Logger::handler_timeout`][crate::logger::Logger::handler_timeout].
+/// When layers with a timeout are nested, the shortest timeout applies.
+///
+/// The handler is cancelled at its next `.await` when it times out, so
+/// any changes it made to the state before that are kept. Use the database
+/// transaction of the transaction context for changes that must be undone.
+/// Layers around it, like a [`MetricsLayer`][super::MetricsLayer],
+/// are cancelled along with it.
+#[derive(Debug, Clone)]
+pub struct TimeoutLayer {
+    timeout: Duration,
+    error: fn(anyhow::Error) -> EventHandlerError,
+}
+
+impl TimeoutLayer {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            error: EventHandlerError::EventRetryError,
+        }
+    }
+
+    /// Sets the error a timeout is turned into,
+    /// like `EventHandlerError::TransactionRetryError`.
+    pub fn error(self, error: fn(anyhow::Error) -> EventHandlerError) -> Self {
+        Self { error, ..self }
+    }
+}
+
+#[allow(non_camel_case_types)]
+impl<STATE, TRANSACTION_CONTEXT> HandlerLayer<STATE, TRANSACTION_CONTEXT>
+    for TimeoutLayer
+where
+    STATE: Send + 'static,
+    TRANSACTION_CONTEXT: Send + 'static,
+{
+    fn layer(
+        &self,
+        handler: Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>,
+    ) -> Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>> {
+        Box::new(Timeout {
+            timeout: self.timeout,
+            error: self.error,
+            handler,
+        })
+    }
+}
+
+#[derive(Clone)]
+struct Timeout<H> {
+    timeout: Duration,
+    error: fn(anyhow::Error) -> EventHandlerError,
+    handler: H,
+}
+
+#[allow(non_camel_case_types)]
+#[async_trait]
+impl<STATE, TRANSACTION_CONTEXT, H> EventHandler<STATE, TRANSACTION_CONTEXT>
+    for Timeout<H>
+where
+    STATE: Send,
+    TRANSACTION_CONTEXT: Send,
+    H: EventHandler<STATE, TRANSACTION_CONTEXT> + Clone,
+{
+    // The processor enforces the timeout, so that it can report it.
+    async fn handle(
+        &self,
+        input: EventHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
+        event: &[u8],
+    ) -> Result<(), EventHandlerError> {
+        self.handler.handle(input, event).await
+    }
+
+    fn name(&self) -> &str {
+        self.handler.name()
+    }
+
+    fn priority(&self) -> i32 {
+        self.handler.priority()
+    }
+
+    fn timeout(&self) -> Option<HandlerTimeout<EventHandlerError>> {
+        let timeout = HandlerTimeout::new(self.timeout, self.error);
+        match self.handler.timeout() {
+            Some(inner) if inner.timeout < timeout.timeout => Some(inner),
+            _ => Some(timeout),
+        }
+    }
+}
@@ -1,12 +1,24 @@
+pub mod checkpoints;
+pub mod dead_letter;
 pub mod encode_string_representation;
 pub mod encodings;
 pub mod error;
 pub mod event_handler;
+pub mod fanout;
+pub mod handle;
+#[cfg(feature = "health")]
+pub mod health;
+#[cfg(any(feature = "health", feature = "prometheus"))]
+mod http;
+pub mod layer;
 pub mod logger;
 pub mod macros;
 pub mod models;
 pub mod native_events;
+pub mod partitioned;
 pub mod processor;
+pub mod registrations;
+pub mod retry;
 pub mod sources;
 pub mod stream;
 pub mod transaction_handler;
@@ -0,0 +1,820 @@
+/*!
+This module contains the [`Logger`] trait and the [`DefaultLogger`] implementation.
+Other implementations are in submodules, behind feature flags.
+
+The [`Logger`] trait is an interface of hooks called by the [`TransactionStreamProcessor`][crate::processor::TransactionStreamProcessor]
+at various points in the processing of transactions. This allows for custom logging
+and metric collection. The default implementation is [`DefaultLogger`].
+*/
+
+#[cfg(feature = "otlp")]
+pub mod otlp;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+pub mod structured;
+#[cfg(feature = "tracing")]
+pub mod tracing;
+
+use crate::{
+    dead_letter::DeadLetter,
+    models::{Event, Transaction},
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use colored::Colorize;
+use log::{error, info};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// The interval at which the metrics are considered for the
+/// `transactions_per_second` and `time_per_transaction_message` metrics.
+const METRIC_CONSIDERATION_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A struct that holds metrics about the transaction stream in the [`DefaultLogger`]
+pub struct StreamMetrics {
+    pub transactions_seen: u64,
+    pub transactions_handled: u64,
+    pub events_seen: u64,
+    pub events_handled: u64,
+    pub time_started: Instant,
+    pub last_seen_state_version: Option<u64>,
+    pub last_seen_timestamp: Option<chrono::DateTime<Utc>>,
+    pub recent_transactions: VecDeque<RecentTransaction>,
+}
+
+pub struct RecentTransaction {
+    time: Instant,
+    duration: Duration,
+    handling: bool,
+}
+
+impl Default for StreamMetrics {
+    fn default() -> Self {
+        Self {
+            transactions_seen: 0,
+            events_seen: 0,
+            transactions_handled: 0,
+            events_handled: 0,
+            time_started: Instant::now(),
+            last_seen_state_version: None,
+            last_seen_timestamp: None,
+            recent_transactions: VecDeque::new(),
+        }
+    }
+}
+
+/// An interface of hooks for the `TransactionStreamProcessor` to call
+/// at various points in the processing of transactions.
+/// This allows for custom logging and metric collection.
+/// The default implementation is `DefaultLogger`.
+///
+/// The hooks take `&self`, so that they can be called without locking
+/// the logger, and the periodic report doesn't compete with them.
+/// Loggers which keep state should use interior mutability. To send
+/// the hooks to several loggers at once, use a [`CompositeLogger`].
+#[async_trait]
+pub trait Logger: Send + Sync {
+    /// Called when:
+    /// - A transaction is received from the stream and is about to be processed, because one
+    /// of its events has a handler associated with it.
+    /// - The transaction is received from the stream and will not be processed because
+    /// none of its events have a handler associated with them.
+    /// - A retry error was returned from a handler and the transaction is being retried.
+    ///
+    /// `handling` indicates whether the transaction has any events that will be handled by an event handler or not.
+    /// `is_retry` indicates whether the transaction is currently being retried or not.
+    async fn receive_transaction(
+        &self,
+        transaction: &Transaction,
+        handling: bool,
+        is_retry: bool,
+    );
+    /// Called when:
+    /// - A transaction has been processed and all of its events have been handled.
+    /// - A transaction has been processed, but nothing was done with it because
+    ///  none of its events had a handler associated with them.
+    /// - A transaction has been retried and is now successfully processed.
+    ///
+    /// `handling` indicates whether the transaction had any events that were handled by an event handler or not.
+    async fn finish_transaction(
+        &self,
+        transaction: &Transaction,
+        handling: bool,
+    );
+    /// Called when:
+    /// - An event is received from the stream and is about to be processed.
+    /// - An event is received from the stream and will not be processed because
+    /// it has no handler associated with it.
+    /// - A retry error was returned from a handler and the event is being retried.
+    ///
+    /// `handling` indicates whether the event will be handled by a handler or not.
+    /// `is_retry` indicates whether the event is currently being retried or not.
+    async fn receive_event(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        handling: bool,
+        is_retry: bool,
+    );
+    /// Called when:
+    /// - An event has been processed.
+    /// - An event has been processed, but nothing was done with it because
+    /// it had no handler associated with it.
+    /// - An event has been retried and is now successfully processed.
+    ///
+    /// `handling` indicates whether the event was handled by a handler or not.
+    async fn finish_event(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        handling: bool,
+    );
+    /// Called before each attempt of a handler of an event. When an event
+    /// has several handlers, they run one after the other
+    /// between `receive_event` and `finish_event`.
+    ///
+    /// `handler` is the [`EventHandler::name`][crate::event_handler::EventHandler::name]
+    /// of the handler, and `attempt` is the attempt, starting at 1.
+    async fn start_handler(
+        &self,
+        _transaction: &Transaction,
+        _event: &Event,
+        _handler: &str,
+        _attempt: u32,
+    ) {
+    }
+    /// Called when one of the handlers of an event is done, either
+    /// because it handled the event, or because it failed for good and
+    /// the event was dead-lettered or skipped, in which case `handled` is false.
+    async fn finish_handler(
+        &self,
+        _transaction: &Transaction,
+        _event: &Event,
+        _handler: &str,
+        _handled: bool,
+    ) {
+    }
+    /// Called when a handler took longer than the timeout set on the processor
+    /// and was cancelled, right before the timeout is turned into its error.
+    /// For an event handler, `event` and `handler` are the event and the
+    /// [`EventHandler::name`][crate::event_handler::EventHandler::name] of the handler.
+    /// For a transaction handler, they are `None`, and a batch is reported
+    /// with its first transaction.
+    async fn handler_timeout(
+        &self,
+        _transaction: &Transaction,
+        _event: Option<&Event>,
+        _handler: Option<&str>,
+        _timeout: Duration,
+    ) {
+    }
+    /// Called when an `EventRetryError` is returned from a handler
+    /// and the event is being retried. It could be called multiple times
+    /// for the same event if it continues to fail.
+    ///
+    /// `timeout` is the delay before the next attempt, and `attempt`
+    /// is the number of the attempt that failed, starting at 1.
+    async fn event_retry_error(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        error: &anyhow::Error,
+        timeout: Duration,
+        attempt: u32,
+    );
+    /// Called when a `TransactionRetryError` is returned from a handler
+    /// and the transaction is being retried.
+    /// It could be called multiple times for the same transaction if it continues to fail.
+    ///
+    /// `timeout` is the delay before the next attempt, and `attempt`
+    /// is the number of the attempt that failed, starting at 1.
+    /// When a batch is retried, this is called with the first transaction of the batch.
+    async fn transaction_retry_error(
+        &self,
+        transaction: &Transaction,
+        error: &anyhow::Error,
+        timeout: Duration,
+        attempt: u32,
+    );
+    /// Called when the last attempt at handling an event failed,
+    /// and the event is skipped because the retry policy says so
+    /// (see [`RetryExhaustedAction::Skip`][crate::retry::RetryExhaustedAction::Skip]).
+    async fn event_skipped(
+        &self,
+        _transaction: &Transaction,
+        _event: &Event,
+        _error: &anyhow::Error,
+    ) {
+    }
+    /// Called when the last attempt at handling a transaction failed,
+    /// and the transaction is skipped because the retry policy says so
+    /// (see [`RetryExhaustedAction::Skip`][crate::retry::RetryExhaustedAction::Skip]).
+    async fn transaction_skipped(
+        &self,
+        _transaction: &Transaction,
+        _error: &anyhow::Error,
+    ) {
+    }
+    /// Called when an event or transaction was sent to the
+    /// [`DeadLetterSink`][crate::dead_letter::DeadLetterSink],
+    /// and processing continues.
+    async fn dead_lettered(&self, _dead_letter: &DeadLetter) {}
+    /// Called when an `UnrecoverableError` is returned from a handler
+    /// and the processor should stop processing.
+    async fn unrecoverable_error(&self, error: &anyhow::Error);
+    /// Called periodically by an independent task. This is useful for
+    /// logging and metric collection. It is possible to set a custom
+    /// interval by implementing the `periodic_report_interval` method.
+    async fn periodic_report(&self);
+    /// Called by the processor to determine the interval at which
+    /// the `periodic_report` method should be called inside of
+    /// an independent task.
+    fn periodic_report_interval(&self) -> Duration;
+    /// Called once when the processor stops, whatever the reason.
+    /// Loggers which buffer output should write it out here.
+    async fn flush(&self) {}
+    /// Called right before the processor calls a transaction or event handler,
+    /// which then runs inside the returned span. Loggers which trace the
+    /// processing return the span of the current attempt, so that spans
+    /// from handler code nest under it. Defaults to no span.
+    #[cfg(feature = "tracing")]
+    fn current_span(&self) -> ::tracing::Span {
+        ::tracing::Span::none()
+    }
+}
+
+/// Locks the state of a logger. A panic while it was locked can't leave
+/// logging state invalid in a harmful way, so a poisoned lock is used anyway.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A [`Logger`] which calls the hooks of several loggers, in the order
+/// they were added, for example console output together with metrics.
+/// Each logger still gets its periodic report at its own interval.
+#[derive(Default)]
+pub struct CompositeLogger {
+    loggers: Vec<Arc<dyn Logger>>,
+    last_reports: Mutex<Vec<Instant>>,
+}
+
+impl CompositeLogger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a logger, which gets the hooks after the loggers added before it.
+    pub fn add_logger(self, logger: impl Logger + 'static) -> Self {
+        self.add_shared_logger(Arc::new(logger))
+    }
+
+    /// Adds a logger which may also be used elsewhere.
+    pub fn add_shared_logger(mut self, logger: Arc<dyn Logger>) -> Self {
+        self.loggers.push(logger);
+        lock(&self.last_reports).push(Instant::now());
+        self
+    }
+}
+
+#[async_trait]
+impl Logger for CompositeLogger {
+    async fn receive_transaction(
+        &self,
+        transaction: &Transaction,
+        handling: bool,
+        is_retry: bool,
+    ) {
+        for logger in &self.loggers {
+            logger
+                .receive_transaction(transaction, handling, is_retry)
+                .await;
+        }
+    }
+
+    async fn finish_transaction(
+        &self,
+        transaction: &Transaction,
+        handling: bool,
+    ) {
+        for logger in &self.loggers {
+            logger.finish_transaction(transaction, handling).await;
+        }
+    }
+
+    async fn receive_event(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        handling: bool,
+        is_retry: bool,
+    ) {
+        for logger in &self.loggers {
+            logger
+                .receive_event(transaction, event, handling, is_retry)
+                .await;
+        }
+    }
+
+    async fn finish_event(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        handling: bool,
+    ) {
+        for logger in &self.loggers {
+            logger.finish_event(transaction, event, handling).await;
+        }
+    }
+
+    async fn start_handler(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        handler: &str,
+        attempt: u32,
+    ) {
+        for logger in &self.loggers {
+            logger
+                .start_handler(transaction, event, handler, attempt)
+                .await;
+        }
+    }
+
+    async fn finish_handler(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        handler: &str,
+        handled: bool,
+    ) {
+        for logger in &self.loggers {
+            logger
+                .finish_handler(transaction, event, handler, handled)
+                .await;
+        }
+    }
+
+    async fn handler_timeout(
+        &self,
+        transaction: &Transaction,
+        event: Option<&Event>,
+        handler: Option<&str>,
+        timeout: Duration,
+    ) {
+        for logger in &self.loggers {
+            logger
+                .handler_timeout(transaction, event, handler, timeout)
+                .await;
+        }
+    }
+
+    async fn event_retry_error(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        error: &anyhow::Error,
+        timeout: Duration,
+        attempt: u32,
+    ) {
+        for logger in &self.loggers {
+            logger
+                .event_retry_error(transaction, event, error, timeout, attempt)
+                .await;
+        }
+    }
+
+    async fn transaction_retry_error(
+        &self,
+        transaction: &Transaction,
+        error: &anyhow::Error,
+        timeout: Duration,
+        attempt: u32,
+    ) {
+        for logger in &self.loggers {
+            logger
+                .transaction_retry_error(transaction, error, timeout, attempt)
+                .await;
+        }
+    }
+
+    async fn event_skipped(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        error: &anyhow::Error,
+    ) {
+        for logger in &self.loggers {
+            logger.event_skipped(transaction, event, error).await;
+        }
+    }
+
+    async fn transaction_skipped(
+        &self,
+        transaction: &Transaction,
+        error: &anyhow::Error,
+    ) {
+        for logger in &self.loggers {
+            logger.transaction_skipped(transaction, error).await;
+        }
+    }
+
+    async fn dead_lettered(&self, dead_letter: &DeadLetter) {
+        for logger in &self.loggers {
+            logger.dead_lettered(dead_letter).await;
+        }
+    }
+
+    async fn unrecoverable_error(&self, error: &anyhow::Error) {
+        for logger in &self.loggers {
+            logger.unrecoverable_error(error).await;
+        }
+    }
+
+    /// Calls the periodic report of each logger whose own interval has passed.
+    async fn periodic_report(&self) {
+        let mut due = Vec::new();
+        {
+            let mut last_reports = lock(&self.last_reports);
+            for (logger, last_report) in
+                self.loggers.iter().zip(last_reports.iter_mut())
+            {
+                if last_report.elapsed() >= logger.periodic_report_interval() {
+                    *last_report = Instant::now();
+                    due.push(logger);
+                }
+            }
+        }
+        for logger in due {
+            logger.periodic_report().await;
+        }
+    }
+
+    /// The shortest interval of all loggers.
+    fn periodic_report_interval(&self) -> Duration {
+        self.loggers
+            .iter()
+            .map(|logger| logger.periodic_report_interval())
+            .min()
+            .unwrap_or(Duration::from_secs(5))
+    }
+
+    async fn flush(&self) {
+        for logger in &self.loggers {
+            logger.flush().await;
+        }
+    }
+
+    /// The span of the first logger which has one.
+    #[cfg(feature = "tracing")]
+    fn current_span(&self) -> ::tracing::Span {
+        self.loggers
+            .iter()
+            .map(|logger| logger.current_span())
+            .find(|span| !span.is_none())
+            .unwrap_or_else(::tracing::Span::none)
+    }
+}
+
+/// Adds a logger next to the current one, if there is one,
+/// so that both of them get the hooks.
+pub(crate) fn combine_loggers(
+    current: Option<Arc<dyn Logger>>,
+    logger: impl Logger + 'static,
+) -> Arc<dyn Logger> {
+    match current {
+        Some(current) => Arc::new(
+            CompositeLogger::new()
+                .add_shared_logger(current)
+                .add_logger(logger),
+        ),
+        None => Arc::new(logger),
+    }
+}
+
+/// The default logger implementation for the `TransactionStreamProcessor`.
+/// This logger collects some metrics about the transaction stream
+/// and logs them periodically. It also logs information about transactions
+/// and events as they are processed.
+pub struct DefaultLogger {
+    metrics: Mutex<StreamMetrics>,
+    transaction_stopwatch: Mutex<Instant>,
+    event_stopwatch: Mutex<Instant>,
+    custom_report_interval: Option<Duration>,
+}
+
+impl Default for DefaultLogger {
+    fn default() -> Self {
+        Self {
+            metrics: Mutex::new(StreamMetrics::default()),
+            transaction_stopwatch: Mutex::new(Instant::now()),
+            event_stopwatch: Mutex::new(Instant::now()),
+            custom_report_interval: None,
+        }
+    }
+}
+
+impl DefaultLogger {
+    pub fn with_custom_report_interval(
+        custom_report_interval: Duration,
+    ) -> Self {
+        Self {
+            custom_report_interval: Some(custom_report_interval),
+            ..Self::default()
+        }
+    }
+}
+
+/// Formats the time a transaction was confirmed, which
+/// transactions from some streams don't have.
+fn format_timestamp(timestamp: Option<chrono::DateTime<Utc>>) -> String {
+    match timestamp {
+        Some(timestamp) => timestamp.format("%a %d-%m-%Y %H:%M").to_string(),
+        None => "unknown time".to_string(),
+    }
+}
+
+#[async_trait]
+impl Logger for DefaultLogger {
+    async fn receive_transaction(
+        &self,
+        transaction: &Transaction,
+        handling: bool,
+        retry: bool,
+    ) {
+        *lock(&self.transaction_stopwatch) = Instant::now();
+        if handling {
+            if !retry {
+                let line =
+                    "--------------------------------------------------------"
+                        .bright_blue();
+                info!("{}", line);
+            }
+            let message = format!(
+                "HANDLING TRANSACTION - {:#?} - {}",
+                transaction.state_version,
+                format_timestamp(transaction.confirmed_at)
+            )
+            .bright_green();
+            let transaction_id = transaction.intent_hash.bright_green();
+            info!("{}", message);
+            info!("{}", transaction_id);
+        }
+    }
+
+    async fn finish_transaction(
+        &self,
+        transaction: &Transaction,
+        handling: bool,
+    ) {
+        let mut metrics = lock(&self.metrics);
+        metrics.transactions_seen += 1;
+        metrics.last_seen_state_version = Some(transaction.state_version);
+        metrics.last_seen_timestamp = transaction.confirmed_at;
+        let time_spent = lock(&self.transaction_stopwatch).elapsed();
+        metrics.recent_transactions.push_back(RecentTransaction {
+            time: Instant::now(),
+            duration: time_spent,
+            handling,
+        });
+        let threshold = Instant::now() - METRIC_CONSIDERATION_INTERVAL;
+        while let Some(&RecentTransaction { time, .. }) =
+            metrics.recent_transactions.front()
+        {
+            if time < threshold {
+                metrics.recent_transactions.pop_front();
+            } else {
+                break;
+            }
+        }
+        if handling {
+            metrics.transactions_handled += 1;
+            let message = format!(
+                "###### END TRANSACTION - HANDLED IN {:?} ######",
+                time_spent
+            )
+            .bright_green();
+            let line =
+                "--------------------------------------------------------"
+                    .bright_blue();
+            info!("{}", message);
+            info!("{}", line);
+        }
+    }
+
+    async fn receive_event(
+        &self,
+        _transaction: &Transaction,
+        event: &Event,
+        handling: bool,
+        _retry: bool,
+    ) {
+        if handling {
+            *lock(&self.event_stopwatch) = Instant::now();
+            let message =
+                format!("HANDLING EVENT: {}", event.name).bright_yellow();
+            info!("{}", message);
+        }
+    }
+
+    async fn finish_event(
+        &self,
+        _transaction: &Transaction,
+        _event: &Event,
+        handling: bool,
+    ) {
+        let mut metrics = lock(&self.metrics);
+        metrics.events_seen += 1;
+        if handling {
+            metrics.events_handled += 1;
+        }
+    }
+
+    async fn handler_timeout(
+        &self,
+        transaction: &Transaction,
+        event: Option<&Event>,
+        handler: Option<&str>,
+        timeout: Duration,
+    ) {
+        let item = match (event, handler) {
+            (Some(event), Some(handler)) => {
+                format!("HANDLER {} FOR EVENT {}", handler, event.name)
+            }
+            _ => format!("TRANSACTION {}", transaction.state_version),
+        };
+        let message = format!(
+            "TIMED OUT: {} AFTER {:.1} SECONDS",
+            item,
+            timeout.as_secs_f32()
+        )
+        .bright_red();
+        error!("{}", message);
+    }
+
+    async fn event_retry_error(
+        &self,
+        _transaction: &Transaction,
+        event: &Event,
+        error: &anyhow::Error,
+        timeout: Duration,
+        attempt: u32,
+    ) {
+        let message =
+            format!("ERROR HANDLING EVENT: {} - {:?}", event.name, error)
+                .bright_red();
+        let retry_message = format!(
+            "ATTEMPT {} FAILED - RETRYING IN {:.1} SECONDS\n",
+            attempt,
+            timeout.as_secs_f32()
+        )
+        .bright_yellow();
+
+        error!("{}", message);
+        info!("{}", retry_message);
+    }
+
+    async fn transaction_retry_error(
+        &self,
+        _transaction: &Transaction,
+        error: &anyhow::Error,
+        timeout: Duration,
+        attempt: u32,
+    ) {
+        let message =
+            format!("FATAL ERROR HANDLING TRANSACTION: {:?}\n", error)
+                .bright_red();
+
+        let retry_message = format!(
+            "ATTEMPT {} FAILED - RETRYING IN {:.1} SECONDS\n",
+            attempt,
+            timeout.as_secs_f32()
+        )
+        .bright_yellow();
+
+        error!("{}", message);
+        info!("{}", retry_message);
+    }
+
+    async fn event_skipped(
+        &self,
+        _transaction: &Transaction,
+        event: &Event,
+        error: &anyhow::Error,
+    ) {
+        let message = format!(
+            "SKIPPING EVENT AFTER LAST ATTEMPT: {} - {:?}\n",
+            event.name, error
+        )
+        .bright_red();
+        error!("{}", message);
+    }
+
+    async fn transaction_skipped(
+        &self,
+        _transaction: &Transaction,
+        error: &anyhow::Error,
+    ) {
+        let message =
+            format!("SKIPPING TRANSACTION AFTER LAST ATTEMPT: {:?}\n", error)
+                .bright_red();
+        error!("{}", message);
+    }
+
+    async fn dead_lettered(&self, dead_letter: &DeadLetter) {
+        let item = match dead_letter.failed_event() {
+            Some(event) => format!("EVENT: {}", event.name),
+            None => "TRANSACTION".to_string(),
+        };
+        let message = format!(
+            "DEAD-LETTERED {} - {} - {}\n",
+            item,
+            dead_letter.state_version(),
+            dead_letter.errors.join(": ")
+        )
+        .bright_red();
+        error!("{}", message);
+    }
+
+    async fn unrecoverable_error(&self, error: &anyhow::Error) {
+        let message = format!("UNRECOVERABLE ERROR: {:?}", error).bright_red();
+        error!("{}", message);
+    }
+
+    async fn periodic_report(&self) {
+        let metrics = lock(&self.metrics);
+        match metrics.last_seen_state_version {
+            Some(state_version) => {
+                let state_message = format!(
+                    "HANDLED UP TO: {} - {}",
+                    state_version,
+                    format_timestamp(metrics.last_seen_timestamp)
+                )
+                .bright_blue();
+
+                let handled_transactions = metrics
+                    .recent_transactions
+                    .iter()
+                    .filter(|RecentTransaction { time, handling, .. }| {
+                        time > &(Instant::now() - METRIC_CONSIDERATION_INTERVAL)
+                            && *handling
+                    });
+
+                let seen_transactions = metrics
+                    .recent_transactions
+                    .iter()
+                    .filter(|RecentTransaction { time, .. }| {
+                        time > &(Instant::now() - METRIC_CONSIDERATION_INTERVAL)
+                    });
+
+                let handled_transaction_amount =
+                    handled_transactions.clone().count();
+
+                let seen_transaction_amount = seen_transactions.clone().count();
+
+                let transactions_handled_per_second = handled_transaction_amount
+                    / METRIC_CONSIDERATION_INTERVAL
+                        .as_secs()
+                        .min(metrics.time_started.elapsed().as_secs().max(1))
+                        as usize;
+                let transactions_seen_per_second = seen_transaction_amount
+                    / METRIC_CONSIDERATION_INTERVAL
+                        .as_secs()
+                        .min(metrics.time_started.elapsed().as_secs().max(1))
+                        as usize;
+                let transactions_per_second_message = format!(
+                    "TRANSACTIONS - SEEN/s: {} - HANDLED/s: {}",
+                    transactions_seen_per_second,
+                    transactions_handled_per_second
+                )
+                .bright_blue();
+                let time_per_transaction = handled_transactions.fold(
+                    Duration::from_secs(0),
+                    |acc, RecentTransaction { duration, .. }| acc + *duration,
+                ) / handled_transaction_amount.max(1)
+                    as u32;
+                let time_per_transaction_message =
+                    format!("AVG TIME HANDLING: {:?}", time_per_transaction)
+                        .bright_blue();
+                info!("{}", state_message);
+                info!("{}", transactions_per_second_message);
+                info!("{}", time_per_transaction_message);
+            }
+            None => {
+                info!("{}", "NO TRANSACTIONS HANDLED YET".bright_blue());
+            }
+        }
+    }
+
+    fn periodic_report_interval(&self) -> Duration {
+        self.custom_report_interval
+            .unwrap_or(Duration::from_secs(5))
+    }
+
+    async fn flush(&self) {
+        self.periodic_report().await;
+        log::logger().flush();
+    }
+}
@@ -0,0 +1,50 @@
+//! Exports the spans of the [`TracingLogger`][crate::logger::tracing::TracingLogger],
+//! and of any other `tracing` instrumentation, to an OpenTelemetry collector over OTLP.
+
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Flushes and shuts down the OTLP exporter when dropped.
+/// Keep it alive for as long as spans should be exported.
+#[derive(Debug)]
+pub struct OtlpGuard {
+    provider: TracerProvider,
+}
+
+impl Drop for OtlpGuard {
+    fn drop(&mut self) {
+        // There is nowhere left to report a failure to at this point.
+        let _ = self.provider.shutdown();
+    }
+}
+
+/// Installs a global `tracing` subscriber which exports spans over OTLP/gRPC
+/// to the given endpoint, like `http://localhost:4317`, with the given service name.
+/// Must be called from within a Tokio runtime, and fails if a global
+/// subscriber was already installed.
+///
+/// To combine the export with other layers, like console output,
+/// build the subscriber yourself with `tracing-opentelemetry` instead.
+pub fn init_otlp(
+    service_name: impl Into<String>,
+    endpoint: impl Into<String>,
+) -> Result<OtlpGuard, anyhow::Error> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            service_name.into(),
+        )]))
+        .build();
+    let tracer = provider.tracer("radix_event_stream");
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()?;
+    Ok(OtlpGuard { provider })
+}
@@ -0,0 +1,333 @@
+//! A logger that exports metrics about the processor to Prometheus.
+
+use super::{lock, Logger};
+use crate::{
+    dead_letter::DeadLetter,
+    http::{self, Response},
+    models::{Event, Transaction},
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, Opts, Registry, TextEncoder,
+};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::net::ToSocketAddrs;
+
+/// A [`Logger`] that collects metrics in a Prometheus [`Registry`],
+/// instead of writing log lines. All metrics are prefixed with
+/// `radix_event_stream_`:
+///
+/// - `transactions_seen_total` and `transactions_handled_total`
+/// - `events_seen_total` and `events_handled_total`
+/// - `retries_total`, `timeouts_total`, `skipped_total` and `dead_letters_total`, by `kind`,
+///   which is `transaction` or `event`
+/// - `unrecoverable_errors_total`
+/// - `handler_duration_seconds`, a histogram by `emitter` and `event`
+/// - `last_state_version`, the last finished transaction
+/// - `ledger_lag_seconds`, the time since the last finished transaction
+///   was confirmed on ledger
+///
+/// Expose the metrics with [`serve_metrics`], or gather them from
+/// [`PrometheusLogger::registry`] in your own endpoint.
+///
+/// The handler latency has a label for every emitter address, so with
+/// handlers for many components, there are many time series.
+pub struct PrometheusLogger {
+    registry: Registry,
+    transactions_seen: IntCounter,
+    transactions_handled: IntCounter,
+    events_seen: IntCounter,
+    events_handled: IntCounter,
+    retries: IntCounterVec,
+    timeouts: IntCounterVec,
+    skipped: IntCounterVec,
+    dead_letters: IntCounterVec,
+    unrecoverable_errors: IntCounter,
+    handler_duration: HistogramVec,
+    last_state_version: IntGauge,
+    ledger_lag: Gauge,
+    last_confirmed_at: Mutex<Option<chrono::DateTime<Utc>>>,
+    event_stopwatch: Mutex<Instant>,
+}
+
+impl PrometheusLogger {
+    /// Creates a new PrometheusLogger with its own registry.
+    pub fn new() -> Result<Self, prometheus::Error> {
+        Self::with_registry(Registry::new())
+    }
+
+    /// Creates a new PrometheusLogger which registers its metrics in the
+    /// given registry, so they can be exposed together with other metrics.
+    pub fn with_registry(
+        registry: Registry,
+    ) -> Result<Self, prometheus::Error> {
+        let kind = &["kind"];
+        let logger = Self {
+            transactions_seen: IntCounter::with_opts(opts(
+                "transactions_seen_total",
+                "Transactions received from the stream",
+            ))?,
+            transactions_handled: IntCounter::with_opts(opts(
+                "transactions_handled_total",
+                "Transactions with at least one handled event",
+            ))?,
+            events_seen: IntCounter::with_opts(opts(
+                "events_seen_total",
+                "Events with a handler which were processed",
+            ))?,
+            events_handled: IntCounter::with_opts(opts(
+                "events_handled_total",
+                "Events which were handled successfully",
+            ))?,
+            retries: IntCounterVec::new(
+                opts("retries_total", "Retries after a retry error"),
+                kind,
+            )?,
+            timeouts: IntCounterVec::new(
+                opts("timeouts_total", "Handlers which took too long"),
+                kind,
+            )?,
+            skipped: IntCounterVec::new(
+                opts("skipped_total", "Items skipped after the last attempt"),
+                kind,
+            )?,
+            dead_letters: IntCounterVec::new(
+                opts(
+                    "dead_letters_total",
+                    "Items sent to the dead-letter sink",
+                ),
+                kind,
+            )?,
+            unrecoverable_errors: IntCounter::with_opts(opts(
+                "unrecoverable_errors_total",
+                "Unrecoverable errors, which stop the processor",
+            ))?,
+            handler_duration: HistogramVec::new(
+                HistogramOpts::from(opts(
+                    "handler_duration_seconds",
+                    "Time spent handling an event, including retries",
+                )),
+                &["emitter", "event"],
+            )?,
+            last_state_version: IntGauge::with_opts(opts(
+                "last_state_version",
+                "State version of the last finished transaction",
+            ))?,
+            ledger_lag: Gauge::with_opts(opts(
+                "ledger_lag_seconds",
+                "Time since the last finished transaction was confirmed",
+            ))?,
+            last_confirmed_at: Mutex::new(None),
+            event_stopwatch: Mutex::new(Instant::now()),
+            registry,
+        };
+        logger.register()?;
+        Ok(logger)
+    }
+
+    fn register(&self) -> Result<(), prometheus::Error> {
+        self.registry
+            .register(Box::new(self.transactions_seen.clone()))?;
+        self.registry
+            .register(Box::new(self.transactions_handled.clone()))?;
+        self.registry.register(Box::new(self.events_seen.clone()))?;
+        self.registry
+            .register(Box::new(self.events_handled.clone()))?;
+        self.registry.register(Box::new(self.retries.clone()))?;
+        self.registry.register(Box::new(self.timeouts.clone()))?;
+        self.registry.register(Box::new(self.skipped.clone()))?;
+        self.registry
+            .register(Box::new(self.dead_letters.clone()))?;
+        self.registry
+            .register(Box::new(self.unrecoverable_errors.clone()))?;
+        self.registry
+            .register(Box::new(self.handler_duration.clone()))?;
+        self.registry
+            .register(Box::new(self.last_state_version.clone()))?;
+        self.registry.register(Box::new(self.ledger_lag.clone()))?;
+        Ok(())
+    }
+
+    /// Returns the registry which holds the metrics.
+    pub fn registry(&self) -> Registry {
+        self.registry.clone()
+    }
+
+    fn update_ledger_lag(&self) {
+        if let Some(confirmed_at) = *lock(&self.last_confirmed_at) {
+            let lag = (Utc::now() - confirmed_at).to_std().unwrap_or_default();
+            self.ledger_lag.set(lag.as_secs_f64());
+        }
+    }
+}
+
+fn opts(name: &str, help: &str) -> Opts {
+    Opts::new(name, help).namespace("radix_event_stream")
+}
+
+/// Listens on the given address and serves the metrics in the registry
+/// on `GET /metrics`, in the Prometheus text format. This never returns
+/// unless listening fails, so it is usually spawned on its own task.
+pub async fn serve_metrics(
+    registry: Registry,
+    address: impl ToSocketAddrs,
+) -> Result<(), anyhow::Error> {
+    http::serve(address, move |method, path| match (method, path) {
+        ("GET", "/metrics") => {
+            let encoder = TextEncoder::new();
+            let mut body = Vec::new();
+            match encoder.encode(&registry.gather(), &mut body) {
+                Ok(()) => Response {
+                    status: "200 OK",
+                    content_type: prometheus::TEXT_FORMAT,
+                    body: String::from_utf8_lossy(&body).into_owned(),
+                },
+                Err(err) => Response {
+                    status: "500 Internal Server Error",
+                    content_type: "text/plain",
+                    body: err.to_string(),
+                },
+            }
+        }
+        _ => Response {
+            status: "404 Not Found",
+            content_type: "text/plain",
+            body: "not found".to_string(),
+        },
+    })
+    .await
+}
+
+#[async_trait]
+impl Logger for PrometheusLogger {
+    async fn receive_transaction(
+        &self,
+        _transaction: &Transaction,
+        _handling: bool,
+        _is_retry: bool,
+    ) {
+    }
+
+    async fn finish_transaction(
+        &self,
+        transaction: &Transaction,
+        handling: bool,
+    ) {
+        self.transactions_seen.inc();
+        if handling {
+            self.transactions_handled.inc();
+        }
+        self.last_state_version
+            .set(transaction.state_version as i64);
+        if transaction.confirmed_at.is_some() {
+            *lock(&self.last_confirmed_at) = transaction.confirmed_at;
+        }
+        self.update_ledger_lag();
+    }
+
+    async fn receive_event(
+        &self,
+        _transaction: &Transaction,
+        _event: &Event,
+        handling: bool,
+        is_retry: bool,
+    ) {
+        // The latency includes retries, like the time a handler blocks the processor.
+        if handling && !is_retry {
+            *lock(&self.event_stopwatch) = Instant::now();
+        }
+    }
+
+    async fn finish_event(
+        &self,
+        _transaction: &Transaction,
+        event: &Event,
+        handling: bool,
+    ) {
+        self.events_seen.inc();
+        if handling {
+            self.events_handled.inc();
+        }
+        self.handler_duration
+            .with_label_values(&[event.emitter.address(), &event.name])
+            .observe(lock(&self.event_stopwatch).elapsed().as_secs_f64());
+    }
+
+    async fn handler_timeout(
+        &self,
+        _transaction: &Transaction,
+        event: Option<&Event>,
+        _handler: Option<&str>,
+        _timeout: Duration,
+    ) {
+        let kind = match event {
+            Some(_) => "event",
+            None => "transaction",
+        };
+        self.timeouts.with_label_values(&[kind]).inc();
+    }
+
+    async fn event_retry_error(
+        &self,
+        _transaction: &Transaction,
+        _event: &Event,
+        _error: &anyhow::Error,
+        _timeout: Duration,
+        _attempt: u32,
+    ) {
+        self.retries.with_label_values(&["event"]).inc();
+    }
+
+    async fn transaction_retry_error(
+        &self,
+        _transaction: &Transaction,
+        _error: &anyhow::Error,
+        _timeout: Duration,
+        _attempt: u32,
+    ) {
+        self.retries.with_label_values(&["transaction"]).inc();
+    }
+
+    async fn event_skipped(
+        &self,
+        _transaction: &Transaction,
+        _event: &Event,
+        _error: &anyhow::Error,
+    ) {
+        self.skipped.with_label_values(&["event"]).inc();
+    }
+
+    async fn transaction_skipped(
+        &self,
+        _transaction: &Transaction,
+        _error: &anyhow::Error,
+    ) {
+        self.skipped.with_label_values(&["transaction"]).inc();
+    }
+
+    async fn dead_lettered(&self, dead_letter: &DeadLetter) {
+        let kind = match dead_letter.event_index {
+            Some(_) => "event",
+            None => "transaction",
+        };
+        self.dead_letters.with_label_values(&[kind]).inc();
+    }
+
+    async fn unrecoverable_error(&self, _error: &anyhow::Error) {
+        self.unrecoverable_errors.inc();
+    }
+
+    async fn periodic_report(&self) {
+        // The lag keeps growing while the processor is stuck.
+        self.update_ledger_lag();
+    }
+
+    fn periodic_report_interval(&self) -> Duration {
+        Duration::from_secs(5)
+    }
+}
@@ -0,0 +1,473 @@
+//! A logger that writes one JSON object per line, for log aggregation systems.
+
+use super::{lock, Logger};
+use crate::{
+    dead_letter::DeadLetter,
+    models::{Event, Transaction},
+};
+use async_trait::async_trait;
+use chrono::{SecondsFormat, Utc};
+use log::{Level, LevelFilter};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A [`Logger`] which writes a JSON object for each hook, on its own line,
+/// instead of the colored lines of the [`DefaultLogger`][super::DefaultLogger].
+///
+/// Each record has a `timestamp`, a `level` and the name of the `hook`, and
+/// depending on the hook: `state_version`, `intent_hash`, `event_name`, `emitter`,
+/// `handler`, `handling`, `duration_ms`, `attempt`, `retry_in_ms`, `timeout_ms` and `error`.
+///
+/// ```json
+/// {"timestamp":"2024-06-01T12:00:00.000Z","level":"INFO","hook":"finish_transaction","state_version":1234,"intent_hash":"txid_...","handling":true,"duration_ms":12.5,"attempt":1}
+/// ```
+///
+/// Records of `receive_transaction`, `receive_event`, `finish_event`,
+/// `start_handler` and `finish_handler` are at the `DEBUG` level, and so is `finish_transaction` for transactions without
+/// handled events. Finished transactions and periodic reports are at `INFO`,
+/// retries and timeouts at `WARN`, and skips, dead letters and unrecoverable errors at `ERROR`.
+pub struct StructuredLogger {
+    writer: Mutex<Box<dyn Write + Send>>,
+    level: LevelFilter,
+    sample_every: u64,
+    report_interval: Duration,
+    state: Mutex<State>,
+}
+
+/// What the logger keeps track of between hooks.
+struct State {
+    sample_counters: HashMap<&'static str, u64>,
+    transaction_stopwatch: Instant,
+    transaction_attempt: u32,
+    event_stopwatch: Instant,
+    event_attempt: u32,
+    last_state_version: Option<u64>,
+}
+
+impl Default for StructuredLogger {
+    fn default() -> Self {
+        Self {
+            writer: Mutex::new(Box::new(std::io::stdout())),
+            level: LevelFilter::Info,
+            sample_every: 1,
+            report_interval: Duration::from_secs(5),
+            state: Mutex::new(State {
+                sample_counters: HashMap::new(),
+                transaction_stopwatch: Instant::now(),
+                transaction_attempt: 1,
+                event_stopwatch: Instant::now(),
+                event_attempt: 1,
+                last_state_version: None,
+            }),
+        }
+    }
+}
+
+impl StructuredLogger {
+    /// Creates a new StructuredLogger which writes records
+    /// at the `INFO` level and above to stdout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets where the records are written to, like a file.
+    pub fn writer(self, writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+            ..self
+        }
+    }
+
+    /// Sets the most detailed level of records to write.
+    /// Use `LevelFilter::Debug` to also get a record for each event.
+    pub fn level(self, level: LevelFilter) -> Self {
+        Self { level, ..self }
+    }
+
+    /// Only writes every n-th record of the high-volume hooks at the `DEBUG` level,
+    /// like `receive_event`, counted per hook. Records at the other levels
+    /// are always written. Defaults to 1, which writes all records.
+    pub fn sample_every(self, sample_every: u64) -> Self {
+        Self {
+            sample_every: sample_every.max(1),
+            ..self
+        }
+    }
+
+    /// Sets the interval of the periodic report. Defaults to 5 seconds.
+    pub fn report_interval(self, report_interval: Duration) -> Self {
+        Self {
+            report_interval,
+            ..self
+        }
+    }
+
+    /// Returns whether a record of the hook should be written,
+    /// counting it for sampling if it is a high-volume record.
+    fn enabled(&self, level: Level, hook: &'static str) -> bool {
+        if level > self.level {
+            return false;
+        }
+        if level < Level::Debug {
+            return true;
+        }
+        let mut state = lock(&self.state);
+        let counter = state.sample_counters.entry(hook).or_default();
+        *counter += 1;
+        (*counter - 1) % self.sample_every == 0
+    }
+
+    /// Writes a record with the given fields. Failing to write
+    /// is ignored, as logging should never stop the processor.
+    fn write(&self, level: Level, hook: &str, fields: Value) {
+        let mut record = Map::new();
+        record.insert(
+            "timestamp".to_string(),
+            json!(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+        );
+        record.insert("level".to_string(), json!(level.as_str()));
+        record.insert("hook".to_string(), json!(hook));
+        if let Value::Object(fields) = fields {
+            record.extend(fields);
+        }
+        let mut line = Value::Object(record).to_string();
+        line.push('\n');
+        let _ = lock(&self.writer).write_all(line.as_bytes());
+    }
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[async_trait]
+impl Logger for StructuredLogger {
+    async fn receive_transaction(
+        &self,
+        transaction: &Transaction,
+        handling: bool,
+        is_retry: bool,
+    ) {
+        let attempt = {
+            let mut state = lock(&self.state);
+            if is_retry {
+                state.transaction_attempt += 1;
+            } else {
+                state.transaction_stopwatch = Instant::now();
+                state.transaction_attempt = 1;
+            }
+            state.transaction_attempt
+        };
+        if self.enabled(Level::Debug, "receive_transaction") {
+            self.write(
+                Level::Debug,
+                "receive_transaction",
+                json!({
+                    "state_version": transaction.state_version,
+                    "intent_hash": transaction.intent_hash,
+                    "handling": handling,
+                    "attempt": attempt,
+                }),
+            );
+        }
+    }
+
+    async fn finish_transaction(
+        &self,
+        transaction: &Transaction,
+        handling: bool,
+    ) {
+        let (duration, attempt) = {
+            let mut state = lock(&self.state);
+            state.last_state_version = Some(transaction.state_version);
+            (
+                state.transaction_stopwatch.elapsed(),
+                state.transaction_attempt,
+            )
+        };
+        let level = if handling { Level::Info } else { Level::Debug };
+        if self.enabled(level, "finish_transaction") {
+            self.write(
+                level,
+                "finish_transaction",
+                json!({
+                    "state_version": transaction.state_version,
+                    "intent_hash": transaction.intent_hash,
+                    "handling": handling,
+                    "duration_ms": milliseconds(duration),
+                    "attempt": attempt,
+                }),
+            );
+        }
+    }
+
+    async fn receive_event(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        handling: bool,
+        is_retry: bool,
+    ) {
+        let attempt = {
+            let mut state = lock(&self.state);
+            if is_retry {
+                state.event_attempt += 1;
+            } else {
+                state.event_stopwatch = Instant::now();
+                state.event_attempt = 1;
+            }
+            state.event_attempt
+        };
+        if self.enabled(Level::Debug, "receive_event") {
+            self.write(
+                Level::Debug,
+                "receive_event",
+                json!({
+                    "state_version": transaction.state_version,
+                    "intent_hash": transaction.intent_hash,
+                    "event_name": event.name,
+                    "emitter": event.emitter.address(),
+                    "handling": handling,
+                    "attempt": attempt,
+                }),
+            );
+        }
+    }
+
+    async fn finish_event(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        handling: bool,
+    ) {
+        if self.enabled(Level::Debug, "finish_event") {
+            let (duration, attempt) = {
+                let state = lock(&self.state);
+                (state.event_stopwatch.elapsed(), state.event_attempt)
+            };
+            self.write(
+                Level::Debug,
+                "finish_event",
+                json!({
+                    "state_version": transaction.state_version,
+                    "intent_hash": transaction.intent_hash,
+                    "event_name": event.name,
+                    "emitter": event.emitter.address(),
+                    "handling": handling,
+                    "duration_ms": milliseconds(duration),
+                    "attempt": attempt,
+                }),
+            );
+        }
+    }
+
+    async fn start_handler(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        handler: &str,
+        attempt: u32,
+    ) {
+        if self.enabled(Level::Debug, "start_handler") {
+            self.write(
+                Level::Debug,
+                "start_handler",
+                json!({
+                    "state_version": transaction.state_version,
+                    "intent_hash": transaction.intent_hash,
+                    "event_name": event.name,
+                    "emitter": event.emitter.address(),
+                    "handler": handler,
+                    "attempt": attempt,
+                }),
+            );
+        }
+    }
+
+    async fn finish_handler(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        handler: &str,
+        handled: bool,
+    ) {
+        if self.enabled(Level::Debug, "finish_handler") {
+            self.write(
+                Level::Debug,
+                "finish_handler",
+                json!({
+                    "state_version": transaction.state_version,
+                    "intent_hash": transaction.intent_hash,
+                    "event_name": event.name,
+                    "emitter": event.emitter.address(),
+                    "handler": handler,
+                    "handling": handled,
+                }),
+            );
+        }
+    }
+
+    async fn handler_timeout(
+        &self,
+        transaction: &Transaction,
+        event: Option<&Event>,
+        handler: Option<&str>,
+        timeout: Duration,
+    ) {
+        if self.enabled(Level::Warn, "handler_timeout") {
+            self.write(
+                Level::Warn,
+                "handler_timeout",
+                json!({
+                    "state_version": transaction.state_version,
+                    "intent_hash": transaction.intent_hash,
+                    "event_name": event.map(|event| &event.name),
+                    "emitter": event.map(|event| event.emitter.address()),
+                    "handler": handler,
+                    "timeout_ms": milliseconds(timeout),
+                }),
+            );
+        }
+    }
+
+    async fn event_retry_error(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        error: &anyhow::Error,
+        timeout: Duration,
+        attempt: u32,
+    ) {
+        if self.enabled(Level::Warn, "event_retry_error") {
+            self.write(
+                Level::Warn,
+                "event_retry_error",
+                json!({
+                    "state_version": transaction.state_version,
+                    "intent_hash": transaction.intent_hash,
+                    "event_name": event.name,
+                    "emitter": event.emitter.address(),
+                    "attempt": attempt,
+                    "retry_in_ms": milliseconds(timeout),
+                    "error": format!("{:#}", error),
+                }),
+            );
+        }
+    }
+
+    async fn transaction_retry_error(
+        &self,
+        transaction: &Transaction,
+        error: &anyhow::Error,
+        timeout: Duration,
+        attempt: u32,
+    ) {
+        if self.enabled(Level::Warn, "transaction_retry_error") {
+            self.write(
+                Level::Warn,
+                "transaction_retry_error",
+                json!({
+                    "state_version": transaction.state_version,
+                    "intent_hash": transaction.intent_hash,
+                    "attempt": attempt,
+                    "retry_in_ms": milliseconds(timeout),
+                    "error": format!("{:#}", error),
+                }),
+            );
+        }
+    }
+
+    async fn event_skipped(
+        &self,
+        transaction: &Transaction,
+        event: &Event,
+        error: &anyhow::Error,
+    ) {
+        if self.enabled(Level::Error, "event_skipped") {
+            let attempt = lock(&self.state).event_attempt;
+            self.write(
+                Level::Error,
+                "event_skipped",
+                json!({
+                    "state_version": transaction.state_version,
+                    "intent_hash": transaction.intent_hash,
+                    "event_name": event.name,
+                    "emitter": event.emitter.address(),
+                    "attempt": attempt,
+                    "error": format!("{:#}", error),
+                }),
+            );
+        }
+    }
+
+    async fn transaction_skipped(
+        &self,
+        transaction: &Transaction,
+        error: &anyhow::Error,
+    ) {
+        if self.enabled(Level::Error, "transaction_skipped") {
+            let attempt = lock(&self.state).transaction_attempt;
+            self.write(
+                Level::Error,
+                "transaction_skipped",
+                json!({
+                    "state_version": transaction.state_version,
+                    "intent_hash": transaction.intent_hash,
+                    "attempt": attempt,
+                    "error": format!("{:#}", error),
+                }),
+            );
+        }
+    }
+
+    async fn dead_lettered(&self, dead_letter: &DeadLetter) {
+        if self.enabled(Level::Error, "dead_lettered") {
+            let event = dead_letter.failed_event();
+            self.write(
+                Level::Error,
+                "dead_lettered",
+                json!({
+                    "state_version": dead_letter.state_version(),
+                    "intent_hash": dead_letter.transaction.intent_hash,
+                    "event_name": event.map(|event| &event.name),
+                    "emitter": event.map(|event| event.emitter.address()),
+                    "error": dead_letter.errors.join(": "),
+                }),
+            );
+        }
+    }
+
+    async fn unrecoverable_error(&self, error: &anyhow::Error) {
+        if self.enabled(Level::Error, "unrecoverable_error") {
+            self.write(
+                Level::Error,
+                "unrecoverable_error",
+                json!({ "error": format!("{:#}", error) }),
+            );
+        }
+    }
+
+    async fn periodic_report(&self) {
+        if Level::Info <= self.level {
+            let last_state_version = lock(&self.state).last_state_version;
+            self.write(
+                Level::Info,
+                "periodic_report",
+                json!({ "state_version": last_state_version }),
+            );
+        }
+    }
+
+    fn periodic_report_interval(&self) -> Duration {
+        self.report_interval
+    }
+
+    async fn flush(&self) {
+        let _ = lock(&self.writer).flush();
+    }
+}
@@ -0,0 +1,255 @@
+//! A logger that records the processing of transactions and events
+//! as [`tracing`] spans and events.
+
+use super::{lock, Logger};
+use crate::{
+    dead_letter::DeadLetter,
+    models::{Event, Transaction},
+};
+use async_trait::async_trait;
+use std::{sync::Mutex, time::Duration};
+use tracing::{field, Span};
+
+/// A [`Logger`] which opens a `transaction` span when a transaction is received,
+/// and closes it when the transaction is finished. Each attempt at handling it
+/// gets an `attempt` child span, in which each event gets an `event` span
+/// with its own `attempt` spans.
+///
+/// The processor runs the handlers inside the span of their current attempt
+/// (see [`Logger::current_span`]), so spans and events from handler code,
+/// like a slow database query, nest under the transaction and event
+/// that caused them.
+///
+/// Transaction spans carry `state_version` and `intent_hash`, and event spans
+/// carry `emitter` and `event_name`. Retries, skips, dead letters
+/// and errors are recorded as events inside these spans.
+///
+/// To export the spans, install a `tracing` subscriber, or use
+/// [`init_otlp`][crate::logger::otlp::init_otlp] with the `otlp` feature.
+#[derive(Debug, Default)]
+pub struct TracingLogger {
+    spans: Mutex<Spans>,
+}
+
+/// The spans which are currently open.
+#[derive(Debug, Default)]
+struct Spans {
+    transaction: Option<Span>,
+    transaction_attempt: Option<(Span, u32)>,
+    event: Option<Span>,
+    event_attempt: Option<(Span, u32)>,
+}
+
+impl TracingLogger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the innermost open span, in which errors are recorded.
+    fn innermost_span(&self) -> Span {
+        let spans = lock(&self.spans);
+        spans
+            .event_attempt
+            .as_ref()
+            .or(spans.transaction_attempt.as_ref())
+            .map(|(span, _)| span.clone())
+            .unwrap_or_else(Span::none)
+    }
+}
+
+/// Opens the span for the next attempt, as a child of `parent`.
+fn next_attempt(
+    parent: Option<&Span>,
+    previous: Option<(Span, u32)>,
+    is_retry: bool,
+) -> Option<(Span, u32)> {
+    let attempt = match previous {
+        Some((_, attempt)) if is_retry => attempt + 1,
+        _ => 1,
+    };
+    let parent = parent?;
+    Some((
+        tracing::info_span!(parent: parent, "attempt", attempt),
+        attempt,
+    ))
+}
+
+#[async_trait]
+impl Logger for TracingLogger {
+    async fn receive_transaction(
+        &self,
+        transaction: &Transaction,
+        handling: bool,
+        is_retry: bool,
+    ) {
+        let mut spans = lock(&self.spans);
+        if !is_retry {
+            spans.transaction = Some(tracing::info_span!(
+                "transaction",
+                state_version = transaction.state_version,
+                intent_hash = %transaction.intent_hash,
+                handling,
+                handled = field::Empty,
+            ));
+        }
+        let previous = spans.transaction_attempt.take();
+        spans.transaction_attempt =
+            next_attempt(spans.transaction.as_ref(), previous, is_retry);
+    }
+
+    async fn finish_transaction(
+        &self,
+        _transaction: &Transaction,
+        handling: bool,
+    ) {
+        let mut spans = lock(&self.spans);
+        if let Some(span) = &spans.transaction {
+            span.record("handled", handling);
+        }
+        // Dropping the spans closes them.
+        spans.transaction_attempt = None;
+        spans.transaction = None;
+    }
+
+    async fn receive_event(
+        &self,
+        _transaction: &Transaction,
+        event: &Event,
+        _handling: bool,
+        is_retry: bool,
+    ) {
+        let mut spans = lock(&self.spans);
+        if !is_retry {
+            let parent = spans
+                .transaction_attempt
+                .as_ref()
+                .map(|(span, _)| span.clone())
+                .unwrap_or_else(Span::current);
+            spans.event = Some(tracing::info_span!(
+                parent: &parent,
+                "event",
+                emitter = event.emitter.address(),
+                event_name = %event.name,
+                handled = field::Empty,
+            ));
+        }
+        let previous = spans.event_attempt.take();
+        spans.event_attempt =
+            next_attempt(spans.event.as_ref(), previous, is_retry);
+    }
+
+    async fn finish_event(
+        &self,
+        _transaction: &Transaction,
+        _event: &Event,
+        handling: bool,
+    ) {
+        let mut spans = lock(&self.spans);
+        if let Some(span) = &spans.event {
+            span.record("handled", handling);
+        }
+        spans.event_attempt = None;
+        spans.event = None;
+    }
+
+    async fn handler_timeout(
+        &self,
+        _transaction: &Transaction,
+        _event: Option<&Event>,
+        handler: Option<&str>,
+        timeout: Duration,
+    ) {
+        tracing::warn!(
+            parent: &self.innermost_span(),
+            handler,
+            timeout_ms = timeout.as_millis() as u64,
+            "handler timed out"
+        );
+    }
+
+    async fn event_retry_error(
+        &self,
+        _transaction: &Transaction,
+        _event: &Event,
+        error: &anyhow::Error,
+        timeout: Duration,
+        attempt: u32,
+    ) {
+        tracing::warn!(
+            parent: &self.innermost_span(),
+            error = ?error,
+            attempt,
+            retry_in_ms = timeout.as_millis() as u64,
+            "event attempt failed, retrying"
+        );
+    }
+
+    async fn transaction_retry_error(
+        &self,
+        _transaction: &Transaction,
+        error: &anyhow::Error,
+        timeout: Duration,
+        attempt: u32,
+    ) {
+        tracing::warn!(
+            parent: &self.innermost_span(),
+            error = ?error,
+            attempt,
+            retry_in_ms = timeout.as_millis() as u64,
+            "transaction attempt failed, retrying"
+        );
+    }
+
+    async fn event_skipped(
+        &self,
+        _transaction: &Transaction,
+        _event: &Event,
+        error: &anyhow::Error,
+    ) {
+        tracing::error!(
+            parent: &self.innermost_span(),
+            error = ?error,
+            "skipping event after last attempt"
+        );
+    }
+
+    async fn transaction_skipped(
+        &self,
+        _transaction: &Transaction,
+        error: &anyhow::Error,
+    ) {
+        tracing::error!(
+            parent: &self.innermost_span(),
+            error = ?error,
+            "skipping transaction after last attempt"
+        );
+    }
+
+    async fn dead_lettered(&self, dead_letter: &DeadLetter) {
+        tracing::error!(
+            parent: &self.innermost_span(),
+            state_version = dead_letter.state_version(),
+            event_index = dead_letter.event_index,
+            error = %dead_letter.errors.join(": "),
+            "dead-lettered"
+        );
+    }
+
+    async fn unrecoverable_error(&self, error: &anyhow::Error) {
+        tracing::error!(
+            parent: &self.innermost_span(),
+            error = ?error,
+            "unrecoverable error"
+        );
+    }
+
+    async fn periodic_report(&self) {}
+
+    fn periodic_report_interval(&self) -> Duration {
+        Duration::from_secs(60)
+    }
+
+    fn current_span(&self) -> Span {
+        self.innermost_span()
+    }
+}
@@ -30,6 +30,18 @@ pub enum EventEmitter {
         entity_type: EntityType,
         is_global: bool,
         object_module_id: ModuleId,
+        /// The package of the blueprint the component was instantiated from,
+        /// if the transaction stream resolved it.
+        #[serde(default)]
+        package_address: Option<String>,
+        /// The blueprint the component was instantiated from,
+        /// if the transaction stream resolved it.
+        #[serde(default)]
+        blueprint_name: Option<String>,
+        /// The resource of the vault, if the emitter is a vault
+        /// and the transaction stream resolved it.
+        #[serde(default)]
+        resource_address: Option<String>,
     },
     Function {
         package_address: String,
@@ -47,6 +59,35 @@ impl EventEmitter {
             } => package_address,
         }
     }
+
+    /// Returns the package address and blueprint name of the emitter,
+    /// if they are known.
+    pub fn blueprint(&self) -> Option<(&str, &str)> {
+        match self {
+            EventEmitter::Method {
+                package_address,
+                blueprint_name,
+                ..
+            } => {
+                Some((package_address.as_deref()?, blueprint_name.as_deref()?))
+            }
+            EventEmitter::Function {
+                package_address,
+                blueprint_name,
+            } => Some((package_address, blueprint_name)),
+        }
+    }
+
+    /// Returns the resource address of the emitter, if it is a vault
+    /// and the resource is known.
+    pub fn resource_address(&self) -> Option<&str> {
+        match self {
+            EventEmitter::Method {
+                resource_address, ..
+            } => resource_address.as_deref(),
+            EventEmitter::Function { .. } => None,
+        }
+    }
 }
 
 /// Generic struct for ledger transactions from a
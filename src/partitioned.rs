@@ -0,0 +1,1120 @@
+/*!
+# Partitioned Transaction Stream Processor - Parallel event processing
+
+This module holds a processor which processes the events of a [`TransactionStream`]
+in parallel, on multiple shards. Each event is assigned to a shard by a partition key,
+which defaults to the address of the emitter of the event. Events with the same
+partition key are always processed by the same shard, in ledger order.
+Events with different partition keys may be processed in any order.
+
+This is useful when the handlers for different components never touch the same data,
+so that there is no reason to process their events one after the other.
+
+Handlers which a shard adds to its registry while processing, for example for
+a component which was just instantiated, handle the events they match on that
+shard, whatever their partition key. This keeps them in ledger order, next to
+the state of the shard which added them.
+*/
+
+use crate::{
+    checkpoints::CheckpointStore,
+    dead_letter::DeadLetterSink,
+    error::{
+        EventHandlerError, PanicAction, TransactionHandlerError,
+        TransactionProcessorError,
+    },
+    event_handler::{HandlerRegistry, State},
+    handle::{Command, Controls, ProcessorHandle},
+    logger::{combine_loggers, DefaultLogger, Logger},
+    models::{Event, Transaction},
+    processor::{
+        next_transaction, spawn_periodic_logging, termination_signal,
+        DefaultTransactionHandler, ShutdownSignal, StopReason,
+        TransactionProcessor, DEFAULT_CHECKPOINT_INTERVAL,
+    },
+    registrations::RegistrationStore,
+    retry::RetryPolicy,
+    stream::{StreamBounds, StreamEnd, TransactionStream},
+    transaction_handler::TransactionHandler,
+};
+use chrono::{DateTime, Utc};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    future::Future,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{
+        mpsc::{Receiver, Sender, UnboundedReceiver},
+        watch,
+    },
+    task::JoinHandle,
+};
+
+/// A function which returns the partition key of an event.
+type PartitionKey = Box<dyn Fn(&Event) -> String + Send + Sync>;
+
+/// The work for a shard: a transaction, and the indices
+/// of the events in it which belong to the shard.
+/// Every shard gets every transaction, for the handlers
+/// it added while processing.
+type ShardWork = (Arc<Dispatched>, Vec<u16>);
+
+/// A transaction which is handed to the shards.
+struct Dispatched {
+    transaction: Transaction,
+    /// Set by the first shard which has handlers for the transaction,
+    /// which reports it to the logger. When no shard does, the processor
+    /// reports it once all shards finished it.
+    reported: AtomicBool,
+}
+
+/// The result of a shard processing its part of a transaction,
+/// which is the state version of the transaction if it succeeded.
+type ShardResult = Result<u64, TransactionProcessorError>;
+
+/// A processor which processes the events of a [`TransactionStream`] in parallel,
+/// sharded by a partition key. Each shard runs on its own task, with its own
+/// [`TransactionProcessor`], [`HandlerRegistry`] and state.
+///
+/// - Events with the same partition key are processed in order by the same shard.
+///   The key defaults to the address of the emitter, and can be changed with
+///   [`PartitionedTransactionStreamProcessor::partition_key`].
+/// - The transaction handler is called by each shard for the events of a transaction
+///   which belong to it, and [`EventProcessor::process_events`][crate::processor::EventProcessor::process_events]
+///   only processes those events. Each transaction is reported to the logger once:
+///   by the first shard with handlers for it, or by the processor when it has none.
+///   The hooks for its events are called by the shards which handle them.
+/// - A handler which a shard adds to its registry while processing, directly or with
+///   [`HandlerRegistry::register_handler`], only exists on that shard. It handles
+///   the events it matches there, whatever shard their partition key belongs to.
+///   The shard which owns such an event still runs its own handlers for it, if it has any.
+/// - The checkpoint is a watermark: the highest state version up to which all
+///   transactions are fully processed by all shards. After a restart, the transactions
+///   after the watermark are processed again, so handlers should be idempotent.
+/// - Each shard can have its own [`RegistrationStore`], see
+///   [`PartitionedTransactionStreamProcessor::registration_store`].
+/// - The [`ProcessorHandle`] and the bounds work like for the
+///   [`TransactionStreamProcessor`][crate::processor::TransactionStreamProcessor],
+///   but act between dispatching transactions to the shards. The shards still finish
+///   the transactions they already received.
+/// - There is no batch mode: each shard handles one transaction at a time.
+#[allow(non_camel_case_types)]
+pub struct PartitionedTransactionStreamProcessor<
+    STREAM,
+    STATE,
+    TRANSACTION_CONTEXT = (),
+> where
+    STREAM: TransactionStream,
+    STATE: State,
+    TRANSACTION_CONTEXT: 'static,
+{
+    transaction_stream: STREAM,
+    shards: Vec<TransactionProcessor<STATE, TRANSACTION_CONTEXT>>,
+    shard_count: usize,
+    shard_capacity: usize,
+    partition_key: PartitionKey,
+    logger: Option<Arc<dyn Logger>>,
+    checkpoint_store: Option<Box<dyn CheckpointStore>>,
+    checkpoint_interval: Duration,
+    bounds: StreamBounds,
+    handle: ProcessorHandle,
+    shutdown_signal: Option<ShutdownSignal>,
+    periodic_logging_joinhandle: Option<JoinHandle<()>>,
+}
+
+#[allow(non_camel_case_types)]
+impl<STREAM, STATE> PartitionedTransactionStreamProcessor<STREAM, STATE>
+where
+    STREAM: TransactionStream,
+    STATE: State,
+{
+    /// Creates a new [`PartitionedTransactionStreamProcessor`] with the given
+    /// [`TransactionStream`] and number of shards. The `shard` function is called
+    /// once for each shard with its index, and returns the [`HandlerRegistry`]
+    /// and the slice of the state which belong to that shard.
+    ///
+    /// The defaults are the same as for the
+    /// [`TransactionStreamProcessor`][crate::processor::TransactionStreamProcessor].
+    pub fn new(
+        transaction_stream: STREAM,
+        shard_count: usize,
+        shard: impl Fn(usize) -> (HandlerRegistry<STATE>, STATE),
+    ) -> Self {
+        Self::with_transaction_handler(
+            transaction_stream,
+            shard_count,
+            shard,
+            DefaultTransactionHandler,
+        )
+    }
+}
+
+#[allow(non_camel_case_types)]
+impl<STREAM, STATE, TRANSACTION_CONTEXT>
+    PartitionedTransactionStreamProcessor<STREAM, STATE, TRANSACTION_CONTEXT>
+where
+    STREAM: TransactionStream,
+    STATE: State,
+    TRANSACTION_CONTEXT: 'static,
+{
+    /// Creates a new [`PartitionedTransactionStreamProcessor`] with the given
+    /// [`TransactionHandler`], which creates the transaction context for the
+    /// handlers in the [`HandlerRegistry`]s. Each shard gets its own clone of it.
+    /// The other defaults are the same as for [`PartitionedTransactionStreamProcessor::new`].
+    pub fn with_transaction_handler(
+        transaction_stream: STREAM,
+        shard_count: usize,
+        shard: impl Fn(
+            usize,
+        )
+            -> (HandlerRegistry<STATE, TRANSACTION_CONTEXT>, STATE),
+        transaction_handler: impl TransactionHandler<STATE, TRANSACTION_CONTEXT>
+            + Clone,
+    ) -> Self {
+        let shard_count = shard_count.max(1);
+        let logger: Option<Arc<dyn Logger>> =
+            Some(Arc::new(DefaultLogger::default()));
+        let handle = ProcessorHandle::default();
+        let shards = (0..shard_count)
+            .map(|index| {
+                let (handler_registry, state) = shard(index);
+                TransactionProcessor {
+                    logger: logger.clone(),
+                    handle: handle.clone(),
+                    ..TransactionProcessor::with_transaction_handler(
+                        handler_registry,
+                        state,
+                        transaction_handler.clone(),
+                    )
+                }
+            })
+            .collect();
+        Self {
+            transaction_stream,
+            shards,
+            shard_count,
+            shard_capacity: 100,
+            partition_key: Box::new(|event| {
+                event.emitter.address().to_string()
+            }),
+            logger,
+            checkpoint_store: None,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            bounds: StreamBounds::default(),
+            handle,
+            shutdown_signal: None,
+            periodic_logging_joinhandle: None,
+        }
+    }
+
+    fn map_shards(
+        self,
+        f: impl FnMut(
+            TransactionProcessor<STATE, TRANSACTION_CONTEXT>,
+        ) -> TransactionProcessor<STATE, TRANSACTION_CONTEXT>,
+    ) -> Self {
+        Self {
+            shards: self.shards.into_iter().map(f).collect(),
+            ..self
+        }
+    }
+
+    /// Sets the function which returns the partition key of an event.
+    /// Events with the same key are processed in order by the same shard.
+    pub fn partition_key(
+        self,
+        partition_key: impl Fn(&Event) -> String + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            partition_key: Box::new(partition_key),
+            ..self
+        }
+    }
+
+    /// Sets how many transactions can be waiting for each shard
+    /// before the stream is paused. Defaults to 100.
+    pub fn shard_capacity(self, shard_capacity: usize) -> Self {
+        Self {
+            shard_capacity: shard_capacity.max(1),
+            ..self
+        }
+    }
+
+    /// Sets the [`TransactionHandler`] for the processor.
+    /// Each shard gets its own clone of the handler.
+    pub fn transaction_handler(
+        self,
+        transaction_handler: impl TransactionHandler<STATE, TRANSACTION_CONTEXT>
+            + Clone,
+    ) -> Self {
+        self.map_shards(|shard| {
+            shard.transaction_handler(transaction_handler.clone())
+        })
+    }
+
+    /// Sets the [`RetryPolicy`] for transactions that fail to process and return
+    /// a `TransactionRetryError`.
+    pub fn transaction_retry_policy(
+        self,
+        transaction_retry_policy: RetryPolicy,
+    ) -> Self {
+        self.map_shards(|shard| {
+            shard.transaction_retry_policy(transaction_retry_policy.clone())
+        })
+    }
+
+    /// Sets the [`RetryPolicy`] for events that fail to process and return
+    /// an `EventRetryError`.
+    pub fn event_retry_policy(self, event_retry_policy: RetryPolicy) -> Self {
+        self.map_shards(|shard| {
+            shard.event_retry_policy(event_retry_policy.clone())
+        })
+    }
+
+    /// Sets a timeout for each attempt of an event handler, like
+    /// [`TransactionStreamProcessor::event_handler_timeout`][crate::processor::TransactionStreamProcessor::event_handler_timeout].
+    pub fn event_handler_timeout(
+        self,
+        timeout: Duration,
+        error: fn(anyhow::Error) -> EventHandlerError,
+    ) -> Self {
+        self.map_shards(|shard| shard.event_handler_timeout(timeout, error))
+    }
+
+    /// Sets a timeout for each attempt of the transaction handler of a shard, like
+    /// [`TransactionStreamProcessor::transaction_timeout`][crate::processor::TransactionStreamProcessor::transaction_timeout].
+    pub fn transaction_timeout(
+        self,
+        timeout: Duration,
+        error: fn(anyhow::Error) -> TransactionHandlerError,
+    ) -> Self {
+        self.map_shards(|shard| shard.transaction_timeout(timeout, error))
+    }
+
+    /// Sets what happens when a handler of a shard panics, like
+    /// [`TransactionStreamProcessor::on_handler_panic`][crate::processor::TransactionStreamProcessor::on_handler_panic].
+    pub fn on_handler_panic(self, panic_action: PanicAction) -> Self {
+        self.map_shards(|shard| shard.on_handler_panic(panic_action))
+    }
+
+    /// Sets the logger for the processor, which is shared by all shards.
+    pub fn logger(self, logger: impl Logger + 'static) -> Self {
+        self.shared_logger(Arc::new(logger))
+    }
+
+    /// Adds a logger next to the current one, so that all of them get the hooks.
+    /// See [`CompositeLogger`][crate::logger::CompositeLogger].
+    pub fn add_logger(self, logger: impl Logger + 'static) -> Self {
+        let logger = combine_loggers(self.logger.clone(), logger);
+        self.shared_logger(logger)
+    }
+
+    fn shared_logger(self, logger: Arc<dyn Logger>) -> Self {
+        Self {
+            logger: Some(logger.clone()),
+            ..self.map_shards(|shard| TransactionProcessor {
+                logger: Some(logger.clone()),
+                ..shard
+            })
+        }
+    }
+
+    /// Disables logging for the processor by setting the logger to `None`.
+    pub fn disable_logging(self) -> Self {
+        Self {
+            logger: None,
+            ..self.map_shards(|shard| shard.disable_logging())
+        }
+    }
+
+    /// Sets a [`CheckpointStore`] for the processor, in which it stores the watermark.
+    pub fn checkpoint_store(
+        self,
+        checkpoint_store: impl CheckpointStore + 'static,
+    ) -> Self {
+        Self {
+            checkpoint_store: Some(Box::new(checkpoint_store)),
+            ..self
+        }
+    }
+
+    /// Sets how often the watermark is stored while it moves forward.
+    /// Defaults to 10 seconds. It is always stored when the processor stops.
+    pub fn checkpoint_interval(self, checkpoint_interval: Duration) -> Self {
+        Self {
+            checkpoint_interval,
+            ..self
+        }
+    }
+
+    /// Sets a [`RegistrationStore`] for each shard, which is created by calling
+    /// `registration_store` with the index of the shard. A shard stores the
+    /// handlers it registers with [`HandlerRegistry::register_handler`] in its
+    /// own store, and adds them to its registry again when it is started.
+    /// The stores must keep their registrations apart, for example by
+    /// including the index of the shard in their name.
+    pub fn registration_store<S: RegistrationStore + 'static>(
+        self,
+        registration_store: impl Fn(usize) -> S,
+    ) -> Self {
+        Self {
+            shards: self
+                .shards
+                .into_iter()
+                .enumerate()
+                .map(|(index, shard)| {
+                    shard.registration_store(registration_store(index))
+                })
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Sets a [`DeadLetterSink`], which is shared by all shards.
+    /// See [`TransactionStreamProcessor::dead_letter_sink`][crate::processor::TransactionStreamProcessor::dead_letter_sink].
+    pub fn dead_letter_sink(
+        self,
+        dead_letter_sink: impl DeadLetterSink + 'static,
+    ) -> Self {
+        let dead_letter_sink: Arc<dyn DeadLetterSink> =
+            Arc::new(dead_letter_sink);
+        self.map_shards(|shard| TransactionProcessor {
+            dead_letter_sink: Some(dead_letter_sink.clone()),
+            ..shard
+        })
+    }
+
+    /// Makes the processor stop after the transaction with this state version,
+    /// which is inclusive, like
+    /// [`TransactionStreamProcessor::until_state_version`][crate::processor::TransactionStreamProcessor::until_state_version].
+    pub fn until_state_version(mut self, until_state_version: u64) -> Self {
+        self.bounds.until_state_version = Some(until_state_version);
+        self
+    }
+
+    /// Makes the processor stop at the first transaction which was confirmed
+    /// after this time, without processing it, like
+    /// [`TransactionStreamProcessor::until_confirmed_at`][crate::processor::TransactionStreamProcessor::until_confirmed_at].
+    pub fn until_confirmed_at(
+        mut self,
+        until_confirmed_at: DateTime<Utc>,
+    ) -> Self {
+        self.bounds.until_confirmed_at = Some(until_confirmed_at);
+        self
+    }
+
+    /// Sets a future which makes the processor shut down gracefully
+    /// when it completes. The shards finish the transactions which
+    /// were already handed to them before the processor stops.
+    pub fn shutdown_signal(
+        self,
+        shutdown_signal: impl Future<Output = ()> + Send + 'static,
+    ) -> Self {
+        Self {
+            shutdown_signal: Some(Box::pin(shutdown_signal)),
+            ..self
+        }
+    }
+
+    /// Makes the processor shut down gracefully when the process
+    /// receives SIGINT (Ctrl+C) or SIGTERM.
+    pub fn graceful_shutdown(self) -> Self {
+        self.shutdown_signal(termination_signal())
+    }
+
+    /// Returns a [`ProcessorHandle`], which can pause, resume, stop
+    /// and seek the processor while it runs, and report its status.
+    /// The shards share it, so it is also the `handle` of the handler contexts.
+    /// The status reports the watermark as the last finished transaction.
+    pub fn handle(&self) -> ProcessorHandle {
+        self.handle.clone()
+    }
+
+    /// The shard processors, for example to inspect their state
+    /// after [`PartitionedTransactionStreamProcessor::run`] returned.
+    /// While running, and after a shard failed, this is empty.
+    pub fn shards(
+        &self,
+    ) -> &[TransactionProcessor<STATE, TRANSACTION_CONTEXT>] {
+        &self.shards
+    }
+
+    /// Starts processing transactions from the [`TransactionStream`].
+    ///
+    /// Returns the reason why the processor stopped. Whatever the outcome,
+    /// the stream is stopped, the periodic logging task is aborted and
+    /// the logger is flushed before returning. If the stream closed its
+    /// channel because it failed, its error is returned instead of
+    /// [`StopReason::StreamFinished`]. If it closed it because it got to the
+    /// end of its own bounds, [`StopReason::BoundReached`] is returned.
+    pub async fn run(
+        &mut self,
+    ) -> Result<StopReason, TransactionProcessorError> {
+        self.handle.set_running(true);
+        let mut result = self.process_stream().await;
+        // The stream may have closed its channel because it got
+        // to the end of its own bounds, or because it failed.
+        if let Ok(StopReason::StreamFinished) = result {
+            result = match self.transaction_stream.end().await {
+                Ok(StreamEnd::Finished) => Ok(StopReason::StreamFinished),
+                Ok(StreamEnd::BoundReached) => Ok(StopReason::BoundReached),
+                Err(error) => Err(self.unrecoverable_error(error).await),
+            };
+        }
+        self.handle.set_running(false);
+        self.transaction_stream.stop().await;
+        if let Some(handle) = self.periodic_logging_joinhandle.take() {
+            handle.abort();
+        }
+        if let Some(logger) = &self.logger {
+            logger.flush().await;
+        }
+        result
+    }
+
+    async fn process_stream(
+        &mut self,
+    ) -> Result<StopReason, TransactionProcessorError> {
+        // Handlers which are added from now on, including the stored
+        // registrations, only handle events on the shard which added them.
+        for shard in &mut self.shards {
+            shard.handler_registry.start_processing();
+            shard.load_registrations().await?;
+        }
+        let checkpoint = self.load_checkpoint().await?;
+        let mut receiver = match checkpoint {
+            Some(state_version) => {
+                self.transaction_stream.start_from(state_version + 1).await
+            }
+            None => self.transaction_stream.start().await,
+        }
+        .map_err(TransactionProcessorError::UnrecoverableError)?;
+        self.periodic_logging_joinhandle =
+            spawn_periodic_logging(self.logger.clone()).await;
+
+        // Start a task for each shard, which processes the work
+        // it receives in order, and reports back when it's done.
+        let (completion_sender, mut completions) =
+            tokio::sync::mpsc::unbounded_channel::<ShardResult>();
+        let mut senders: Vec<Sender<ShardWork>> = Vec::new();
+        let mut workers: Vec<
+            JoinHandle<TransactionProcessor<STATE, TRANSACTION_CONTEXT>>,
+        > = Vec::new();
+        for mut shard in std::mem::take(&mut self.shards) {
+            let (sender, mut work) =
+                tokio::sync::mpsc::channel::<ShardWork>(self.shard_capacity);
+            let completion_sender = completion_sender.clone();
+            workers.push(tokio::spawn(async move {
+                while let Some((dispatched, event_indices)) = work.recv().await
+                {
+                    let result = shard
+                        .process_partition(
+                            &dispatched.transaction,
+                            &event_indices,
+                            &dispatched.reported,
+                        )
+                        .await
+                        .map(|()| dispatched.transaction.state_version);
+                    let failed = result.is_err();
+                    let _ = completion_sender.send(result);
+                    if failed {
+                        break;
+                    }
+                }
+                shard
+            }));
+            senders.push(sender);
+        }
+        drop(completion_sender);
+
+        let mut watermark = Watermark::new(checkpoint);
+        let mut shutdown_signal = self.shutdown_signal.take();
+        let mut controls = self.handle.watch();
+        let stop_reason = loop {
+            self.handle.set_waiting(false);
+            let controlled = self
+                .apply_controls(
+                    &mut controls,
+                    &mut receiver,
+                    &mut shutdown_signal,
+                    &mut watermark,
+                    &mut completions,
+                )
+                .await;
+            // Once the shards finished everything, waiting
+            // for the stream means the processor is caught up.
+            self.handle.set_waiting(
+                receiver.is_empty() && watermark.in_flight.is_empty(),
+            );
+            let result = match controlled {
+                Ok(Some(stop_reason)) => break stop_reason,
+                Ok(None) => tokio::select! {
+                    biased;
+                    Some(result) = completions.recv() => match result {
+                        Ok(state_version) => {
+                            self.complete(&mut watermark, state_version).await;
+                            Ok(())
+                        }
+                        Err(err) => Err(err),
+                    },
+                    transaction = next_transaction(&mut receiver, &mut shutdown_signal) => {
+                        self.handle.set_waiting(false);
+                        let Some(transaction) = transaction else {
+                            break StopReason::Shutdown;
+                        };
+                        self.handle.set_queue(receiver.len(), receiver.max_capacity());
+                        let Some(transaction) = transaction else {
+                            break StopReason::StreamFinished;
+                        };
+                        if self.bounds.is_past(&transaction) {
+                            break StopReason::BoundReached;
+                        }
+                        let is_last = self.bounds.is_last(&transaction);
+                        match self
+                            .dispatch(transaction, &senders, &mut watermark, &mut completions)
+                            .await
+                        {
+                            Ok(()) if is_last => break StopReason::BoundReached,
+                            result => result,
+                        }
+                    }
+                    // Go back to the controls when they change while waiting,
+                    // so that the handle also works when no transactions arrive.
+                    _ = controls.changed() => Ok(()),
+                },
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                workers.iter().for_each(JoinHandle::abort);
+                return Err(err);
+            }
+            self.save_watermark(&mut watermark, false).await?;
+        };
+
+        // Let the shards finish the work they already received.
+        drop(senders);
+        for worker in workers {
+            match worker.await {
+                Ok(shard) => self.shards.push(shard),
+                Err(err) => {
+                    return Err(self
+                        .unrecoverable_error(anyhow::anyhow!(
+                            "Shard stopped unexpectedly: {}",
+                            err
+                        ))
+                        .await)
+                }
+            }
+        }
+        while let Some(result) = completions.recv().await {
+            self.complete(&mut watermark, result?).await;
+        }
+        self.save_watermark(&mut watermark, true).await?;
+        Ok(stop_reason)
+    }
+
+    /// Acts on the requests of the [`ProcessorHandle`]: waits while the
+    /// processor is paused, and restarts the stream for a seek.
+    /// Returns a [`StopReason`] if the processor should stop.
+    async fn apply_controls(
+        &mut self,
+        controls: &mut watch::Receiver<Controls>,
+        receiver: &mut Receiver<Transaction>,
+        shutdown_signal: &mut Option<ShutdownSignal>,
+        watermark: &mut Watermark,
+        completions: &mut UnboundedReceiver<ShardResult>,
+    ) -> Result<Option<StopReason>, TransactionProcessorError> {
+        loop {
+            match self.handle.take_command() {
+                Some(Command::Stop) => return Ok(Some(StopReason::Stopped)),
+                Some(Command::Seek(state_version)) => {
+                    self.seek(receiver, state_version, watermark, completions)
+                        .await?;
+                    continue;
+                }
+                None => {}
+            }
+            let paused = controls.borrow_and_update().paused;
+            if !paused {
+                return Ok(None);
+            }
+            // The shards finish the work they already received while
+            // the processor is paused, so keep the watermark moving.
+            let shutdown = async {
+                match shutdown_signal {
+                    Some(shutdown_signal) => shutdown_signal.as_mut().await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                biased;
+                _ = shutdown => return Ok(Some(StopReason::Shutdown)),
+                Some(result) = completions.recv() => {
+                    self.complete(watermark, result?).await;
+                    self.save_watermark(watermark, false).await?;
+                }
+                // The handle holds the sender, so it is never closed.
+                _ = controls.changed() => {}
+            }
+        }
+    }
+
+    /// Waits until the shards finished the transactions they received,
+    /// and restarts the stream from the given state version. Transactions
+    /// from there on are no longer skipped as finished, so they are
+    /// processed again. If the stream can't be restarted, the seek is ignored.
+    async fn seek(
+        &mut self,
+        receiver: &mut Receiver<Transaction>,
+        state_version: u64,
+        watermark: &mut Watermark,
+        completions: &mut UnboundedReceiver<ShardResult>,
+    ) -> Result<(), TransactionProcessorError> {
+        if !self.transaction_stream.can_restart() {
+            log::warn!(
+                "Ignoring the seek to state version {}, because the stream can't be restarted",
+                state_version
+            );
+            return Ok(());
+        }
+        while !watermark.in_flight.is_empty() {
+            let Some(result) = completions.recv().await else {
+                break;
+            };
+            self.complete(watermark, result?).await;
+        }
+        self.save_watermark(watermark, true).await?;
+        self.transaction_stream.stop().await;
+        *receiver = self
+            .transaction_stream
+            .start_from(state_version)
+            .await
+            .map_err(TransactionProcessorError::UnrecoverableError)?;
+        watermark.seek(state_version);
+        Ok(())
+    }
+
+    /// Marks a transaction as finished by one of the shards, and reports
+    /// the watermark in the status of the handle. Transactions which are
+    /// fully finished without any shard having handlers for them are
+    /// reported to the logger here.
+    async fn complete(&self, watermark: &mut Watermark, state_version: u64) {
+        let finished = watermark.complete(state_version);
+        for dispatched in &finished {
+            if dispatched.reported.load(Ordering::Acquire) {
+                continue;
+            }
+            if let Some(logger) = &self.logger {
+                let transaction = &dispatched.transaction;
+                logger.receive_transaction(transaction, false, false).await;
+                logger.finish_transaction(transaction, false).await;
+            }
+        }
+        if let Some(dispatched) = finished.last() {
+            self.handle.set_finished(
+                dispatched.transaction.state_version,
+                dispatched.transaction.confirmed_at,
+            );
+        }
+    }
+
+    /// Splits the events of a transaction over the shards, and hands each
+    /// shard the transaction with the indices of the events which belong to it.
+    async fn dispatch(
+        &self,
+        transaction: Transaction,
+        senders: &[Sender<ShardWork>],
+        watermark: &mut Watermark,
+        completions: &mut UnboundedReceiver<ShardResult>,
+    ) -> Result<(), TransactionProcessorError> {
+        // Skip transactions which were already finished before,
+        // which can happen when a stream doesn't support starting
+        // from a checkpoint.
+        if watermark
+            .stored
+            .is_some_and(|stored| transaction.state_version <= stored)
+        {
+            return Ok(());
+        }
+        let mut partitions: Vec<Vec<u16>> = vec![Vec::new(); self.shard_count];
+        for (event_index, event) in transaction.events.iter().enumerate() {
+            let mut hasher = DefaultHasher::new();
+            (self.partition_key)(event).hash(&mut hasher);
+            let shard = (hasher.finish() % self.shard_count as u64) as usize;
+            partitions[shard].push(event_index as u16);
+        }
+        let dispatched = Arc::new(Dispatched {
+            transaction,
+            reported: AtomicBool::new(false),
+        });
+        watermark.dispatch(dispatched.clone(), self.shard_count);
+
+        for (shard, event_indices) in partitions.into_iter().enumerate() {
+            if senders[shard]
+                .send((dispatched.clone(), event_indices))
+                .await
+                .is_ok()
+            {
+                continue;
+            }
+            // A shard only stops receiving work after it failed,
+            // which it reports before it stops.
+            while let Ok(result) = completions.try_recv() {
+                self.complete(watermark, result?).await;
+            }
+            return Err(self
+                .unrecoverable_error(anyhow::anyhow!(
+                    "Shard {} stopped unexpectedly",
+                    shard
+                ))
+                .await);
+        }
+        Ok(())
+    }
+
+    async fn load_checkpoint(
+        &self,
+    ) -> Result<Option<u64>, TransactionProcessorError> {
+        let Some(checkpoint_store) = &self.checkpoint_store else {
+            return Ok(None);
+        };
+        match checkpoint_store.load().await {
+            Ok(checkpoint) => Ok(checkpoint),
+            Err(e) => Err(self.unrecoverable_error(e).await),
+        }
+    }
+
+    /// Stores the watermark in the [`CheckpointStore`] if it moved forward
+    /// since it was last stored, at most once per checkpoint interval
+    /// unless `force` is set.
+    async fn save_watermark(
+        &self,
+        watermark: &mut Watermark,
+        force: bool,
+    ) -> Result<(), TransactionProcessorError> {
+        let Some(value) = watermark.value() else {
+            return Ok(());
+        };
+        if watermark.stored.is_some_and(|stored| value <= stored) {
+            return Ok(());
+        }
+        if !force && watermark.stored_at.elapsed() < self.checkpoint_interval {
+            return Ok(());
+        }
+        if let Some(checkpoint_store) = &self.checkpoint_store {
+            if let Err(e) = checkpoint_store.save(value).await {
+                return Err(self.unrecoverable_error(e).await);
+            }
+        }
+        watermark.stored = Some(value);
+        watermark.stored_at = Instant::now();
+        Ok(())
+    }
+
+    async fn unrecoverable_error(
+        &self,
+        error: anyhow::Error,
+    ) -> TransactionProcessorError {
+        if let Some(logger) = &self.logger {
+            logger.unrecoverable_error(&error).await;
+        }
+        TransactionProcessorError::UnrecoverableError(error)
+    }
+}
+
+/// Keeps track of the transactions which are being processed by the shards,
+/// to find the highest state version up to which all transactions are done.
+struct Watermark {
+    /// The transactions which not all shards finished yet, by state version,
+    /// with the number of shards which are still processing each of them.
+    in_flight: BTreeMap<u64, (usize, Arc<Dispatched>)>,
+    /// The state version up to which all transactions are finished.
+    finished: Option<u64>,
+    /// The watermark which was last stored.
+    stored: Option<u64>,
+    stored_at: Instant,
+}
+
+impl Watermark {
+    fn new(checkpoint: Option<u64>) -> Self {
+        Self {
+            in_flight: BTreeMap::new(),
+            finished: checkpoint,
+            stored: checkpoint,
+            stored_at: Instant::now(),
+        }
+    }
+
+    fn dispatch(&mut self, dispatched: Arc<Dispatched>, shards: usize) {
+        self.in_flight
+            .insert(dispatched.transaction.state_version, (shards, dispatched));
+    }
+
+    /// Marks a transaction as finished by one shard. Returns the transactions
+    /// the watermark moved past, in order, which is none if it didn't move.
+    fn complete(&mut self, state_version: u64) -> Vec<Arc<Dispatched>> {
+        if let Some((shards, _)) = self.in_flight.get_mut(&state_version) {
+            *shards = shards.saturating_sub(1);
+        }
+        // Everything before the oldest transaction
+        // which is still in flight is done.
+        let mut finished = Vec::new();
+        while let Some(entry) = self.in_flight.first_entry() {
+            if entry.get().0 > 0 {
+                break;
+            }
+            let (state_version, (_, dispatched)) = entry.remove_entry();
+            self.finished = Some(state_version);
+            finished.push(dispatched);
+        }
+        finished
+    }
+
+    /// Continues from the given state version, after all transactions
+    /// in flight finished.
+    fn seek(&mut self, state_version: u64) {
+        self.finished = state_version.checked_sub(1);
+        self.stored = None;
+    }
+
+    fn value(&self) -> Option<u64> {
+        self.finished
+    }
+}
+
+#[cfg(all(test, feature = "channel"))]
+mod tests {
+    use super::*;
+    use crate::{
+        event_handler::{EventHandler, EventHandlerContext},
+        models::EventEmitter,
+        sources::channel::ChannelTransactionStream,
+    };
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    fn transaction(state_version: u64, emitters: &[&str]) -> Transaction {
+        Transaction {
+            intent_hash: format!("txid_{}", state_version),
+            state_version,
+            confirmed_at: None,
+            events: emitters
+                .iter()
+                .map(|emitter| Event {
+                    name: "Event".to_string(),
+                    binary_sbor_data: Vec::new(),
+                    emitter: EventEmitter::Function {
+                        package_address: emitter.to_string(),
+                        blueprint_name: "Blueprint".to_string(),
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    fn dispatched(state_version: u64) -> Arc<Dispatched> {
+        Arc::new(Dispatched {
+            transaction: transaction(state_version, &[]),
+            reported: AtomicBool::new(false),
+        })
+    }
+
+    fn state_versions(finished: Vec<Arc<Dispatched>>) -> Vec<u64> {
+        finished
+            .iter()
+            .map(|dispatched| dispatched.transaction.state_version)
+            .collect()
+    }
+
+    #[derive(Clone)]
+    struct Handler;
+
+    #[async_trait]
+    impl EventHandler<(), ()> for Handler {
+        async fn handle(
+            &self,
+            _input: EventHandlerContext<'_, (), ()>,
+            _event: &[u8],
+        ) -> Result<(), EventHandlerError> {
+            Ok(())
+        }
+    }
+
+    /// Records the transactions which are finished, and whether they were handled.
+    #[derive(Default)]
+    struct RecordingLogger {
+        received: Mutex<Vec<u64>>,
+        finished: Mutex<Vec<(u64, bool)>>,
+    }
+
+    #[async_trait]
+    impl Logger for RecordingLogger {
+        async fn receive_transaction(
+            &self,
+            transaction: &Transaction,
+            _handling: bool,
+            is_retry: bool,
+        ) {
+            if !is_retry {
+                self.received
+                    .lock()
+                    .unwrap()
+                    .push(transaction.state_version);
+            }
+        }
+
+        async fn finish_transaction(
+            &self,
+            transaction: &Transaction,
+            handling: bool,
+        ) {
+            self.finished
+                .lock()
+                .unwrap()
+                .push((transaction.state_version, handling));
+        }
+
+        async fn receive_event(
+            &self,
+            _transaction: &Transaction,
+            _event: &Event,
+            _handling: bool,
+            _is_retry: bool,
+        ) {
+        }
+
+        async fn finish_event(
+            &self,
+            _transaction: &Transaction,
+            _event: &Event,
+            _handling: bool,
+        ) {
+        }
+
+        async fn event_retry_error(
+            &self,
+            _transaction: &Transaction,
+            _event: &Event,
+            _error: &anyhow::Error,
+            _timeout: Duration,
+            _attempt: u32,
+        ) {
+        }
+
+        async fn transaction_retry_error(
+            &self,
+            _transaction: &Transaction,
+            _error: &anyhow::Error,
+            _timeout: Duration,
+            _attempt: u32,
+        ) {
+        }
+
+        async fn unrecoverable_error(&self, _error: &anyhow::Error) {}
+
+        async fn periodic_report(&self) {}
+
+        fn periodic_report_interval(&self) -> Duration {
+            Duration::from_secs(3600)
+        }
+    }
+
+    #[test]
+    fn watermark_only_moves_past_fully_finished_transactions() {
+        let mut watermark = Watermark::new(None);
+        for state_version in 1..=3 {
+            watermark.dispatch(dispatched(state_version), 2);
+        }
+        // One shard finishes everything, the other only the later ones.
+        for state_version in 1..=3 {
+            assert!(watermark.complete(state_version).is_empty());
+        }
+        assert!(watermark.complete(3).is_empty());
+        assert!(watermark.complete(2).is_empty());
+        assert_eq!(watermark.value(), None);
+        assert_eq!(state_versions(watermark.complete(1)), [1, 2, 3]);
+        assert_eq!(watermark.value(), Some(3));
+    }
+
+    #[test]
+    fn watermark_stops_at_the_oldest_transaction_in_flight() {
+        let mut watermark = Watermark::new(Some(10));
+        for state_version in 11..=13 {
+            watermark.dispatch(dispatched(state_version), 1);
+        }
+        assert_eq!(state_versions(watermark.complete(11)), [11]);
+        assert!(watermark.complete(13).is_empty());
+        assert_eq!(watermark.value(), Some(11));
+        assert_eq!(state_versions(watermark.complete(12)), [12, 13]);
+        assert_eq!(watermark.value(), Some(13));
+    }
+
+    #[tokio::test]
+    async fn each_transaction_is_reported_once() {
+        let (stream, sender) = ChannelTransactionStream::new(10);
+        let emitters = ["a", "b", "c", "d"];
+        let logger = Arc::new(RecordingLogger::default());
+        let mut processor =
+            PartitionedTransactionStreamProcessor::new(stream, 4, |_| {
+                let mut handler_registry = HandlerRegistry::new();
+                for emitter in ["a", "b", "c"] {
+                    handler_registry.add_handler(emitter, "Event", Handler);
+                }
+                (handler_registry, ())
+            })
+            .shared_logger(logger.clone())
+            .until_state_version(4);
+        sender.send(transaction(1, &emitters)).await.unwrap();
+        sender.send(transaction(2, &["d"])).await.unwrap();
+        sender.send(transaction(3, &[])).await.unwrap();
+        sender.send(transaction(4, &["a", "c"])).await.unwrap();
+
+        let stop_reason = processor.run().await.unwrap();
+
+        assert_eq!(stop_reason, StopReason::BoundReached);
+        let mut received = logger.received.lock().unwrap().clone();
+        received.sort();
+        assert_eq!(received, [1, 2, 3, 4]);
+        let mut finished = logger.finished.lock().unwrap().clone();
+        finished.sort();
+        assert_eq!(finished, [(1, true), (2, false), (3, false), (4, true)]);
+    }
+
+    #[tokio::test]
+    async fn seek_is_ignored_when_the_stream_cant_restart() {
+        let (stream, sender) = ChannelTransactionStream::new(10);
+        let mut processor =
+            PartitionedTransactionStreamProcessor::new(stream, 2, |_| {
+                (HandlerRegistry::new(), ())
+            })
+            .disable_logging()
+            .until_state_version(4);
+        let handle = processor.handle();
+        let control = async {
+            sender.send(transaction(1, &["a"])).await.unwrap();
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while handle.status().last_state_version != Some(1) {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .unwrap();
+            handle.seek(1);
+            for state_version in 2..=4 {
+                sender
+                    .send(transaction(state_version, &["a"]))
+                    .await
+                    .unwrap();
+            }
+        };
+
+        let (result, ()) = tokio::join!(processor.run(), control);
+        assert_eq!(result.unwrap(), StopReason::BoundReached);
+    }
+}
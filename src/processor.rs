@@ -6,37 +6,117 @@ a default implementation of a [`TransactionHandler`], and a struct that processe
 */
 
 use crate::{
+    checkpoints::CheckpointStore,
+    dead_letter::{DeadLetter, DeadLetterSink},
     error::{
-        EventHandlerError, TransactionHandlerError, TransactionProcessorError,
+        EventHandlerError, HandlerPanic, PanicAction, TransactionHandlerError,
+        TransactionProcessorError,
+    },
+    event_handler::{
+        EventHandler, EventHandlerContext, HandlerRegistry, State,
+    },
+    handle::{Command, Controls, ProcessorHandle},
+    logger::{combine_loggers, DefaultLogger, Logger},
+    models::{Event, Transaction},
+    registrations::RegistrationStore,
+    retry::{HandlerTimeout, RetryExhaustedAction, RetryPolicy},
+    stream::{StreamBounds, StreamEnd, TransactionStream},
+    transaction_handler::{
+        BatchTransactionHandler, BatchTransactionHandlerContext, Savepoints,
+        TransactionHandler, TransactionHandlerContext,
     },
-    event_handler::{EventHandlerContext, HandlerRegistry, State},
-    logger::{DefaultLogger, Logger},
-    models::{EventEmitter, Transaction},
-    native_events::NativeEventType,
-    stream::TransactionStream,
-    transaction_handler::{TransactionHandler, TransactionHandlerContext},
 };
+use anyhow::anyhow;
 use async_trait::async_trait;
-use core::panic;
-use std::{sync::Arc, time::Duration};
-use tokio::sync::RwLock;
+use chrono::Utc;
+use std::{
+    any::Any,
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::{pin, Pin},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::Poll,
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    mpsc::{error::TryRecvError, Receiver},
+    watch,
+};
+
+/// The reason why [`TransactionStreamProcessor::run`] stopped
+/// processing transactions without an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The stream closed its channel, because there are
+    /// no more transactions to process.
+    StreamFinished,
+    /// The shutdown signal completed. The transaction that was
+    /// being processed at that moment was finished first.
+    Shutdown,
+    /// The last transaction within the bounds set with
+    /// [`TransactionStreamProcessor::until_state_version`] or
+    /// [`TransactionStreamProcessor::until_confirmed_at`] was processed,
+    /// or the stream got to the end of its own bounds.
+    BoundReached,
+    /// [`ProcessorHandle::stop`] was called. The transaction that was
+    /// being processed at that moment was finished first.
+    Stopped,
+}
+
+/// A future which completes when the processor should shut down.
+pub(crate) type ShutdownSignal = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Transactions confirmed more recently than this are considered
+/// to be at the ledger tip, by default. See
+/// [`TransactionStreamProcessor::caught_up_threshold`].
+const DEFAULT_CAUGHT_UP_THRESHOLD: Duration = Duration::from_secs(60);
+
+/// How often the checkpoint is stored while only transactions without
+/// handlers are finished, by default. See
+/// [`TransactionProcessor::checkpoint_interval`].
+pub(crate) const DEFAULT_CHECKPOINT_INTERVAL: Duration =
+    Duration::from_secs(10);
+
+/// How a transaction was finished, which decides when its checkpoint is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Finished {
+    /// None of its events had a handler.
+    Unhandled,
+    /// Its events were handled, or it was dead-lettered or skipped.
+    Handled,
+    /// The transaction handler stored the checkpoint itself.
+    CheckpointStored,
+}
 
 /// The main struct that processes transactions from a [`TransactionStream`].
 /// It processes transactions by having an instance of [`TransactionProcessor`], and passing transactions to it.
 /// It can be created using a builder pattern, where you can set the [`TransactionHandler`],
-/// retry intervals, and logger. It handles the lifecycle of an asynchronous periodic logging task.
+/// retry policies, and logger. It handles the lifecycle of an asynchronous periodic logging task.
 ///
 /// If you don't set a transaction handler explicitly, the processor will use a default handler
 /// that simply calls [`EventProcessor::process_events`] on the transaction, without any custom logic.
+///
+/// `TRANSACTION_CONTEXT` is the transaction context of the handlers in the [`HandlerRegistry`].
+/// Unless it is the unit type, the processor needs a transaction handler which creates it,
+/// so it is created with [`TransactionStreamProcessor::with_transaction_handler`].
 #[allow(non_camel_case_types)]
-pub struct TransactionStreamProcessor<STREAM, STATE>
+pub struct TransactionStreamProcessor<STREAM, STATE, TRANSACTION_CONTEXT = ()>
 where
     STREAM: TransactionStream,
     STATE: State,
+    TRANSACTION_CONTEXT: 'static,
 {
-    transaction_processor: TransactionProcessor<STATE>,
+    transaction_processor: TransactionProcessor<STATE, TRANSACTION_CONTEXT>,
     transaction_stream: STREAM,
     periodic_logging_joinhandle: Option<tokio::task::JoinHandle<()>>,
+    shutdown_signal: Option<ShutdownSignal>,
+    max_batch_size: usize,
+    batch_window: Duration,
+    caught_up_threshold: Duration,
+    bounds: StreamBounds,
 }
 
 #[allow(non_camel_case_types)]
@@ -52,32 +132,59 @@ where
     /// simply calls [`EventProcessor::process_events`] on the transaction, without
     /// any custom logic.
     ///
-    /// - The default retry intervals for transactions and events are
-    /// set to 10 seconds.
+    /// - The default retry policies for transactions and events
+    /// retry forever, with a fixed delay of 10 seconds.
     ///
     /// - The logger is set to a default logger that logs to stdout.
     ///
-    /// Change the default handler, retry intervals, or logger using
+    /// Change the default handler, retry policies, or logger using
     /// the builder methods.
     pub fn new(
         transaction_stream: STREAM,
-        handler_registry: HandlerRegistry,
+        handler_registry: HandlerRegistry<STATE>,
         state: STATE,
     ) -> Self {
-        let transaction_processor = TransactionProcessor {
-            logger: Some(Arc::new(
-                RwLock::new(Box::<DefaultLogger>::default()),
-            )),
-            transaction_handler: Box::new(DefaultTransactionHandler),
-            transaction_retry_delay: Duration::from_secs(10),
-            event_retry_delay: Duration::from_secs(10),
-            handler_registry: handler_registry,
-            state: state,
-        };
+        Self::with_transaction_handler(
+            transaction_stream,
+            handler_registry,
+            state,
+            DefaultTransactionHandler,
+        )
+    }
+}
+
+#[allow(non_camel_case_types)]
+impl<STREAM, STATE, TRANSACTION_CONTEXT>
+    TransactionStreamProcessor<STREAM, STATE, TRANSACTION_CONTEXT>
+where
+    STREAM: TransactionStream,
+    STATE: State,
+    TRANSACTION_CONTEXT: 'static,
+{
+    /// Creates a new [`TransactionStreamProcessor`] with the given
+    /// [`TransactionHandler`], which creates the transaction context
+    /// for the handlers in the [`HandlerRegistry`].
+    /// The other defaults are the same as for [`TransactionStreamProcessor::new`].
+    pub fn with_transaction_handler(
+        transaction_stream: STREAM,
+        handler_registry: HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+        state: STATE,
+        transaction_handler: impl TransactionHandler<STATE, TRANSACTION_CONTEXT>,
+    ) -> Self {
         Self {
             transaction_stream,
-            transaction_processor,
+            transaction_processor:
+                TransactionProcessor::with_transaction_handler(
+                    handler_registry,
+                    state,
+                    transaction_handler,
+                ),
             periodic_logging_joinhandle: None,
+            shutdown_signal: None,
+            max_batch_size: 1000,
+            batch_window: Duration::from_secs(1),
+            caught_up_threshold: DEFAULT_CAUGHT_UP_THRESHOLD,
+            bounds: StreamBounds::default(),
         }
     }
 
@@ -86,7 +193,7 @@ where
     /// has event handlers registered.
     pub fn transaction_handler(
         self,
-        transaction_handler: impl TransactionHandler<STATE>,
+        transaction_handler: impl TransactionHandler<STATE, TRANSACTION_CONTEXT>,
     ) -> Self {
         Self {
             transaction_processor: self
@@ -96,6 +203,58 @@ where
         }
     }
 
+    /// Sets a [`BatchTransactionHandler`] for the processor, which replaces the
+    /// [`TransactionHandler`]. It is called with batches of consecutive transactions.
+    ///
+    /// The batch size adapts to how far behind the processor is. Transactions
+    /// which are already waiting in the stream are always added to the batch.
+    /// While the processor is behind the ledger tip, it also waits up to the
+    /// batch window for more transactions. Once it has caught up, it no longer
+    /// waits, so batches typically hold a single transaction.
+    pub fn batch_transaction_handler(
+        self,
+        batch_transaction_handler: impl BatchTransactionHandler<
+            STATE,
+            TRANSACTION_CONTEXT,
+        >,
+    ) -> Self {
+        Self {
+            transaction_processor: self
+                .transaction_processor
+                .batch_transaction_handler(batch_transaction_handler),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of transactions in a batch. Defaults to 1000.
+    /// Only used with a [`BatchTransactionHandler`].
+    pub fn max_batch_size(self, max_batch_size: usize) -> Self {
+        Self {
+            max_batch_size: max_batch_size.max(1),
+            ..self
+        }
+    }
+
+    /// Sets how long the processor waits for more transactions to fill a batch
+    /// while it is behind the ledger tip. Defaults to 1 second.
+    /// Only used with a [`BatchTransactionHandler`].
+    pub fn batch_window(self, batch_window: Duration) -> Self {
+        Self {
+            batch_window,
+            ..self
+        }
+    }
+
+    /// Sets how recently a transaction must have been confirmed for the processor
+    /// to consider itself caught up with the ledger tip. Defaults to 60 seconds.
+    /// Only used with a [`BatchTransactionHandler`].
+    pub fn caught_up_threshold(self, caught_up_threshold: Duration) -> Self {
+        Self {
+            caught_up_threshold,
+            ..self
+        }
+    }
+
     /// Sets the retry delay for transactions that fail to process and return a `TransactionRetryError`
     /// (see [`crate::error::TransactionHandlerError`]).
     pub fn transaction_retry_delay(
@@ -121,6 +280,80 @@ where
         }
     }
 
+    /// Sets the [`RetryPolicy`] for transactions that fail to process and return
+    /// a `TransactionRetryError`. This replaces the fixed retry delay.
+    pub fn transaction_retry_policy(
+        self,
+        transaction_retry_policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            transaction_processor: self
+                .transaction_processor
+                .transaction_retry_policy(transaction_retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the [`RetryPolicy`] for events that fail to process and return
+    /// an `EventRetryError`. This replaces the fixed retry delay.
+    pub fn event_retry_policy(self, event_retry_policy: RetryPolicy) -> Self {
+        Self {
+            transaction_processor: self
+                .transaction_processor
+                .event_retry_policy(event_retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets a timeout for each attempt of an event handler. A handler which
+    /// takes longer is cancelled, reported with [`Logger::handler_timeout`],
+    /// and fails with the given error, like `EventHandlerError::EventRetryError`
+    /// to retry it according to the event retry policy.
+    /// A handler with its own [`EventHandler::timeout`] uses that one instead.
+    pub fn event_handler_timeout(
+        self,
+        timeout: Duration,
+        error: fn(anyhow::Error) -> EventHandlerError,
+    ) -> Self {
+        Self {
+            transaction_processor: self
+                .transaction_processor
+                .event_handler_timeout(timeout, error),
+            ..self
+        }
+    }
+
+    /// Sets a timeout for each attempt of the transaction handler, or of the
+    /// batch transaction handler, including the retries of its events.
+    /// A handler which takes longer is cancelled, which rolls back a database
+    /// transaction it started, reported with [`Logger::handler_timeout`], and fails
+    /// with the given error, like `TransactionHandlerError::TransactionRetryError`
+    /// to retry it according to the transaction retry policy.
+    pub fn transaction_timeout(
+        self,
+        timeout: Duration,
+        error: fn(anyhow::Error) -> TransactionHandlerError,
+    ) -> Self {
+        Self {
+            transaction_processor: self
+                .transaction_processor
+                .transaction_timeout(timeout, error),
+            ..self
+        }
+    }
+
+    /// Sets what happens when an event handler or transaction handler panics.
+    /// The panic is caught and turned into an error carrying a [`HandlerPanic`],
+    /// which is an `UnrecoverableError` by default.
+    pub fn on_handler_panic(self, panic_action: PanicAction) -> Self {
+        Self {
+            transaction_processor: self
+                .transaction_processor
+                .on_handler_panic(panic_action),
+            ..self
+        }
+    }
+
     /// Sets the logger for the processor. It should implement the [`Logger`] trait.
     pub fn logger(self, logger: impl Logger + 'static) -> Self {
         Self {
@@ -129,6 +362,19 @@ where
         }
     }
 
+    /// Adds a logger next to the current one, for example a
+    /// [`PrometheusLogger`][crate::logger::prometheus::PrometheusLogger]
+    /// next to the default logger, so that all of them get the hooks.
+    /// See [`CompositeLogger`][crate::logger::CompositeLogger].
+    pub fn add_logger(self, logger: impl Logger + 'static) -> Self {
+        Self {
+            transaction_processor: self
+                .transaction_processor
+                .add_logger(logger),
+            ..self
+        }
+    }
+
     /// Sets the logger for the processor to the default logger, but with
     /// a custom report interval given by `interval`.
     pub fn default_logger_with_report_interval(
@@ -151,47 +397,498 @@ where
         }
     }
 
+    /// Sets a [`CheckpointStore`] for the processor. The processor stores
+    /// the state version of each finished transaction in it, and resumes
+    /// from the stored checkpoint when it is started again.
+    /// The state version the [`TransactionStream`] was configured with
+    /// is only used when no checkpoint was stored yet.
+    pub fn checkpoint_store(
+        self,
+        checkpoint_store: impl CheckpointStore + 'static,
+    ) -> Self {
+        Self {
+            transaction_processor: self
+                .transaction_processor
+                .checkpoint_store(checkpoint_store),
+            ..self
+        }
+    }
+
+    /// Sets how often the checkpoint is stored while only transactions
+    /// without handlers are finished. Defaults to 10 seconds.
+    /// See [`TransactionProcessor::checkpoint_interval`].
+    pub fn checkpoint_interval(self, checkpoint_interval: Duration) -> Self {
+        Self {
+            transaction_processor: self
+                .transaction_processor
+                .checkpoint_interval(checkpoint_interval),
+            ..self
+        }
+    }
+
+    /// Sets a [`RegistrationStore`] for the processor. The processor stores
+    /// the handlers registered with [`HandlerRegistry::register_handler`]
+    /// in it, and adds them to the registry again when it is started.
+    pub fn registration_store(
+        self,
+        registration_store: impl RegistrationStore + 'static,
+    ) -> Self {
+        Self {
+            transaction_processor: self
+                .transaction_processor
+                .registration_store(registration_store),
+            ..self
+        }
+    }
+
+    /// Sets a [`DeadLetterSink`], which enables dead-letter mode.
+    /// Events and transactions which would otherwise stop the processor,
+    /// because a handler returned an `UnrecoverableError` or because they
+    /// ran out of retry attempts, are sent to the sink and processing continues.
+    pub fn dead_letter_sink(
+        self,
+        dead_letter_sink: impl DeadLetterSink + 'static,
+    ) -> Self {
+        Self {
+            transaction_processor: self
+                .transaction_processor
+                .dead_letter_sink(dead_letter_sink),
+            ..self
+        }
+    }
+
+    /// Makes the processor stop after the transaction with this state version,
+    /// which is inclusive. [`TransactionStreamProcessor::run`] then stops the stream
+    /// and returns [`StopReason::BoundReached`]. Together with a start state version
+    /// on the stream, this processes a fixed range of transactions.
+    pub fn until_state_version(mut self, until_state_version: u64) -> Self {
+        self.bounds.until_state_version = Some(until_state_version);
+        self
+    }
+
+    /// Makes the processor stop at the first transaction which was confirmed
+    /// after this time, without processing it.
+    /// [`TransactionStreamProcessor::run`] then stops the stream and
+    /// returns [`StopReason::BoundReached`].
+    pub fn until_confirmed_at(
+        mut self,
+        until_confirmed_at: chrono::DateTime<Utc>,
+    ) -> Self {
+        self.bounds.until_confirmed_at = Some(until_confirmed_at);
+        self
+    }
+
+    /// Sets a future which makes the processor shut down gracefully
+    /// when it completes, like a cancellation token.
+    /// The processor finishes the transaction it is processing at that moment,
+    /// including any retries, and then stops the [`TransactionStream`],
+    /// flushes the logger and returns [`StopReason::Shutdown`].
+    pub fn shutdown_signal(
+        self,
+        shutdown_signal: impl Future<Output = ()> + Send + 'static,
+    ) -> Self {
+        Self {
+            shutdown_signal: Some(Box::pin(shutdown_signal)),
+            ..self
+        }
+    }
+
+    /// Makes the processor shut down gracefully when the process
+    /// receives SIGINT (Ctrl+C) or SIGTERM.
+    /// See [`TransactionStreamProcessor::shutdown_signal`].
+    pub fn graceful_shutdown(self) -> Self {
+        self.shutdown_signal(termination_signal())
+    }
+
+    /// Returns a [`ProcessorHandle`], which can pause, resume, stop
+    /// and seek the processor while it runs, and report its status.
+    pub fn handle(&self) -> ProcessorHandle {
+        self.transaction_processor.handle.clone()
+    }
+
     /// Starts processing transactions from the [`TransactionStream`].
-    pub async fn run(&mut self) -> Result<(), TransactionProcessorError> {
+    ///
+    /// Returns the reason why the processor stopped. Whatever the outcome,
+    /// the checkpoint of the last finished transaction is stored,
+    /// the stream is stopped, the periodic logging task is aborted and
+    /// the logger is flushed before returning. If the stream closed its
+    /// channel because it failed, its error is returned instead of
+    /// [`StopReason::StreamFinished`]. If it closed it because it got to the
+    /// end of its own bounds, [`StopReason::BoundReached`] is returned.
+    pub async fn run(
+        &mut self,
+    ) -> Result<StopReason, TransactionProcessorError> {
+        self.transaction_processor.handle.set_running(true);
+        let mut result = self.process_stream().await;
+        // The stream may have closed its channel because it got
+        // to the end of its own bounds, or because it failed.
+        if let Ok(StopReason::StreamFinished) = result {
+            result = match self.transaction_stream.end().await {
+                Ok(StreamEnd::Finished) => Ok(StopReason::StreamFinished),
+                Ok(StreamEnd::BoundReached) => Ok(StopReason::BoundReached),
+                Err(error) => {
+                    if let Some(logger) = &self.transaction_processor.logger {
+                        logger.unrecoverable_error(&error).await;
+                    }
+                    Err(TransactionProcessorError::UnrecoverableError(error))
+                }
+            };
+        }
+        let flushed = self.transaction_processor.flush_checkpoint().await;
+        let result =
+            result.and_then(|stop_reason| flushed.map(|()| stop_reason));
+        self.transaction_processor.handle.set_running(false);
+        self.transaction_stream.stop().await;
+        if let Some(handle) = self.periodic_logging_joinhandle.take() {
+            handle.abort();
+        }
+        if let Some(logger) = &self.transaction_processor.logger {
+            logger.flush().await;
+        }
+        result
+    }
+
+    async fn process_stream(
+        &mut self,
+    ) -> Result<StopReason, TransactionProcessorError> {
         // Start the transaction stream and get a receiver.
         // This often involves starting a task that fetches transactions
         // from a remote source and sends them to the receiver.
-        let mut receiver =
-            self.transaction_stream.start().await.map_err(|error| {
-                TransactionProcessorError::UnrecoverableError(error)
-            })?;
-        let logger = self.transaction_processor.logger.clone();
-        self.periodic_logging_joinhandle = if let Some(logger) = logger {
-            let interval = logger.read().await.periodic_report_interval();
-            Some(tokio::spawn(async move {
-                loop {
-                    tokio::time::sleep(interval).await;
-                    logger.read().await.periodic_report().await;
-                }
-            }))
-        } else {
-            None
-        };
+        // If a checkpoint was stored, continue after it,
+        // with the handlers that were registered before it.
+        self.transaction_processor.load_registrations().await?;
+        let checkpoint = self.transaction_processor.load_checkpoint().await?;
+        let mut receiver = match checkpoint {
+            Some(state_version) => {
+                self.transaction_stream.start_from(state_version + 1).await
+            }
+            None => self.transaction_stream.start().await,
+        }
+        .map_err(TransactionProcessorError::UnrecoverableError)?;
+        self.periodic_logging_joinhandle =
+            spawn_periodic_logging(self.transaction_processor.logger.clone())
+                .await;
         // Process transactions as they arrive.
-        while let Some(transaction) = receiver.recv().await {
+        let mut shutdown_signal = self.shutdown_signal.take();
+        let mut controls = self.transaction_processor.handle.watch();
+        let handle = self.transaction_processor.handle.clone();
+        loop {
+            handle.set_waiting(false);
+            if let Some(stop_reason) = self
+                .apply_controls(
+                    &mut controls,
+                    &mut receiver,
+                    &mut shutdown_signal,
+                )
+                .await?
+            {
+                return Ok(stop_reason);
+            }
+            // Go back to the controls when they change while waiting,
+            // so that the handle also works when no transactions arrive.
+            handle.set_waiting(receiver.is_empty());
+            let transaction = tokio::select! {
+                biased;
+                transaction = next_transaction(&mut receiver, &mut shutdown_signal) => transaction,
+                _ = controls.changed() => continue,
+            };
+            handle.set_waiting(false);
+            let Some(transaction) = transaction else {
+                return Ok(StopReason::Shutdown);
+            };
             self.transaction_processor
-                .process_transaction(&transaction)
-                .await?;
+                .handle
+                .set_queue(receiver.len(), receiver.max_capacity());
+            // If the transmitting half of the channel is dropped,
+            // the receiver will return None and we will exit the loop.
+            // The processor will exit gracefully.
+            let Some(transaction) = transaction else {
+                return Ok(StopReason::StreamFinished);
+            };
+            if self.bounds.is_past(&transaction) {
+                return Ok(StopReason::BoundReached);
+            }
+            if self
+                .transaction_processor
+                .batch_transaction_handler
+                .is_none()
+            {
+                self.transaction_processor
+                    .process_transaction(&transaction)
+                    .await?;
+                if self.bounds.is_last(&transaction) {
+                    return Ok(StopReason::BoundReached);
+                }
+                continue;
+            }
+            let mut batch = vec![transaction];
+            let mut stop_reason = self
+                .fill_batch(&mut receiver, &mut shutdown_signal, &mut batch)
+                .await;
+            // Leave out the transactions after the bounds.
+            if let Some(index) = batch
+                .iter()
+                .position(|transaction| self.bounds.is_past(transaction))
+            {
+                batch.truncate(index);
+                stop_reason = Some(StopReason::BoundReached);
+            }
+            if let Some(index) = batch
+                .iter()
+                .position(|transaction| self.bounds.is_last(transaction))
+            {
+                batch.truncate(index + 1);
+                stop_reason = Some(StopReason::BoundReached);
+            }
+            self.transaction_processor.process_batch(&batch).await?;
+            if let Some(stop_reason) = stop_reason {
+                return Ok(stop_reason);
+            }
         }
-        // If the transmitting half of the channel is dropped,
-        // the receiver will return None and we will exit the loop.
-        // The processor will exit gracefully.
-        if let Some(handle) = self.periodic_logging_joinhandle.take() {
-            handle.abort();
+    }
+
+    /// Acts on the requests of the [`ProcessorHandle`]: waits while the
+    /// processor is paused, and restarts the stream for a seek.
+    /// Returns a [`StopReason`] if the processor should stop.
+    async fn apply_controls(
+        &mut self,
+        controls: &mut watch::Receiver<Controls>,
+        receiver: &mut Receiver<Transaction>,
+        shutdown_signal: &mut Option<ShutdownSignal>,
+    ) -> Result<Option<StopReason>, TransactionProcessorError> {
+        loop {
+            match self.transaction_processor.handle.take_command() {
+                Some(Command::Stop) => return Ok(Some(StopReason::Stopped)),
+                Some(Command::Seek(state_version)) => {
+                    self.seek(receiver, state_version).await?;
+                    continue;
+                }
+                None => {}
+            }
+            let paused = controls.borrow_and_update().paused;
+            if !paused {
+                return Ok(None);
+            }
+            // The handle holds the sender, so it is never closed.
+            match shutdown_signal {
+                Some(shutdown_signal) => tokio::select! {
+                    biased;
+                    _ = shutdown_signal.as_mut() => return Ok(Some(StopReason::Shutdown)),
+                    _ = controls.changed() => {},
+                },
+                None => {
+                    let _ = controls.changed().await;
+                }
+            }
+        }
+    }
+
+    /// Restarts the stream from the given state version. Transactions from there on
+    /// are no longer skipped as finished, so they are processed again.
+    /// If the stream can't be restarted, the seek is ignored.
+    async fn seek(
+        &mut self,
+        receiver: &mut Receiver<Transaction>,
+        state_version: u64,
+    ) -> Result<(), TransactionProcessorError> {
+        if !self.transaction_stream.can_restart() {
+            log::warn!(
+                "Ignoring the seek to state version {}, because the stream can't be restarted",
+                state_version
+            );
+            return Ok(());
         }
+        self.transaction_stream.stop().await;
+        *receiver = self
+            .transaction_stream
+            .start_from(state_version)
+            .await
+            .map_err(TransactionProcessorError::UnrecoverableError)?;
+        // The checkpoint which wasn't stored yet is from before the seek,
+        // and is replaced by the new position.
+        let checkpoint = state_version.checked_sub(1);
+        self.transaction_processor.checkpoint = checkpoint;
+        self.transaction_processor.unsaved_checkpoint = checkpoint;
         Ok(())
     }
+
+    /// Adds transactions which are waiting in the receiver to the batch,
+    /// up to the maximum batch size. While the last transaction is behind
+    /// the ledger tip, it also waits for more until the batch window ends.
+    /// Returns a [`StopReason`] if the processor should stop after this batch.
+    async fn fill_batch(
+        &self,
+        receiver: &mut Receiver<Transaction>,
+        shutdown_signal: &mut Option<ShutdownSignal>,
+        batch: &mut Vec<Transaction>,
+    ) -> Option<StopReason> {
+        let deadline = tokio::time::Instant::now() + self.batch_window;
+        while batch.len() < self.max_batch_size {
+            match receiver.try_recv() {
+                Ok(transaction) => {
+                    batch.push(transaction);
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    return Some(StopReason::StreamFinished)
+                }
+                Err(TryRecvError::Empty) => {}
+            }
+            // Without a timestamp, there is no telling how far behind
+            // the processor is, so it doesn't wait for more.
+            let caught_up = batch
+                .last()
+                .and_then(|transaction| transaction.confirmed_at)
+                .map_or(true, |confirmed_at| {
+                    (Utc::now() - confirmed_at)
+                        .to_std()
+                        .map_or(true, |behind| {
+                            behind < self.caught_up_threshold
+                        })
+                });
+            if caught_up {
+                break;
+            }
+            match tokio::time::timeout_at(
+                deadline,
+                next_transaction(receiver, shutdown_signal),
+            )
+            .await
+            {
+                Ok(Some(Some(transaction))) => batch.push(transaction),
+                Ok(Some(None)) => return Some(StopReason::StreamFinished),
+                Ok(None) => return Some(StopReason::Shutdown),
+                Err(_) => break,
+            }
+        }
+        None
+    }
+}
+
+/// Waits for the next transaction from the receiver.
+/// Returns `None` if the shutdown signal completes first, which is
+/// checked before the receiver so that a shutdown is never delayed
+/// by a full channel.
+pub(crate) async fn next_transaction(
+    receiver: &mut Receiver<Transaction>,
+    shutdown_signal: &mut Option<ShutdownSignal>,
+) -> Option<Option<Transaction>> {
+    match shutdown_signal {
+        Some(shutdown_signal) => tokio::select! {
+            biased;
+            _ = shutdown_signal.as_mut() => None,
+            transaction = receiver.recv() => Some(transaction),
+        },
+        None => Some(receiver.recv().await),
+    }
+}
+
+/// Spawns a task which calls the periodic report of the logger,
+/// if there is one, at the interval the logger asks for.
+pub(crate) async fn spawn_periodic_logging(
+    logger: Option<Arc<dyn Logger>>,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let logger = logger?;
+    let interval = logger.periodic_report_interval();
+    Some(tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            logger.periodic_report().await;
+        }
+    }))
+}
+
+/// Completes when the process receives SIGINT (Ctrl+C) or SIGTERM.
+pub(crate) async fn termination_signal() {
+    // If a signal handler can't be installed, we never complete
+    // instead of shutting down right away.
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(
+            tokio::signal::unix::SignalKind::terminate(),
+        ) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Runs a handler inside the logger's current span, so that spans and events
+/// from handler code nest under the transaction or event being processed.
+async fn in_logger_span<F: Future>(
+    logger: &Option<Arc<dyn Logger>>,
+    handler: F,
+) -> F::Output {
+    #[cfg(feature = "tracing")]
+    if let Some(logger) = logger {
+        let span = logger.current_span();
+        return tracing::Instrument::instrument(handler, span).await;
+    }
+    #[cfg(not(feature = "tracing"))]
+    let _ = logger;
+    handler.await
+}
+
+/// Runs a handler with a timeout, if one is set. Returns the timeout
+/// as the error if the handler took longer and was cancelled.
+async fn within<ERROR, F: Future>(
+    timeout: Option<HandlerTimeout<ERROR>>,
+    handler: F,
+) -> Result<F::Output, HandlerTimeout<ERROR>> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout.timeout, handler)
+            .await
+            .map_err(|_| timeout),
+        None => Ok(handler.await),
+    }
+}
+
+/// The payload of a caught panic.
+type PanicPayload = Box<dyn Any + Send>;
+
+/// Runs a handler, catching a panic in it instead of unwinding
+/// through the processor.
+async fn catch_panic<F: Future>(handler: F) -> Result<F::Output, PanicPayload> {
+    let mut handler = pin!(handler);
+    std::future::poll_fn(|cx| {
+        std::panic::catch_unwind(AssertUnwindSafe(|| handler.as_mut().poll(cx)))
+            .map_or_else(
+                |payload| Poll::Ready(Err(payload)),
+                |poll| poll.map(Ok),
+            )
+    })
+    .await
+}
+
+/// Gets the message a handler panicked with, which is
+/// a string unless it panicked with another value.
+fn panic_message(payload: PanicPayload) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "panicked with a value which is not a string".to_string(),
+        },
+    }
 }
 
 /// A default transaction handler that simply calls [`EventProcessor::process_events`]
 /// on the transaction, without any custom logic.
 #[derive(Clone)]
-struct DefaultTransactionHandler;
+pub(crate) struct DefaultTransactionHandler;
 
 #[async_trait]
 impl<STATE> TransactionHandler<STATE> for DefaultTransactionHandler
@@ -213,34 +910,83 @@ where
 /// [`TransactionProcessor`] encapsulates the logic for processing transactions, which includes calling
 /// the [`TransactionHandler`] for each transaction, calling logging hooks, and handling retries.
 /// It can be created using a builder pattern, where you can set the [`TransactionHandler`],
-/// retry intervals, and logger. As opposed to the [`TransactionStreamProcessor`], it does not
+/// retry policies, and logger. As opposed to the [`TransactionStreamProcessor`], it does not
 /// have a transaction stream, and you have to pass transactions to it manually.
 /// It is useful when you want to process transactions from a source other than a [`TransactionStream`],
 /// like when testing using mocked transactions. [`TransactionStreamProcessor`] uses this struct internally.
 ///
 /// All the logging hooks work when using this struct, except for the periodic report, which is left out.
 #[allow(non_camel_case_types)]
-pub struct TransactionProcessor<STATE: State> {
-    pub logger: Option<Arc<RwLock<Box<dyn Logger>>>>,
-    pub handler_registry: HandlerRegistry,
-    pub transaction_handler: Box<dyn TransactionHandler<STATE>>,
+pub struct TransactionProcessor<STATE: State, TRANSACTION_CONTEXT: 'static = ()>
+{
+    pub logger: Option<Arc<dyn Logger>>,
+    pub handler_registry: HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+    pub transaction_handler:
+        Box<dyn TransactionHandler<STATE, TRANSACTION_CONTEXT>>,
     pub state: STATE,
-    pub transaction_retry_delay: Duration,
-    pub event_retry_delay: Duration,
+    pub transaction_retry_policy: RetryPolicy,
+    pub event_retry_policy: RetryPolicy,
+    pub checkpoint_store: Option<Box<dyn CheckpointStore>>,
+    /// How often the checkpoint is stored while only transactions
+    /// without handlers are finished.
+    pub checkpoint_interval: Duration,
+    pub registration_store: Option<Box<dyn RegistrationStore>>,
+    /// The state version of the last finished transaction, if known.
+    /// Transactions up to and including this state version are skipped.
+    pub checkpoint: Option<u64>,
+    pub dead_letter_sink: Option<Arc<dyn DeadLetterSink>>,
+    pub event_handler_timeout: Option<HandlerTimeout<EventHandlerError>>,
+    pub transaction_timeout: Option<HandlerTimeout<TransactionHandlerError>>,
+    pub handler_panic_action: PanicAction,
+    pub batch_transaction_handler:
+        Option<Box<dyn BatchTransactionHandler<STATE, TRANSACTION_CONTEXT>>>,
+    pub handle: ProcessorHandle,
+    /// The checkpoint of a finished transaction which is not stored yet.
+    pub(crate) unsaved_checkpoint: Option<u64>,
+    pub(crate) checkpoint_saved_at: Instant,
 }
 
 #[allow(non_camel_case_types)]
 impl<STATE: State> TransactionProcessor<STATE> {
-    pub fn new(handler_registry: HandlerRegistry, state: STATE) -> Self {
+    pub fn new(handler_registry: HandlerRegistry<STATE>, state: STATE) -> Self {
+        Self::with_transaction_handler(
+            handler_registry,
+            state,
+            DefaultTransactionHandler,
+        )
+    }
+}
+
+#[allow(non_camel_case_types)]
+impl<STATE: State, TRANSACTION_CONTEXT: 'static>
+    TransactionProcessor<STATE, TRANSACTION_CONTEXT>
+{
+    /// Creates a new [`TransactionProcessor`] with the given [`TransactionHandler`],
+    /// which creates the transaction context for the handlers in the [`HandlerRegistry`].
+    pub fn with_transaction_handler(
+        handler_registry: HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+        state: STATE,
+        transaction_handler: impl TransactionHandler<STATE, TRANSACTION_CONTEXT>,
+    ) -> Self {
         Self {
-            logger: Some(Arc::new(
-                RwLock::new(Box::<DefaultLogger>::default()),
-            )),
-            transaction_handler: Box::new(DefaultTransactionHandler),
-            transaction_retry_delay: Duration::from_secs(10),
-            event_retry_delay: Duration::from_secs(10),
-            handler_registry: handler_registry,
-            state: state,
+            logger: Some(Arc::new(DefaultLogger::default())),
+            transaction_handler: Box::new(transaction_handler),
+            transaction_retry_policy: RetryPolicy::default(),
+            event_retry_policy: RetryPolicy::default(),
+            checkpoint_store: None,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            registration_store: None,
+            checkpoint: None,
+            dead_letter_sink: None,
+            event_handler_timeout: None,
+            transaction_timeout: None,
+            handler_panic_action: PanicAction::default(),
+            batch_transaction_handler: None,
+            handle: ProcessorHandle::default(),
+            unsaved_checkpoint: None,
+            checkpoint_saved_at: Instant::now(),
+            handler_registry,
+            state,
         }
     }
 
@@ -248,22 +994,64 @@ impl<STATE: State> TransactionProcessor<STATE> {
         self,
         transaction_retry_delay: Duration,
     ) -> Self {
-        Self {
+        self.transaction_retry_policy(RetryPolicy::fixed(
             transaction_retry_delay,
-            ..self
-        }
+        ))
     }
 
     pub fn event_retry_delay(self, event_retry_delay: Duration) -> Self {
+        self.event_retry_policy(RetryPolicy::fixed(event_retry_delay))
+    }
+
+    pub fn transaction_retry_policy(
+        self,
+        transaction_retry_policy: RetryPolicy,
+    ) -> Self {
         Self {
-            event_retry_delay,
+            transaction_retry_policy,
+            ..self
+        }
+    }
+
+    pub fn event_retry_policy(self, event_retry_policy: RetryPolicy) -> Self {
+        Self {
+            event_retry_policy,
+            ..self
+        }
+    }
+
+    pub fn event_handler_timeout(
+        self,
+        timeout: Duration,
+        error: fn(anyhow::Error) -> EventHandlerError,
+    ) -> Self {
+        Self {
+            event_handler_timeout: Some(HandlerTimeout::new(timeout, error)),
+            ..self
+        }
+    }
+
+    pub fn transaction_timeout(
+        self,
+        timeout: Duration,
+        error: fn(anyhow::Error) -> TransactionHandlerError,
+    ) -> Self {
+        Self {
+            transaction_timeout: Some(HandlerTimeout::new(timeout, error)),
+            ..self
+        }
+    }
+
+    pub fn on_handler_panic(self, handler_panic_action: PanicAction) -> Self {
+        Self {
+            handler_panic_action,
             ..self
         }
     }
 
     pub fn transaction_handler(
         self,
-        transaction_handler: impl TransactionHandler<STATE>,
+        transaction_handler: impl TransactionHandler<STATE, TRANSACTION_CONTEXT>,
     ) -> Self {
         Self {
             transaction_handler: Box::new(transaction_handler),
@@ -271,9 +1059,31 @@ impl<STATE: State> TransactionProcessor<STATE> {
         }
     }
 
+    pub fn batch_transaction_handler(
+        self,
+        batch_transaction_handler: impl BatchTransactionHandler<
+            STATE,
+            TRANSACTION_CONTEXT,
+        >,
+    ) -> Self {
+        Self {
+            batch_transaction_handler: Some(Box::new(
+                batch_transaction_handler,
+            )),
+            ..self
+        }
+    }
+
     pub fn logger(self, logger: impl Logger + 'static) -> Self {
         Self {
-            logger: Some(Arc::new(RwLock::new(Box::new(logger)))),
+            logger: Some(Arc::new(logger)),
+            ..self
+        }
+    }
+
+    pub fn add_logger(self, logger: impl Logger + 'static) -> Self {
+        Self {
+            logger: Some(combine_loggers(self.logger, logger)),
             ..self
         }
     }
@@ -285,21 +1095,278 @@ impl<STATE: State> TransactionProcessor<STATE> {
         }
     }
 
+    pub fn checkpoint_store(
+        self,
+        checkpoint_store: impl CheckpointStore + 'static,
+    ) -> Self {
+        Self {
+            checkpoint_store: Some(Box::new(checkpoint_store)),
+            ..self
+        }
+    }
+
+    /// Sets how often the checkpoint is stored while only transactions
+    /// without handlers are finished. Defaults to 10 seconds.
+    ///
+    /// The checkpoint is always stored right after a transaction with handlers.
+    /// After a transaction without handlers, it is only stored once this
+    /// interval has passed since it was last stored, so that a stream of
+    /// unhandled transactions doesn't write the store for each of them.
+    /// Call [`TransactionProcessor::flush_checkpoint`] to store it before
+    /// stopping, which [`TransactionStreamProcessor::run`] does by itself.
+    pub fn checkpoint_interval(self, checkpoint_interval: Duration) -> Self {
+        Self {
+            checkpoint_interval,
+            ..self
+        }
+    }
+
+    pub fn registration_store(
+        self,
+        registration_store: impl RegistrationStore + 'static,
+    ) -> Self {
+        Self {
+            registration_store: Some(Box::new(registration_store)),
+            ..self
+        }
+    }
+
+    pub fn dead_letter_sink(
+        self,
+        dead_letter_sink: impl DeadLetterSink + 'static,
+    ) -> Self {
+        Self {
+            dead_letter_sink: Some(Arc::new(dead_letter_sink)),
+            ..self
+        }
+    }
+
+    /// Returns a [`ProcessorHandle`], which reports the status of this processor.
+    pub fn handle(&self) -> ProcessorHandle {
+        self.handle.clone()
+    }
+
+    /// Loads the checkpoint from the [`CheckpointStore`], if one is set.
+    /// Transactions up to and including the checkpoint are skipped
+    /// from then on.
+    pub async fn load_checkpoint(
+        &mut self,
+    ) -> Result<Option<u64>, TransactionProcessorError> {
+        let Some(checkpoint_store) = &self.checkpoint_store else {
+            return Ok(None);
+        };
+        let checkpoint = match checkpoint_store.load().await {
+            Ok(checkpoint) => checkpoint,
+            Err(e) => {
+                if let Some(logger) = &self.logger {
+                    logger.unrecoverable_error(&e).await;
+                }
+                return Err(TransactionProcessorError::UnrecoverableError(e));
+            }
+        };
+        self.checkpoint = checkpoint;
+        Ok(checkpoint)
+    }
+
+    /// Adds the handlers stored in the [`RegistrationStore`] to the
+    /// handler registry, if a store is set.
+    pub async fn load_registrations(
+        &mut self,
+    ) -> Result<(), TransactionProcessorError> {
+        let Some(registration_store) = &self.registration_store else {
+            return Ok(());
+        };
+        let result = match registration_store.load().await {
+            Ok(registrations) => {
+                registrations.iter().try_for_each(|registration| {
+                    self.handler_registry.restore_registration(registration)
+                })
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            if let Some(logger) = &self.logger {
+                logger.unrecoverable_error(&e).await;
+            }
+            return Err(TransactionProcessorError::UnrecoverableError(e));
+        }
+        Ok(())
+    }
+
+    /// Stores the handlers which were registered while handling
+    /// transactions in the [`RegistrationStore`], if one is set.
+    async fn save_registrations(
+        &mut self,
+    ) -> Result<(), TransactionProcessorError> {
+        let registrations = self.handler_registry.take_registrations();
+        let Some(registration_store) = &self.registration_store else {
+            return Ok(());
+        };
+        for registration in &registrations {
+            if let Err(e) = registration_store.save(registration).await {
+                if let Some(logger) = &self.logger {
+                    logger.unrecoverable_error(&e).await;
+                }
+                return Err(TransactionProcessorError::UnrecoverableError(e));
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks a transaction as finished, and stores its state version in the
+    /// [`CheckpointStore`], if one is set. If none of its events had a handler,
+    /// storing it is put off until the checkpoint interval has passed.
+    async fn save_checkpoint(
+        &mut self,
+        transaction: &Transaction,
+        finished: Finished,
+    ) -> Result<(), TransactionProcessorError> {
+        self.checkpoint = Some(transaction.state_version);
+        self.handle
+            .set_finished(transaction.state_version, transaction.confirmed_at);
+        match finished {
+            Finished::Unhandled
+                if self.checkpoint_saved_at.elapsed()
+                    < self.checkpoint_interval =>
+            {
+                self.unsaved_checkpoint = Some(transaction.state_version);
+                Ok(())
+            }
+            Finished::CheckpointStored => {
+                self.save_registrations().await?;
+                self.unsaved_checkpoint = None;
+                self.checkpoint_saved_at = Instant::now();
+                Ok(())
+            }
+            _ => self.store_checkpoint(transaction.state_version).await,
+        }
+    }
+
+    /// Stores the checkpoint of the last finished transaction, if it
+    /// wasn't stored yet because its transaction had no handlers.
+    pub async fn flush_checkpoint(
+        &mut self,
+    ) -> Result<(), TransactionProcessorError> {
+        match self.unsaved_checkpoint {
+            Some(state_version) => self.store_checkpoint(state_version).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Stores a state version in the [`CheckpointStore`], if one is set.
+    /// The handlers registered up to it are stored first, so that they
+    /// are never missing after a restart from the checkpoint.
+    async fn store_checkpoint(
+        &mut self,
+        state_version: u64,
+    ) -> Result<(), TransactionProcessorError> {
+        self.save_registrations().await?;
+        self.unsaved_checkpoint = None;
+        self.checkpoint_saved_at = Instant::now();
+        let Some(checkpoint_store) = &self.checkpoint_store else {
+            return Ok(());
+        };
+        if let Err(e) = checkpoint_store.save(state_version).await {
+            if let Some(logger) = &self.logger {
+                logger.unrecoverable_error(&e).await;
+            }
+            return Err(TransactionProcessorError::UnrecoverableError(e));
+        }
+        Ok(())
+    }
+
     pub async fn process_transaction(
         &mut self,
         transaction: &Transaction,
     ) -> Result<(), TransactionProcessorError> {
+        // Skip transactions which were already finished before,
+        // which can happen when a stream doesn't support starting
+        // from a checkpoint.
+        if self
+            .checkpoint
+            .is_some_and(|checkpoint| transaction.state_version <= checkpoint)
+        {
+            return Ok(());
+        }
+        let finished = self
+            .handle_transaction(transaction, EventSelection::All, true, true)
+            .await?;
+        self.save_checkpoint(transaction, finished).await
+    }
+
+    /// Processes only the events at the given indices of a transaction,
+    /// without looking at or updating the checkpoint. This is used by the
+    /// shards of a [`PartitionedTransactionStreamProcessor`][crate::partitioned::PartitionedTransactionStreamProcessor].
+    ///
+    /// Every shard gets every transaction, but only the first one which
+    /// has handlers for it, which sets `reported`, calls the transaction
+    /// hooks of the logger. A shard without handlers for it calls no hooks.
+    pub(crate) async fn process_partition(
+        &mut self,
+        transaction: &Transaction,
+        event_indices: &[u16],
+        reported: &AtomicBool,
+    ) -> Result<(), TransactionProcessorError> {
+        let events = EventSelection::Partition(event_indices);
+        if !events.handler_exists(&self.handler_registry, transaction) {
+            return Ok(());
+        }
+        let report = !reported.swap(true, Ordering::AcqRel);
+        self.handle_transaction(transaction, events, false, report)
+            .await?;
+        self.save_registrations().await
+    }
+
+    /// Replays dead letters through the transaction handler and event handlers
+    /// of this processor. For a dead-lettered event, only that event is handled
+    /// again, by the handler which failed. For a dead-lettered transaction,
+    /// all of its events are.
+    ///
+    /// Replaying doesn't look at or update the checkpoint. If an item fails again
+    /// and a [`DeadLetterSink`] is set, it is dead-lettered again.
+    pub async fn replay_dead_letters(
+        &mut self,
+        dead_letters: &[DeadLetter],
+    ) -> Result<(), TransactionProcessorError> {
+        for dead_letter in dead_letters {
+            let events = match dead_letter.event_index {
+                Some(event_index) => EventSelection::Event(
+                    event_index,
+                    dead_letter.handler.as_deref(),
+                ),
+                None => EventSelection::All,
+            };
+            self.handle_transaction(
+                &dead_letter.transaction,
+                events,
+                false,
+                true,
+            )
+            .await?;
+            self.save_registrations().await?;
+        }
+        Ok(())
+    }
+
+    /// Handles the selected events of a transaction with retries
+    /// and logging hooks. `store_checkpoint` is passed on to
+    /// the transaction handler. The hooks for the transaction itself
+    /// are only called if `report` is set.
+    async fn handle_transaction(
+        &mut self,
+        transaction: &Transaction,
+        events: EventSelection<'_>,
+        store_checkpoint: bool,
+        report: bool,
+    ) -> Result<Finished, TransactionProcessorError> {
         // Find out if there are any events inside this transaction
         // that have a handler registered.
-        let handler_exists = transaction
-            .events
-            .iter()
-            .any(|event| self.handler_registry.handler_exists(event));
+        let handler_exists =
+            events.handler_exists(&self.handler_registry, transaction);
+        let transaction_logger = self.logger.clone().filter(|_| report);
 
-        if let Some(logger) = &self.logger {
+        if let Some(logger) = &transaction_logger {
             logger
-                .write()
-                .await
                 .receive_transaction(transaction, handler_exists, false)
                 .await;
         }
@@ -307,79 +1374,434 @@ impl<STATE: State> TransactionProcessor<STATE> {
         if !handler_exists {
             // If there are no handlers for any of the events in this transaction,
             // we can skip processing it.
-            if let Some(logger) = &self.logger {
-                logger
-                    .write()
-                    .await
-                    .finish_transaction(transaction, false)
-                    .await;
+            if let Some(logger) = &transaction_logger {
+                logger.finish_transaction(transaction, false).await;
             }
-            return Ok(());
+            return Ok(Finished::Unhandled);
         }
 
         // Keep trying to handle the transaction in case
-        // the handler requests this through a TransactionHandlerError.
-        while let Err(err) = self
-            .transaction_handler
-            .handle(TransactionHandlerContext {
-                state: &mut self.state,
+        // the handler requests this through a TransactionHandlerError,
+        // until the retry policy runs out of attempts.
+        let started_at = Instant::now();
+        let mut attempt = 1;
+        let handled = loop {
+            let dead_letters = Mutex::new(Vec::new());
+            let event_processor = &mut EventProcessor {
+                event_retry_policy: &self.event_retry_policy,
+                event_handler_timeout: self.event_handler_timeout,
+                handler_panic_action: self.handler_panic_action,
                 transaction,
-                event_processor: &mut EventProcessor {
-                    event_retry_interval: self.event_retry_delay,
+                logger: &self.logger,
+                dead_letters: self
+                    .dead_letter_sink
+                    .is_some()
+                    .then_some(&dead_letters),
+                events,
+                handle: &self.handle,
+            };
+            let call = in_logger_span(
+                &self.logger,
+                self.transaction_handler.handle(TransactionHandlerContext {
+                    state: &mut self.state,
                     transaction,
-                    logger: &self.logger,
-                },
-                handler_registry: &mut self.handler_registry,
-            })
-            .await
-        {
-            match err {
-                TransactionHandlerError::TransactionRetryError(e) => {
-                    if let Some(logger) = &self.logger {
-                        logger
-                            .write()
-                            .await
-                            .transaction_retry_error(
-                                transaction,
-                                &e,
-                                self.transaction_retry_delay,
-                            )
-                            .await;
-                    }
-                    tokio::time::sleep(self.transaction_retry_delay).await;
-                    if let Some(logger) = &self.logger {
-                        logger
-                            .write()
-                            .await
-                            .receive_transaction(
-                                transaction,
-                                handler_exists,
-                                true,
-                            )
-                            .await;
+                    event_processor,
+                    handler_registry: &mut self.handler_registry,
+                    handle: &self.handle,
+                    attempt,
+                    elapsed: started_at.elapsed(),
+                    store_checkpoint,
+                }),
+            );
+            let result =
+                match within(self.transaction_timeout, catch_panic(call)).await
+                {
+                    Ok(Ok(result)) => result,
+                    Ok(Err(payload)) => {
+                        Err(self
+                            .transaction_handler_panicked(transaction, payload))
                     }
-                    continue;
+                    Err(timeout) => Err(self
+                        .transaction_timed_out(transaction, timeout)
+                        .await),
+                };
+            let e = match result {
+                Ok(()) => {
+                    self.send_dead_letters(dead_letters).await?;
+                    break true;
                 }
-                TransactionHandlerError::UnrecoverableError(e) => {
-                    if let Some(logger) = &self.logger {
-                        logger.write().await.unrecoverable_error(&e).await;
-                    }
-                    return Err(TransactionProcessorError::UnrecoverableError(
-                        e,
-                    ));
+                Err(TransactionHandlerError::TransactionRetryError(e)) => e,
+                Err(TransactionHandlerError::UnrecoverableError(e)) => {
+                    self.dead_letter_transaction(transaction, e).await?;
+                    break false;
                 }
+            };
+            let Some(delay) = self.transaction_retry_policy.next_delay(attempt)
+            else {
+                self.give_up_on_transaction(transaction, e, attempt).await?;
+                break false;
+            };
+            if let Some(logger) = &self.logger {
+                logger
+                    .transaction_retry_error(transaction, &e, delay, attempt)
+                    .await;
             }
+            self.handle.set_retrying(true);
+            tokio::time::sleep(delay).await;
+            self.handle.set_retrying(false);
+            attempt += 1;
+            if let Some(logger) = &transaction_logger {
+                logger
+                    .receive_transaction(transaction, handler_exists, true)
+                    .await;
+            }
+        };
+        if let Some(logger) = &transaction_logger {
+            logger.finish_transaction(transaction, handled).await;
         }
+        if handled
+            && store_checkpoint
+            && self.transaction_handler.stores_checkpoint()
+        {
+            return Ok(Finished::CheckpointStored);
+        }
+        Ok(Finished::Handled)
+    }
+
+    /// Reports that the transaction handler took longer than the transaction
+    /// timeout, and returns the error of the timeout.
+    async fn transaction_timed_out(
+        &self,
+        transaction: &Transaction,
+        timeout: HandlerTimeout<TransactionHandlerError>,
+    ) -> TransactionHandlerError {
         if let Some(logger) = &self.logger {
             logger
-                .write()
-                .await
-                .finish_transaction(transaction, true)
+                .handler_timeout(transaction, None, None, timeout.timeout)
                 .await;
         }
+        (timeout.error)(anyhow!(
+            "transaction handler timed out after {:?} on transaction {}",
+            timeout.timeout,
+            transaction.state_version
+        ))
+    }
+
+    /// Turns a panic in the transaction handler into an error according
+    /// to the [`PanicAction`]. It is reported like any other error: through
+    /// the retry hooks while it is retried, and once when it is dead-lettered
+    /// or becomes unrecoverable.
+    fn transaction_handler_panicked(
+        &self,
+        transaction: &Transaction,
+        payload: PanicPayload,
+    ) -> TransactionHandlerError {
+        let error = anyhow::Error::new(HandlerPanic {
+            message: panic_message(payload),
+            state_version: transaction.state_version,
+            intent_hash: transaction.intent_hash.clone(),
+            event_index: None,
+            emitter: None,
+            handler: None,
+        });
+        match self.handler_panic_action {
+            PanicAction::Unrecoverable => {
+                TransactionHandlerError::UnrecoverableError(error)
+            }
+            PanicAction::Retry => {
+                TransactionHandlerError::TransactionRetryError(error)
+            }
+        }
+    }
+
+    /// Applies the [`RetryExhaustedAction`] of the transaction retry policy
+    /// after the last attempt at handling a transaction failed.
+    async fn give_up_on_transaction(
+        &self,
+        transaction: &Transaction,
+        error: anyhow::Error,
+        attempt: u32,
+    ) -> Result<(), TransactionProcessorError> {
+        match self.transaction_retry_policy.exhausted_action() {
+            RetryExhaustedAction::Fail => {
+                let error = error.context(format!(
+                    "gave up on transaction {} after {} attempts",
+                    transaction.state_version, attempt
+                ));
+                self.dead_letter_transaction(transaction, error).await
+            }
+            RetryExhaustedAction::Skip => {
+                if let Some(logger) = &self.logger {
+                    logger.transaction_skipped(transaction, &error).await;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Sends a failed transaction to the [`DeadLetterSink`], so that
+    /// processing can continue. Without a sink, the error is returned
+    /// as an unrecoverable error instead.
+    async fn dead_letter_transaction(
+        &self,
+        transaction: &Transaction,
+        error: anyhow::Error,
+    ) -> Result<(), TransactionProcessorError> {
+        let error = match &self.dead_letter_sink {
+            Some(dead_letter_sink) => {
+                let dead_letter = DeadLetter::transaction(transaction, &error);
+                match dead_letter_sink.send(dead_letter.clone()).await {
+                    Ok(()) => {
+                        if let Some(logger) = &self.logger {
+                            logger.dead_lettered(&dead_letter).await;
+                        }
+                        return Ok(());
+                    }
+                    Err(e) => e.context(format!(
+                        "failed to dead-letter transaction {}: {:?}",
+                        transaction.state_version, error
+                    )),
+                }
+            }
+            None => error,
+        };
+        if let Some(logger) = &self.logger {
+            logger.unrecoverable_error(&error).await;
+        }
+        Err(TransactionProcessorError::UnrecoverableError(error))
+    }
+
+    /// Sends the dead letters of the events which failed during a successful
+    /// attempt at handling a transaction or batch to the [`DeadLetterSink`].
+    /// They are only sent once the attempt succeeded, so that a transaction
+    /// or batch which is retried doesn't send them twice.
+    async fn send_dead_letters(
+        &self,
+        dead_letters: Mutex<Vec<DeadLetter>>,
+    ) -> Result<(), TransactionProcessorError> {
+        let Some(dead_letter_sink) = &self.dead_letter_sink else {
+            return Ok(());
+        };
+        let dead_letters = dead_letters
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        for dead_letter in dead_letters {
+            if let Err(e) = dead_letter_sink.send(dead_letter.clone()).await {
+                let error = e.context(format!(
+                    "failed to dead-letter event {:?} of transaction {}: {}",
+                    dead_letter.event_index,
+                    dead_letter.state_version(),
+                    dead_letter.errors.join(": ")
+                ));
+                if let Some(logger) = &self.logger {
+                    logger.unrecoverable_error(&error).await;
+                }
+                return Err(TransactionProcessorError::UnrecoverableError(
+                    error,
+                ));
+            }
+            if let Some(logger) = &self.logger {
+                logger.dead_lettered(&dead_letter).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Processes consecutive transactions as one batch with the
+    /// [`BatchTransactionHandler`]. Without a batch handler,
+    /// the transactions are processed one by one.
+    ///
+    /// If the batch fails for good, because the handler returns an `UnrecoverableError`
+    /// or the batch runs out of retry attempts, it is processed again one transaction
+    /// at a time. That way, only the failing transaction is dead-lettered, skipped,
+    /// or stops the processor. Changes which the failed batch made to the in-memory
+    /// state are not undone, just like those of a retried transaction, so handlers
+    /// should keep the state consistent with what was committed.
+    pub async fn process_batch(
+        &mut self,
+        transactions: &[Transaction],
+    ) -> Result<(), TransactionProcessorError> {
+        if self.batch_transaction_handler.is_none() {
+            return self.process_transactions(transactions).await;
+        }
+        // Skip transactions which were already finished before.
+        let start = transactions.partition_point(|transaction| {
+            self.checkpoint.is_some_and(|checkpoint| {
+                transaction.state_version <= checkpoint
+            })
+        });
+        let transactions = &transactions[start..];
+        let Some(last_transaction) = transactions.last() else {
+            return Ok(());
+        };
+        if let Some(finished) = self.handle_batch(transactions).await? {
+            return self.save_checkpoint(last_transaction, finished).await;
+        }
+        for transaction in transactions {
+            let finished = self
+                .handle_batch(std::slice::from_ref(transaction))
+                .await?
+                .unwrap_or(Finished::Handled);
+            self.save_checkpoint(transaction, finished).await?;
+        }
         Ok(())
     }
 
+    /// Handles a batch with retries and logging hooks. Returns `None` if a batch
+    /// of more than one transaction failed for good, and should be handled
+    /// one transaction at a time instead.
+    async fn handle_batch(
+        &mut self,
+        transactions: &[Transaction],
+    ) -> Result<Option<Finished>, TransactionProcessorError> {
+        let Some(batch_transaction_handler) = &self.batch_transaction_handler
+        else {
+            return Ok(Some(Finished::Handled));
+        };
+        // Find out which transactions have events with a handler registered.
+        let handling: Vec<bool> = transactions
+            .iter()
+            .map(|transaction| {
+                transaction.events.iter().any(|event| {
+                    self.handler_registry.handler_exists(event, transaction)
+                })
+            })
+            .collect();
+
+        if let Some(logger) = &self.logger {
+            for (transaction, handling) in transactions.iter().zip(&handling) {
+                logger
+                    .receive_transaction(transaction, *handling, false)
+                    .await;
+            }
+        }
+
+        if !handling.contains(&true) {
+            // If none of the transactions has events with a handler,
+            // we can skip processing the batch.
+            if let Some(logger) = &self.logger {
+                for transaction in transactions {
+                    logger.finish_transaction(transaction, false).await;
+                }
+            }
+            return Ok(Some(Finished::Unhandled));
+        }
+
+        // Logging hooks which are called once per batch
+        // get the first transaction of the batch.
+        let first_transaction = &transactions[0];
+        let started_at = Instant::now();
+        let mut attempt = 1;
+        // `None` means that the batch failed for good,
+        // and should be handled one transaction at a time.
+        let handled = loop {
+            let dead_letters = Mutex::new(Vec::new());
+            let event_processor = &mut BatchEventProcessor {
+                event_retry_policy: &self.event_retry_policy,
+                event_handler_timeout: self.event_handler_timeout,
+                handler_panic_action: self.handler_panic_action,
+                transactions,
+                logger: &self.logger,
+                dead_letters: self
+                    .dead_letter_sink
+                    .is_some()
+                    .then_some(&dead_letters),
+                handle: &self.handle,
+            };
+            let call = in_logger_span(
+                &self.logger,
+                batch_transaction_handler.handle(
+                    BatchTransactionHandlerContext {
+                        state: &mut self.state,
+                        transactions,
+                        event_processor,
+                        handler_registry: &mut self.handler_registry,
+                        handle: &self.handle,
+                        attempt,
+                        elapsed: started_at.elapsed(),
+                    },
+                ),
+            );
+            let result =
+                match within(self.transaction_timeout, catch_panic(call)).await
+                {
+                    Ok(Ok(result)) => result,
+                    Ok(Err(payload)) => Err(self.transaction_handler_panicked(
+                        first_transaction,
+                        payload,
+                    )),
+                    Err(timeout) => Err(self
+                        .transaction_timed_out(first_transaction, timeout)
+                        .await),
+                };
+            let e = match result {
+                Ok(()) => {
+                    self.send_dead_letters(dead_letters).await?;
+                    break Some(true);
+                }
+                Err(TransactionHandlerError::TransactionRetryError(e)) => e,
+                Err(TransactionHandlerError::UnrecoverableError(_))
+                    if transactions.len() > 1 =>
+                {
+                    break None;
+                }
+                Err(TransactionHandlerError::UnrecoverableError(e)) => {
+                    self.dead_letter_transaction(first_transaction, e).await?;
+                    break Some(false);
+                }
+            };
+            let Some(delay) = self.transaction_retry_policy.next_delay(attempt)
+            else {
+                if transactions.len() > 1 {
+                    break None;
+                }
+                self.give_up_on_transaction(first_transaction, e, attempt)
+                    .await?;
+                break Some(false);
+            };
+            if let Some(logger) = &self.logger {
+                logger
+                    .transaction_retry_error(
+                        first_transaction,
+                        &e,
+                        delay,
+                        attempt,
+                    )
+                    .await;
+            }
+            self.handle.set_retrying(true);
+            tokio::time::sleep(delay).await;
+            self.handle.set_retrying(false);
+            attempt += 1;
+            if let Some(logger) = &self.logger {
+                for (transaction, handling) in
+                    transactions.iter().zip(&handling)
+                {
+                    logger
+                        .receive_transaction(transaction, *handling, true)
+                        .await;
+                }
+            }
+        };
+        if let Some(logger) = &self.logger {
+            for (transaction, handling) in transactions.iter().zip(&handling) {
+                logger
+                    .finish_transaction(
+                        transaction,
+                        *handling && handled == Some(true),
+                    )
+                    .await;
+            }
+        }
+        let Some(handled) = handled else {
+            return Ok(None);
+        };
+        if handled && batch_transaction_handler.stores_checkpoint() {
+            return Ok(Some(Finished::CheckpointStored));
+        }
+        Ok(Some(Finished::Handled))
+    }
+
     pub async fn process_transactions(
         &mut self,
         transactions: &[Transaction],
@@ -397,114 +1819,647 @@ impl<STATE: State> TransactionProcessor<STATE> {
 /// It handles retries for events that fail to process, and calls logging hooks.
 /// It is highly recommended to use this method when implementing a custom [`TransactionHandler`].
 pub struct EventProcessor<'a> {
-    event_retry_interval: Duration,
+    event_retry_policy: &'a RetryPolicy,
+    event_handler_timeout: Option<HandlerTimeout<EventHandlerError>>,
+    handler_panic_action: PanicAction,
     transaction: &'a Transaction,
-    logger: &'a Option<Arc<RwLock<Box<dyn Logger>>>>,
+    logger: &'a Option<Arc<dyn Logger>>,
+    /// Collects the dead letters of the failed events,
+    /// if a [`DeadLetterSink`] is set.
+    dead_letters: Option<&'a Mutex<Vec<DeadLetter>>>,
+    events: EventSelection<'a>,
+    handle: &'a ProcessorHandle,
+}
+
+/// The events of a transaction which an [`EventProcessor`] handles.
+#[derive(Clone, Copy)]
+enum EventSelection<'a> {
+    All,
+    /// Only the event at this index, to replay a dead-lettered event.
+    /// If a handler name is given, only that handler of the event runs.
+    Event(u16, Option<&'a str>),
+    /// The events of a shard of a [`PartitionedTransactionStreamProcessor`][crate::partitioned::PartitionedTransactionStreamProcessor]:
+    /// the events at these indices, which belong to the shard, and any other
+    /// event for the handlers which the shard added while processing.
+    Partition(&'a [u16]),
 }
 
+#[allow(non_camel_case_types)]
+impl EventSelection<'_> {
+    /// Returns whether any selected event of the transaction has a handler.
+    fn handler_exists<STATE: State, TRANSACTION_CONTEXT: 'static>(
+        &self,
+        handler_registry: &HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+        transaction: &Transaction,
+    ) -> bool {
+        transaction.events.iter().enumerate().any(|(index, event)| {
+            !self
+                .event_handlers(
+                    handler_registry,
+                    index as u16,
+                    event,
+                    transaction,
+                )
+                .is_empty()
+        })
+    }
+
+    /// Gets the handlers which handle the event at an index, if it is selected.
+    #[allow(clippy::borrowed_box)]
+    fn event_handlers<'r, STATE: State, TRANSACTION_CONTEXT: 'static>(
+        &self,
+        handler_registry: &'r HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+        event_index: u16,
+        event: &Event,
+        transaction: &Transaction,
+    ) -> Vec<&'r Box<dyn EventHandler<STATE, TRANSACTION_CONTEXT>>> {
+        match self {
+            Self::All => handler_registry.event_handlers(event, transaction),
+            Self::Event(index, handler_name) if *index == event_index => {
+                handler_registry
+                    .event_handlers(event, transaction)
+                    .into_iter()
+                    .filter(|event_handler| {
+                        handler_name.map_or(true, |handler_name| {
+                            event_handler.name() == handler_name
+                        })
+                    })
+                    .collect()
+            }
+            Self::Event(..) => Vec::new(),
+            Self::Partition(event_indices)
+                if event_indices.contains(&event_index) =>
+            {
+                handler_registry.event_handlers(event, transaction)
+            }
+            Self::Partition(_) => handler_registry
+                .event_handlers_added_while_processing(event, transaction),
+        }
+    }
+}
+
+/// Gets the [`Savepoints`] of a transaction context, if the handlers
+/// should run inside savepoints.
+#[allow(non_camel_case_types)]
+type AsSavepoints<TRANSACTION_CONTEXT> =
+    Option<fn(&mut TRANSACTION_CONTEXT) -> &mut dyn Savepoints>;
+
 #[allow(non_camel_case_types)]
 impl<'a> EventProcessor<'a> {
     pub async fn process_events<STATE: State, TRANSACTION_CONTEXT: 'static>(
         &self,
         state: &mut STATE,
-        handler_registry: &mut HandlerRegistry,
+        handler_registry: &mut HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+        transaction_context: &mut TRANSACTION_CONTEXT,
+    ) -> Result<(), EventHandlerError> {
+        self.process_events_with(
+            state,
+            handler_registry,
+            transaction_context,
+            None,
+        )
+        .await
+    }
+
+    /// Like [`EventProcessor::process_events`], but runs each attempt of
+    /// an event handler inside a savepoint of the transaction context.
+    /// When an attempt fails, its writes are rolled back to the savepoint,
+    /// so that a retried, dead-lettered or skipped event leaves no partial
+    /// writes behind when the transaction commits.
+    pub async fn process_events_with_savepoints<
+        STATE: State,
+        TRANSACTION_CONTEXT: Savepoints + 'static,
+    >(
+        &self,
+        state: &mut STATE,
+        handler_registry: &mut HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
         transaction_context: &mut TRANSACTION_CONTEXT,
+    ) -> Result<(), EventHandlerError> {
+        let as_savepoints: fn(&mut TRANSACTION_CONTEXT) -> &mut dyn Savepoints =
+            |transaction_context| transaction_context;
+        self.process_events_with(
+            state,
+            handler_registry,
+            transaction_context,
+            Some(as_savepoints),
+        )
+        .await
+    }
+
+    async fn process_events_with<STATE: State, TRANSACTION_CONTEXT: 'static>(
+        &self,
+        state: &mut STATE,
+        handler_registry: &mut HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+        transaction_context: &mut TRANSACTION_CONTEXT,
+        as_savepoints: AsSavepoints<TRANSACTION_CONTEXT>,
     ) -> Result<(), EventHandlerError> {
         for (event_index, event) in self.transaction.events.iter().enumerate() {
-            let handler_exists = handler_registry.handler_exists(event);
-            if !handler_exists {
+            let event_index = event_index as u16;
+            let event_handlers: Vec<_> = self
+                .events
+                .event_handlers(
+                    handler_registry,
+                    event_index,
+                    event,
+                    self.transaction,
+                )
+                .into_iter()
+                .cloned()
+                .collect();
+            if event_handlers.is_empty() {
                 continue;
             }
             if let Some(logger) = self.logger {
                 logger
-                    .write()
-                    .await
-                    .receive_event(
+                    .receive_event(self.transaction, event, true, false)
+                    .await;
+            }
+            // The handlers run in order. One which fails for good is
+            // dead-lettered or skipped on its own, and the others still run.
+            let mut handled = true;
+            for event_handler in &event_handlers {
+                handled &= self
+                    .handle_event(
+                        event_handler.as_ref(),
+                        state,
+                        handler_registry,
+                        transaction_context,
+                        as_savepoints,
+                        event_index,
+                    )
+                    .await?;
+            }
+            if let Some(logger) = self.logger {
+                logger.finish_event(self.transaction, event, handled).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs one handler of an event, retrying it according to the event
+    /// retry policy. Returns whether it handled the event, or false
+    /// if the event was dead-lettered or skipped instead.
+    async fn handle_event<STATE: State, TRANSACTION_CONTEXT: 'static>(
+        &self,
+        event_handler: &dyn EventHandler<STATE, TRANSACTION_CONTEXT>,
+        state: &mut STATE,
+        handler_registry: &mut HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+        transaction_context: &mut TRANSACTION_CONTEXT,
+        as_savepoints: AsSavepoints<TRANSACTION_CONTEXT>,
+        event_index: u16,
+    ) -> Result<bool, EventHandlerError> {
+        let event = &self.transaction.events[event_index as usize];
+        let handler_name = event_handler.name();
+        let timeout = event_handler.timeout().or(self.event_handler_timeout);
+        let started_at = Instant::now();
+        let mut attempt = 1;
+        let handled = loop {
+            if let Some(logger) = self.logger {
+                logger
+                    .start_handler(
                         self.transaction,
                         event,
-                        handler_exists,
-                        false,
+                        handler_name,
+                        attempt,
                     )
                     .await;
             }
-            let event_handler = {
-                handler_registry
-                    .handler::<STATE, TRANSACTION_CONTEXT>(
-                        event.emitter.address(),
-                        &event.name,
-                    )
-                    .or_else(|| {
-                        let entity_type = match &event.emitter {
-                            EventEmitter::Method { entity_type, .. } => {
-                                entity_type
-                            }
-                            EventEmitter::Function { .. } => {
-                                panic!("Got a function call while expecting a native event.")
-                            }
-                        };
-                        handler_registry.native_handler(
-                            NativeEventType::resolve(&event.name, entity_type.clone()).unwrap(),
-                        )
-                    })
-                    .unwrap()
-            };
-            let event_handler = event_handler.clone();
-            while let Err(err) = event_handler
-                .handle(
+            if let Some(as_savepoints) = as_savepoints {
+                as_savepoints(transaction_context)
+                    .savepoint()
+                    .await
+                    .map_err(EventHandlerError::TransactionRetryError)?;
+            }
+            let call = in_logger_span(
+                self.logger,
+                event_handler.handle(
                     EventHandlerContext {
                         state,
                         transaction: self.transaction,
                         event,
                         handler_registry,
                         transaction_context,
-                        event_index: event_index as u16,
+                        event_index,
+                        attempt,
+                        elapsed: started_at.elapsed(),
+                        handle: self.handle,
                     },
                     &event.binary_sbor_data,
-                )
-                .await
-            {
-                match err {
-                    EventHandlerError::EventRetryError(e) => {
-                        if let Some(logger) = self.logger {
-                            logger
-                                .write()
-                                .await
-                                .event_retry_error(
-                                    self.transaction,
-                                    event,
-                                    &e,
-                                    self.event_retry_interval,
-                                )
-                                .await;
-                        }
-                        tokio::time::sleep(self.event_retry_interval).await;
+                ),
+            );
+            let result = match within(timeout, catch_panic(call)).await {
+                Ok(Ok(result)) => result,
+                Ok(Err(payload)) => Err(self.event_handler_panicked(
+                    event_index,
+                    event,
+                    handler_name,
+                    payload,
+                )),
+                Err(timeout) => Err(self
+                    .event_handler_timed_out(event, handler_name, timeout)
+                    .await),
+            };
+            if let Some(as_savepoints) = as_savepoints {
+                let savepoints = as_savepoints(transaction_context);
+                match result {
+                    Ok(()) => savepoints.release_savepoint().await,
+                    Err(_) => savepoints.rollback_to_savepoint().await,
+                }
+                .map_err(EventHandlerError::TransactionRetryError)?;
+            }
+            let e = match result {
+                Ok(()) => break true,
+                Err(EventHandlerError::EventRetryError(e)) => e,
+                Err(EventHandlerError::UnrecoverableError(e)) => {
+                    self.dead_letter_event(event_index, handler_name, e)?;
+                    break false;
+                }
+                Err(err) => return Err(err),
+            };
+            let Some(delay) = self.event_retry_policy.next_delay(attempt)
+            else {
+                match self.event_retry_policy.exhausted_action() {
+                    RetryExhaustedAction::Fail => {
+                        let e = e.context(format!(
+                            "{} gave up on event {} after {} attempts",
+                            handler_name, event.name, attempt
+                        ));
+                        self.dead_letter_event(event_index, handler_name, e)?;
+                        break false;
+                    }
+                    RetryExhaustedAction::Skip => {
                         if let Some(logger) = self.logger {
                             logger
-                                .write()
-                                .await
-                                .receive_event(
-                                    self.transaction,
-                                    event,
-                                    handler_exists,
-                                    true,
-                                )
+                                .event_skipped(self.transaction, event, &e)
                                 .await;
                         }
-                        continue;
-                    }
-                    _ => {
-                        return Err(err);
+                        break false;
                     }
                 }
+            };
+            if let Some(logger) = self.logger {
+                logger
+                    .event_retry_error(
+                        self.transaction,
+                        event,
+                        &e,
+                        delay,
+                        attempt,
+                    )
+                    .await;
             }
+            self.handle.set_retrying(true);
+            tokio::time::sleep(delay).await;
+            self.handle.set_retrying(false);
+            attempt += 1;
             if let Some(logger) = self.logger {
                 logger
-                    .write()
-                    .await
-                    .finish_event(self.transaction, event, handler_exists)
+                    .receive_event(self.transaction, event, true, true)
                     .await;
             }
+        };
+        if let Some(logger) = self.logger {
+            logger
+                .finish_handler(self.transaction, event, handler_name, handled)
+                .await;
+        }
+        Ok(handled)
+    }
+
+    /// Turns a panic in an event handler into an error according to
+    /// the [`PanicAction`], which is reported like the errors of the handler.
+    fn event_handler_panicked(
+        &self,
+        event_index: u16,
+        event: &Event,
+        handler_name: &str,
+        payload: PanicPayload,
+    ) -> EventHandlerError {
+        let error = anyhow::Error::new(HandlerPanic {
+            message: panic_message(payload),
+            state_version: self.transaction.state_version,
+            intent_hash: self.transaction.intent_hash.clone(),
+            event_index: Some(event_index),
+            emitter: Some(event.emitter.address().to_string()),
+            handler: Some(handler_name.to_string()),
+        });
+        match self.handler_panic_action {
+            PanicAction::Unrecoverable => {
+                EventHandlerError::UnrecoverableError(error)
+            }
+            PanicAction::Retry => EventHandlerError::EventRetryError(error),
+        }
+    }
+
+    /// Reports that an event handler took longer than the event handler
+    /// timeout, and returns the error of the timeout.
+    async fn event_handler_timed_out(
+        &self,
+        event: &Event,
+        handler_name: &str,
+        timeout: HandlerTimeout<EventHandlerError>,
+    ) -> EventHandlerError {
+        if let Some(logger) = self.logger {
+            logger
+                .handler_timeout(
+                    self.transaction,
+                    Some(event),
+                    Some(handler_name),
+                    timeout.timeout,
+                )
+                .await;
+        }
+        (timeout.error)(anyhow!(
+            "{} timed out after {:?} on event {}",
+            handler_name,
+            timeout.timeout,
+            event.name
+        ))
+    }
+
+    /// Dead-letters a handler which failed on an event, so that processing
+    /// can continue with the next handler. The dead letter is sent to the
+    /// [`DeadLetterSink`] once the transaction handler succeeds. Without
+    /// a sink, the error is returned as an unrecoverable error instead.
+    fn dead_letter_event(
+        &self,
+        event_index: u16,
+        handler_name: &str,
+        error: anyhow::Error,
+    ) -> Result<(), EventHandlerError> {
+        let Some(dead_letters) = self.dead_letters else {
+            return Err(EventHandlerError::UnrecoverableError(error));
+        };
+        dead_letters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(DeadLetter::event(
+                self.transaction,
+                event_index,
+                handler_name,
+                &error,
+            ));
+        Ok(())
+    }
+}
+
+/// The [`BatchEventProcessor`] is the counterpart of the [`EventProcessor`]
+/// for a [`BatchTransactionHandler`]. Calling [`BatchEventProcessor::process_events`]
+/// processes the events of all transactions in the batch, in order,
+/// with the same transaction context.
+pub struct BatchEventProcessor<'a> {
+    event_retry_policy: &'a RetryPolicy,
+    event_handler_timeout: Option<HandlerTimeout<EventHandlerError>>,
+    handler_panic_action: PanicAction,
+    transactions: &'a [Transaction],
+    logger: &'a Option<Arc<dyn Logger>>,
+    dead_letters: Option<&'a Mutex<Vec<DeadLetter>>>,
+    handle: &'a ProcessorHandle,
+}
+
+#[allow(non_camel_case_types)]
+impl<'a> BatchEventProcessor<'a> {
+    pub async fn process_events<STATE: State, TRANSACTION_CONTEXT: 'static>(
+        &self,
+        state: &mut STATE,
+        handler_registry: &mut HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+        transaction_context: &mut TRANSACTION_CONTEXT,
+    ) -> Result<(), EventHandlerError> {
+        for transaction in self.transactions {
+            EventProcessor {
+                event_retry_policy: self.event_retry_policy,
+                event_handler_timeout: self.event_handler_timeout,
+                handler_panic_action: self.handler_panic_action,
+                transaction,
+                logger: self.logger,
+                dead_letters: self.dead_letters,
+                events: EventSelection::All,
+                handle: self.handle,
+            }
+            .process_events(state, handler_registry, transaction_context)
+            .await?;
         }
         Ok(())
     }
+
+    /// Like [`BatchEventProcessor::process_events`], but runs each attempt
+    /// of an event handler inside a savepoint of the transaction context.
+    /// See [`EventProcessor::process_events_with_savepoints`].
+    pub async fn process_events_with_savepoints<
+        STATE: State,
+        TRANSACTION_CONTEXT: Savepoints + 'static,
+    >(
+        &self,
+        state: &mut STATE,
+        handler_registry: &mut HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+        transaction_context: &mut TRANSACTION_CONTEXT,
+    ) -> Result<(), EventHandlerError> {
+        for transaction in self.transactions {
+            EventProcessor {
+                event_retry_policy: self.event_retry_policy,
+                event_handler_timeout: self.event_handler_timeout,
+                handler_panic_action: self.handler_panic_action,
+                transaction,
+                logger: self.logger,
+                dead_letters: self.dead_letters,
+                events: EventSelection::All,
+                handle: self.handle,
+            }
+            .process_events_with_savepoints(
+                state,
+                handler_registry,
+                transaction_context,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "channel"))]
+mod tests {
+    use super::*;
+    use crate::sources::channel::ChannelTransactionStream;
+    use tokio::sync::mpsc::Sender;
+
+    fn transaction(state_version: u64) -> Transaction {
+        Transaction {
+            intent_hash: format!("txid_{}", state_version),
+            state_version,
+            confirmed_at: None,
+            events: Vec::new(),
+        }
+    }
+
+    /// Waits until the condition holds, failing the test after a while.
+    async fn wait_until(condition: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("condition wasn't met in time");
+    }
+
+    #[derive(Clone, Default)]
+    struct MemoryCheckpointStore(Arc<Mutex<Option<u64>>>);
+
+    #[async_trait]
+    impl CheckpointStore for MemoryCheckpointStore {
+        async fn load(&self) -> Result<Option<u64>, anyhow::Error> {
+            Ok(*self.0.lock().unwrap())
+        }
+
+        async fn save(&self, state_version: u64) -> Result<(), anyhow::Error> {
+            *self.0.lock().unwrap() = Some(state_version);
+            Ok(())
+        }
+    }
+
+    /// A stream of fixed transactions, which can be started from any of them.
+    /// It keeps its channels open, like a stream at the ledger tip.
+    #[derive(Debug)]
+    struct RestartableStream {
+        transactions: Vec<Transaction>,
+        senders: Vec<Sender<Transaction>>,
+        started_from: Arc<Mutex<Option<u64>>>,
+    }
+
+    #[async_trait]
+    impl TransactionStream for RestartableStream {
+        async fn start(
+            &mut self,
+        ) -> Result<Receiver<Transaction>, anyhow::Error> {
+            self.start_from(0).await
+        }
+
+        async fn start_from(
+            &mut self,
+            state_version: u64,
+        ) -> Result<Receiver<Transaction>, anyhow::Error> {
+            let (sender, receiver) =
+                tokio::sync::mpsc::channel(self.transactions.len().max(1));
+            for transaction in &self.transactions {
+                if transaction.state_version >= state_version {
+                    sender.send(transaction.clone()).await?;
+                }
+            }
+            self.senders.push(sender);
+            *self.started_from.lock().unwrap() = Some(state_version);
+            Ok(receiver)
+        }
+
+        async fn stop(&mut self) {}
+    }
+
+    #[tokio::test]
+    async fn seeking_backwards_moves_the_stored_checkpoint() {
+        let started_from = Arc::new(Mutex::new(None));
+        let stream = RestartableStream {
+            transactions: (1..=10).map(transaction).collect(),
+            senders: Vec::new(),
+            started_from: started_from.clone(),
+        };
+        let checkpoints = MemoryCheckpointStore::default();
+        // Transactions without handlers don't store the checkpoint
+        // before the interval passed, so it is only stored on stop.
+        let mut processor =
+            TransactionStreamProcessor::new(stream, HandlerRegistry::new(), ())
+                .checkpoint_store(checkpoints.clone())
+                .checkpoint_interval(Duration::from_secs(3600))
+                .disable_logging();
+        let handle = processor.handle();
+        let control = async {
+            wait_until(|| handle.status().last_state_version == Some(10)).await;
+            handle.pause();
+            handle.seek(5);
+            wait_until(|| *started_from.lock().unwrap() == Some(5)).await;
+            handle.stop();
+        };
+
+        let (result, ()) = tokio::join!(processor.run(), control);
+        assert_eq!(result.unwrap(), StopReason::Stopped);
+        assert_eq!(*checkpoints.0.lock().unwrap(), Some(4));
+    }
+
+    #[tokio::test]
+    async fn seek_is_ignored_when_the_stream_cant_restart() {
+        let (stream, sender) = ChannelTransactionStream::new(10);
+        let mut processor =
+            TransactionStreamProcessor::new(stream, HandlerRegistry::new(), ())
+                .disable_logging();
+        let handle = processor.handle();
+        let control = async {
+            for state_version in 1..=3 {
+                sender.send(transaction(state_version)).await.unwrap();
+            }
+            wait_until(|| handle.status().last_state_version == Some(3)).await;
+            handle.seek(1);
+            sender.send(transaction(4)).await.unwrap();
+            wait_until(|| handle.status().last_state_version == Some(4)).await;
+            handle.stop();
+        };
+
+        let (result, ()) = tokio::join!(processor.run(), control);
+        assert_eq!(result.unwrap(), StopReason::Stopped);
+    }
+
+    /// A stream which sends one transaction and then fails.
+    #[derive(Debug)]
+    struct FailingStream;
+
+    #[async_trait]
+    impl TransactionStream for FailingStream {
+        async fn start(
+            &mut self,
+        ) -> Result<Receiver<Transaction>, anyhow::Error> {
+            let (sender, receiver) = tokio::sync::mpsc::channel(1);
+            sender.send(transaction(1)).await?;
+            Ok(receiver)
+        }
+
+        async fn stop(&mut self) {}
+
+        async fn end(&mut self) -> Result<StreamEnd, anyhow::Error> {
+            Err(anyhow::anyhow!("Failed to resolve entities"))
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_errors_are_returned() {
+        let checkpoints = MemoryCheckpointStore::default();
+        let mut processor = TransactionStreamProcessor::new(
+            FailingStream,
+            HandlerRegistry::new(),
+            (),
+        )
+        .checkpoint_store(checkpoints.clone())
+        .disable_logging();
+
+        let result = processor.run().await;
+        assert!(matches!(
+            result,
+            Err(TransactionProcessorError::UnrecoverableError(_))
+        ));
+        // The transactions before the failure are still finished.
+        assert_eq!(*checkpoints.0.lock().unwrap(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn stream_bounds_stop_with_bound_reached() {
+        let (stream, sender) = ChannelTransactionStream::new(10);
+        let stream = stream.until_state_version(3);
+        let mut processor =
+            TransactionStreamProcessor::new(stream, HandlerRegistry::new(), ())
+                .disable_logging();
+        // The sender stays open, so only the bounds end the stream.
+        for state_version in 1..=5 {
+            sender.send(transaction(state_version)).await.unwrap();
+        }
+
+        let result = processor.run().await;
+        assert_eq!(result.unwrap(), StopReason::BoundReached);
+        assert_eq!(processor.handle().status().last_state_version, Some(3));
+    }
 }
@@ -0,0 +1,72 @@
+//! A registration store that appends registrations to a JSON Lines file.
+
+use super::{Registration, RegistrationStore};
+use async_trait::async_trait;
+use std::{collections::HashMap, io::ErrorKind, path::PathBuf};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+/// A registration store that appends each registration as a line of JSON
+/// to a file. The file is created on the first registration.
+///
+/// A registration which replaces an earlier one for the same emitter,
+/// event name and handler ID is appended too, and only the last one is loaded.
+#[derive(Debug)]
+pub struct FileRegistrationStore {
+    file_path: PathBuf,
+}
+
+impl FileRegistrationStore {
+    /// Creates a new FileRegistrationStore which writes to the given path.
+    pub fn new(file_path: String) -> Self {
+        Self {
+            file_path: PathBuf::from(file_path),
+        }
+    }
+}
+
+#[async_trait]
+impl RegistrationStore for FileRegistrationStore {
+    async fn load(&self) -> Result<Vec<Registration>, anyhow::Error> {
+        let contents = match tokio::fs::read_to_string(&self.file_path).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Ok(Vec::new())
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let mut registrations: Vec<Registration> = Vec::new();
+        let mut positions = HashMap::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let registration: Registration = serde_json::from_str(line)?;
+            let key = (
+                registration.emitter.clone(),
+                registration.event_name.clone(),
+                registration.handler_id.clone(),
+            );
+            match positions.get(&key) {
+                Some(&position) => registrations[position] = registration,
+                None => {
+                    positions.insert(key, registrations.len());
+                    registrations.push(registration);
+                }
+            }
+        }
+        Ok(registrations)
+    }
+
+    async fn save(
+        &self,
+        registration: &Registration,
+    ) -> Result<(), anyhow::Error> {
+        let mut line = serde_json::to_string(registration)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file_path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+//! Persistent handler registrations, which allow handlers that are added
+//! while processing, like the handlers for a newly instantiated component,
+//! to survive a restart from a checkpoint.
+//!
+//! Instead of adding such a handler with [`HandlerRegistry::add_handler`],
+//! an event handler registers it with [`HandlerRegistry::register_handler`],
+//! by the ID of a handler in the [`HandlerCatalog`] of the registry.
+//! The processor writes these [`Registration`]s to a [`RegistrationStore`]
+//! before it stores the checkpoint of the transaction, and adds them
+//! to the registry again when it starts.
+//!
+//! Like the sources, the implementations provided by the framework
+//! are behind feature flags.
+
+use crate::event_handler::{EventHandler, HandlerRegistry, State};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[cfg(feature = "file")]
+pub mod file;
+#[cfg(any(feature = "database", feature = "sqlite"))]
+#[macro_use]
+mod sql;
+#[cfg(feature = "database")]
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// A handler which was added for an emitter and event name while processing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Registration {
+    pub emitter: String,
+    pub event_name: String,
+    /// The ID of the handler in the [`HandlerCatalog`].
+    pub handler_id: String,
+    /// The state version of the transaction in which the handler was added.
+    pub added_at_state_version: u64,
+}
+
+/// A trait that abstracts a persistent store for handler registrations.
+#[async_trait]
+pub trait RegistrationStore: Send + Sync {
+    /// Loads all registrations, in the order they were added.
+    async fn load(&self) -> Result<Vec<Registration>, anyhow::Error>;
+
+    /// Stores a registration. It replaces an earlier registration
+    /// of the same handler ID for the same emitter and event name.
+    /// Registrations of other handlers for that event are kept, since
+    /// several handlers can handle the same event.
+    async fn save(
+        &self,
+        registration: &Registration,
+    ) -> Result<(), anyhow::Error>;
+}
+
+/// Adds a handler of the catalog to a registry,
+/// for the given emitter and event name.
+#[allow(non_camel_case_types)]
+type AddHandler<STATE, TRANSACTION_CONTEXT> = Box<
+    dyn Fn(&mut HandlerRegistry<STATE, TRANSACTION_CONTEXT>, &str, &str)
+        + Send
+        + Sync,
+>;
+
+/// Event handlers by ID, so that a [`Registration`] can refer to a handler
+/// and the registry can be rebuilt from stored registrations.
+/// Like the [`HandlerRegistry`], it is parametrized by the signature of its handlers.
+///
+/// The IDs are stored with each registration, so they should stay the same
+/// between versions of your application.
+#[allow(non_camel_case_types)]
+pub struct HandlerCatalog<STATE, TRANSACTION_CONTEXT = ()> {
+    handlers: HashMap<String, AddHandler<STATE, TRANSACTION_CONTEXT>>,
+}
+
+#[allow(non_camel_case_types)]
+impl<STATE, TRANSACTION_CONTEXT> Default
+    for HandlerCatalog<STATE, TRANSACTION_CONTEXT>
+{
+    fn default() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+impl<STATE: State, TRANSACTION_CONTEXT: 'static>
+    HandlerCatalog<STATE, TRANSACTION_CONTEXT>
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a handler to the catalog with the given ID.
+    pub fn handler(
+        mut self,
+        handler_id: &str,
+        handler: impl EventHandler<STATE, TRANSACTION_CONTEXT> + Clone + 'static,
+    ) -> Self {
+        self.handlers.insert(
+            handler_id.to_string(),
+            Box::new(move |registry, emitter, event_name| {
+                registry.add_handler(emitter, event_name, handler.clone())
+            }),
+        );
+        self
+    }
+
+    /// Returns whether the catalog has a handler with the given ID.
+    pub fn contains(&self, handler_id: &str) -> bool {
+        self.handlers.contains_key(handler_id)
+    }
+
+    /// Adds the handler of the registration to the registry.
+    pub(crate) fn add_to(
+        &self,
+        registry: &mut HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+        registration: &Registration,
+    ) -> Result<(), anyhow::Error> {
+        let add_handler =
+            self.handlers.get(&registration.handler_id).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "No handler with ID {} in the handler catalog",
+                    registration.handler_id
+                )
+            })?;
+        add_handler(registry, &registration.emitter, &registration.event_name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::EventHandlerError,
+        event_handler::{EventHandlerContext, Named},
+        models::{Event, EventEmitter, Transaction},
+    };
+
+    #[derive(Clone)]
+    struct Handler;
+
+    #[async_trait]
+    impl EventHandler<(), ()> for Handler {
+        async fn handle(
+            &self,
+            _input: EventHandlerContext<'_, (), ()>,
+            _event: &[u8],
+        ) -> Result<(), EventHandlerError> {
+            Ok(())
+        }
+    }
+
+    fn registry_with_catalog() -> HandlerRegistry<()> {
+        let mut registry = HandlerRegistry::new();
+        registry.set_catalog(
+            HandlerCatalog::new()
+                .handler("swaps", Named::new("swaps", Handler))
+                .handler("volume", Named::new("volume", Handler)),
+        );
+        registry
+    }
+
+    /// Registers two handlers for the same event, and checks that both
+    /// of them are added again from the store.
+    async fn restores_handlers_of_the_same_event(
+        store: impl RegistrationStore,
+    ) {
+        let mut registry = registry_with_catalog();
+        for (state_version, handler_id) in [(1, "swaps"), (2, "volume")] {
+            registry
+                .register_handler(
+                    "component",
+                    "Swap",
+                    handler_id,
+                    state_version,
+                )
+                .unwrap();
+        }
+        for registration in registry.take_registrations() {
+            store.save(&registration).await.unwrap();
+        }
+
+        let mut restored = registry_with_catalog();
+        for registration in store.load().await.unwrap() {
+            restored.restore_registration(&registration).unwrap();
+        }
+        let event = Event {
+            name: "Swap".to_string(),
+            binary_sbor_data: Vec::new(),
+            emitter: EventEmitter::Function {
+                package_address: "component".to_string(),
+                blueprint_name: "Pool".to_string(),
+            },
+        };
+        let names: Vec<_> = restored
+            .event_handlers(&event, &Transaction::default())
+            .iter()
+            .map(|handler| handler.name().to_string())
+            .collect();
+        assert_eq!(names, ["swaps", "volume"]);
+    }
+
+    #[cfg(feature = "file")]
+    #[tokio::test]
+    async fn file_store_restores_handlers_of_the_same_event() {
+        let path = std::env::temp_dir().join(format!(
+            "radix_event_stream_registrations_{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        restores_handlers_of_the_same_event(file::FileRegistrationStore::new(
+            path.to_string_lossy().to_string(),
+        ))
+        .await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_store_restores_handlers_of_the_same_event() {
+        // Each connection to an in-memory database has its own database.
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        restores_handlers_of_the_same_event(
+            sqlite::SqliteRegistrationStore::new(pool),
+        )
+        .await;
+    }
+}
@@ -0,0 +1,9 @@
+//! A registration store that keeps registrations in a PostgreSQL database.
+
+sql_registration_store! {
+    database: Postgres,
+    database_name: "PostgreSQL",
+    connection: PgConnection,
+    big_integer: "BIGINT",
+    store: PostgresRegistrationStore,
+}
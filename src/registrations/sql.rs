@@ -0,0 +1,159 @@
+//! The implementation which is shared by the PostgreSQL and SQLite
+//! registration stores.
+//!
+//! The queries use `$1`-style placeholders, which both databases understand.
+//! Only the names and the column type of the state version differ,
+//! and these are passed to the macro.
+
+macro_rules! sql_registration_store {
+    (
+        database: $database:ident,
+        database_name: $database_name:literal,
+        connection: $connection:ident,
+        big_integer: $big_integer:literal,
+        store: $store:ident,
+    ) => {
+        use super::{Registration, RegistrationStore};
+        use async_trait::async_trait;
+        use sqlx::{$connection, $database, Pool};
+        use tokio::sync::OnceCell;
+
+        #[doc = concat!("A registration store that keeps registrations in a table of a ", $database_name, " database.
+The table is created on first use if it doesn't exist yet.
+
+The registrations of each processor are identified by a name,
+so that multiple processors can share the same table.")]
+        #[derive(Debug)]
+        pub struct $store {
+            pool: Pool<$database>,
+            name: String,
+            table_name: String,
+            table_created: OnceCell<()>,
+        }
+
+        impl $store {
+            #[doc = concat!("Creates a new ", stringify!($store), " with default settings
+which uses the given connection pool.")]
+            pub fn new(pool: Pool<$database>) -> Self {
+                Self {
+                    pool,
+                    name: "default".to_string(),
+                    table_name: "handler_registrations".to_string(),
+                    table_created: OnceCell::new(),
+                }
+            }
+
+            /// Sets the name which identifies the registrations of this processor.
+            pub fn name(mut self, name: String) -> Self {
+                self.name = name;
+                self
+            }
+
+            /// Sets the name of the table in which registrations are stored.
+            pub fn table_name(mut self, table_name: String) -> Self {
+                self.table_name = table_name;
+                self
+            }
+
+            pub(crate) async fn create_table(
+                &self,
+            ) -> Result<(), anyhow::Error> {
+                self.table_created
+                    .get_or_try_init(|| async {
+                        sqlx::query(&format!(
+                            r#"
+                                CREATE TABLE IF NOT EXISTS {} (
+                                    name TEXT NOT NULL,
+                                    emitter TEXT NOT NULL,
+                                    event_name TEXT NOT NULL,
+                                    handler_id TEXT NOT NULL,
+                                    added_at_state_version {} NOT NULL,
+                                    PRIMARY KEY (name, emitter, event_name, handler_id)
+                                )
+                            "#,
+                            self.table_name, $big_integer
+                        ))
+                        .execute(&self.pool)
+                        .await
+                        .map(|_| ())
+                    })
+                    .await?;
+                Ok(())
+            }
+        }
+
+        #[async_trait]
+        impl RegistrationStore for $store {
+            async fn load(&self) -> Result<Vec<Registration>, anyhow::Error> {
+                self.create_table().await?;
+                let rows: Vec<(String, String, String, i64)> =
+                    sqlx::query_as(&format!(
+                        r#"
+                            SELECT emitter, event_name, handler_id, added_at_state_version
+                            FROM {}
+                            WHERE name = $1
+                            ORDER BY added_at_state_version
+                        "#,
+                        self.table_name
+                    ))
+                    .bind(&self.name)
+                    .fetch_all(&self.pool)
+                    .await?;
+                Ok(rows
+                    .into_iter()
+                    .map(
+                        |(
+                            emitter,
+                            event_name,
+                            handler_id,
+                            added_at_state_version,
+                        )| Registration {
+                            emitter,
+                            event_name,
+                            handler_id,
+                            added_at_state_version: added_at_state_version
+                                as u64,
+                        },
+                    )
+                    .collect())
+            }
+
+            async fn save(
+                &self,
+                registration: &Registration,
+            ) -> Result<(), anyhow::Error> {
+                self.create_table().await?;
+                let mut connection = self.pool.acquire().await?;
+                self.save_with(&mut connection, registration).await
+            }
+        }
+
+        impl $store {
+            /// Stores a registration using the given connection,
+            /// which may be inside a database transaction.
+            pub(crate) async fn save_with(
+                &self,
+                connection: &mut $connection,
+                registration: &Registration,
+            ) -> Result<(), anyhow::Error> {
+                sqlx::query(&format!(
+                    r#"
+                        INSERT INTO {} (name, emitter, event_name, handler_id, added_at_state_version)
+                        VALUES ($1, $2, $3, $4, $5)
+                        ON CONFLICT (name, emitter, event_name, handler_id) DO UPDATE SET
+                            added_at_state_version = excluded.added_at_state_version
+                    "#,
+                    self.table_name
+                ))
+                .bind(&self.name)
+                .bind(&registration.emitter)
+                .bind(&registration.event_name)
+                .bind(&registration.handler_id)
+                .bind(registration.added_at_state_version as i64)
+                .execute(connection)
+                .await?;
+                Ok(())
+            }
+        }
+    };
+}
@@ -0,0 +1,9 @@
+//! A registration store that keeps registrations in a SQLite database.
+
+sql_registration_store! {
+    database: Sqlite,
+    database_name: "SQLite",
+    connection: SqliteConnection,
+    big_integer: "INTEGER",
+    store: SqliteRegistrationStore,
+}
@@ -0,0 +1,310 @@
+//! Retry policies which determine how the processor retries
+//! events and transactions when a handler returns a retry error.
+//!
+//! A [`RetryPolicy`] computes the delay before each new attempt,
+//! and decides what happens when there are no attempts left.
+//! A [`HandlerTimeout`] turns a handler which takes too long into
+//! one of these errors.
+
+use rand::Rng;
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+/// What the processor does with an event or transaction
+/// when its [`RetryPolicy`] has run out of attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryExhaustedAction {
+    /// Stop the processor with an unrecoverable error.
+    Fail,
+    /// Skip the event or transaction and continue processing.
+    Skip,
+}
+
+/// A timeout for the handlers of a processor, and the error which a handler
+/// that takes longer fails with, like `EventHandlerError::EventRetryError`.
+/// The error is then handled like any other error the handler returns,
+/// so a retry error goes through the retry policy.
+#[derive(Debug)]
+pub struct HandlerTimeout<ERROR> {
+    pub timeout: Duration,
+    pub error: fn(anyhow::Error) -> ERROR,
+}
+
+impl<ERROR> Clone for HandlerTimeout<ERROR> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<ERROR> Copy for HandlerTimeout<ERROR> {}
+
+impl<ERROR> HandlerTimeout<ERROR> {
+    pub fn new(timeout: Duration, error: fn(anyhow::Error) -> ERROR) -> Self {
+        Self { timeout, error }
+    }
+}
+
+#[derive(Clone)]
+enum Backoff {
+    Fixed(Duration),
+    Exponential {
+        initial_delay: Duration,
+        multiplier: f64,
+    },
+    Custom(Arc<dyn Fn(u32) -> Duration + Send + Sync>),
+}
+
+/// A policy which determines the delay before each retry of a failed
+/// event or transaction, and how many attempts are made.
+/// It is created with one of the backoff constructors, and can be
+/// tweaked further using the builder methods.
+///
+/// ```ignore
+/// // Retry with delays of 1s, 2s, 4s, ... up to one minute,
+/// // and stop the processor after 10 attempts.
+/// let policy = RetryPolicy::exponential(Duration::from_secs(1), 2.0)
+///     .max_delay(Duration::from_secs(60))
+///     .max_attempts(10)
+///     .jitter(0.1);
+/// ```
+///
+/// The default policy retries forever, with a fixed delay of 10 seconds.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    backoff: Backoff,
+    max_delay: Option<Duration>,
+    max_attempts: Option<u32>,
+    jitter: f64,
+    exhausted_action: RetryExhaustedAction,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::fixed(Duration::from_secs(10))
+    }
+}
+
+impl Debug for RetryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let backoff = match &self.backoff {
+            Backoff::Fixed(delay) => format!("Fixed({:?})", delay),
+            Backoff::Exponential {
+                initial_delay,
+                multiplier,
+            } => format!("Exponential({:?}, {})", initial_delay, multiplier),
+            Backoff::Custom(_) => "Custom".to_string(),
+        };
+        f.debug_struct("RetryPolicy")
+            .field("backoff", &backoff)
+            .field("max_delay", &self.max_delay)
+            .field("max_attempts", &self.max_attempts)
+            .field("jitter", &self.jitter)
+            .field("exhausted_action", &self.exhausted_action)
+            .finish()
+    }
+}
+
+impl RetryPolicy {
+    fn new(backoff: Backoff) -> Self {
+        Self {
+            backoff,
+            max_delay: None,
+            max_attempts: None,
+            jitter: 0.0,
+            exhausted_action: RetryExhaustedAction::Fail,
+        }
+    }
+
+    /// Creates a policy which waits the same delay before each retry.
+    pub fn fixed(delay: Duration) -> Self {
+        Self::new(Backoff::Fixed(delay))
+    }
+
+    /// Creates a policy which waits `initial_delay` before the first retry,
+    /// and multiplies the delay by `multiplier` for each following retry.
+    pub fn exponential(initial_delay: Duration, multiplier: f64) -> Self {
+        Self::new(Backoff::Exponential {
+            initial_delay,
+            multiplier,
+        })
+    }
+
+    /// Creates a policy which computes the delay with a closure.
+    /// The closure gets the number of the attempt that just failed,
+    /// starting at 1.
+    pub fn custom(
+        delay: impl Fn(u32) -> Duration + Send + Sync + 'static,
+    ) -> Self {
+        Self::new(Backoff::Custom(Arc::new(delay)))
+    }
+
+    /// Caps the delay between attempts, including the jitter.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
+
+    /// Sets the maximum number of attempts, including the first one.
+    /// When the last attempt fails, the policy escalates to its
+    /// [`RetryExhaustedAction`]. By default, there is no maximum.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Randomizes each delay by up to the given fraction in both directions,
+    /// so that many failing processors don't retry at the same time.
+    /// For example, a jitter of `0.1` turns a delay of 10 seconds into
+    /// a delay between 9 and 11 seconds. The value is clamped to `0.0..=1.0`.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets what happens when the last attempt fails.
+    /// Defaults to [`RetryExhaustedAction::Fail`].
+    pub fn on_exhausted(mut self, action: RetryExhaustedAction) -> Self {
+        self.exhausted_action = action;
+        self
+    }
+
+    /// Returns what happens when the last attempt fails.
+    pub fn exhausted_action(&self) -> RetryExhaustedAction {
+        self.exhausted_action
+    }
+
+    /// Returns the delay to wait after the given attempt failed,
+    /// where the first attempt is 1. Returns `None` if that was
+    /// the last attempt.
+    pub fn next_delay(&self, failed_attempt: u32) -> Option<Duration> {
+        if self
+            .max_attempts
+            .is_some_and(|max_attempts| failed_attempt >= max_attempts)
+        {
+            return None;
+        }
+        let delay = match &self.backoff {
+            Backoff::Fixed(delay) => *delay,
+            Backoff::Exponential {
+                initial_delay,
+                multiplier,
+            } => {
+                let exponent = failed_attempt.saturating_sub(1) as i32;
+                Duration::try_from_secs_f64(
+                    initial_delay.as_secs_f64() * multiplier.powi(exponent),
+                )
+                .unwrap_or(Duration::MAX)
+            }
+            Backoff::Custom(delay) => delay(failed_attempt),
+        };
+        // The jitter is applied first, so that it can't push
+        // the delay over the maximum.
+        let delay = if self.jitter > 0.0 {
+            let factor =
+                1.0 + rand::thread_rng().gen_range(-self.jitter..=self.jitter);
+            Duration::try_from_secs_f64(delay.as_secs_f64() * factor)
+                .unwrap_or(Duration::MAX)
+        } else {
+            delay
+        };
+        match self.max_delay {
+            Some(max_delay) => Some(delay.min(max_delay)),
+            None => Some(delay),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_delay_is_the_same_for_each_attempt() {
+        let policy = RetryPolicy::fixed(Duration::from_secs(3));
+        for attempt in 1..=5 {
+            assert_eq!(
+                policy.next_delay(attempt),
+                Some(Duration::from_secs(3))
+            );
+        }
+    }
+
+    #[test]
+    fn exponential_delay_multiplies_after_each_attempt() {
+        let policy = RetryPolicy::exponential(Duration::from_secs(1), 2.0);
+        let delays: Vec<_> =
+            (1..=4).map(|attempt| policy.next_delay(attempt)).collect();
+        assert_eq!(
+            delays,
+            [1, 2, 4, 8].map(|secs| Some(Duration::from_secs(secs)))
+        );
+    }
+
+    #[test]
+    fn exponential_delay_saturates_instead_of_overflowing() {
+        let policy = RetryPolicy::exponential(Duration::from_secs(1), 10.0);
+        assert_eq!(policy.next_delay(1000), Some(Duration::MAX));
+    }
+
+    #[test]
+    fn max_delay_caps_the_delay() {
+        let policy = RetryPolicy::exponential(Duration::from_secs(1), 2.0)
+            .max_delay(Duration::from_secs(5));
+        assert_eq!(policy.next_delay(3), Some(Duration::from_secs(4)));
+        assert_eq!(policy.next_delay(4), Some(Duration::from_secs(5)));
+        assert_eq!(policy.next_delay(100), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn custom_delay_gets_the_failed_attempt() {
+        let policy = RetryPolicy::custom(|attempt| {
+            Duration::from_millis(attempt as u64)
+        });
+        assert_eq!(policy.next_delay(7), Some(Duration::from_millis(7)));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let policy = RetryPolicy::fixed(Duration::from_secs(10)).jitter(0.1);
+        for _ in 0..1000 {
+            let delay = policy.next_delay(1).unwrap();
+            assert!(delay >= Duration::from_secs(9), "{:?}", delay);
+            assert!(delay <= Duration::from_secs(11), "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn jitter_never_exceeds_max_delay() {
+        let policy = RetryPolicy::fixed(Duration::from_secs(10))
+            .max_delay(Duration::from_secs(10))
+            .jitter(0.5);
+        for _ in 0..1000 {
+            let delay = policy.next_delay(1).unwrap();
+            assert!(delay >= Duration::from_secs(5), "{:?}", delay);
+            assert!(delay <= Duration::from_secs(10), "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn jitter_is_clamped() {
+        let policy = RetryPolicy::fixed(Duration::from_secs(1)).jitter(5.0);
+        for _ in 0..1000 {
+            assert!(policy.next_delay(1).unwrap() <= Duration::from_secs(2));
+        }
+    }
+
+    #[test]
+    fn max_attempts_ends_retrying() {
+        let policy = RetryPolicy::fixed(Duration::from_secs(1)).max_attempts(3);
+        assert!(policy.next_delay(1).is_some());
+        assert!(policy.next_delay(2).is_some());
+        assert_eq!(policy.next_delay(3), None);
+        assert_eq!(policy.next_delay(4), None);
+    }
+
+    #[test]
+    fn default_policy_retries_forever() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.next_delay(u32::MAX), Some(Duration::from_secs(10)));
+        assert_eq!(policy.exhausted_action(), RetryExhaustedAction::Fail);
+    }
+}
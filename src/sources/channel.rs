@@ -1,7 +1,11 @@
 //! A transaction stream that receives transactions from a [`tokio::sync::mpsc::channel`].
 
-use crate::{models::Transaction, stream::TransactionStream};
+use crate::{
+    models::Transaction,
+    stream::{StreamBounds, StreamEnd, TransactionStream},
+};
 use async_trait::async_trait;
+use chrono::Utc;
 use tokio::sync::mpsc::Receiver;
 
 /// A transaction stream that receives transactions from a channel.
@@ -10,6 +14,8 @@ use tokio::sync::mpsc::Receiver;
 #[derive(Debug)]
 pub struct ChannelTransactionStream {
     receiver: Option<tokio::sync::mpsc::Receiver<Transaction>>,
+    bounds: StreamBounds,
+    handle: Option<tokio::task::JoinHandle<StreamEnd>>,
 }
 
 impl ChannelTransactionStream {
@@ -20,17 +26,78 @@ impl ChannelTransactionStream {
         (
             Self {
                 receiver: Some(receiver),
+                bounds: StreamBounds::default(),
+                handle: None,
             },
             sender,
         )
     }
+
+    /// Sets the last state version to receive, which is inclusive.
+    /// The stream finishes after this transaction, even if the sender is still open.
+    pub fn until_state_version(mut self, until_state_version: u64) -> Self {
+        self.bounds.until_state_version = Some(until_state_version);
+        self
+    }
+
+    /// Sets the last confirmation time to receive, which is inclusive.
+    /// The stream finishes at the first transaction confirmed after it.
+    pub fn until_confirmed_at(
+        mut self,
+        until_confirmed_at: chrono::DateTime<Utc>,
+    ) -> Self {
+        self.bounds.until_confirmed_at = Some(until_confirmed_at);
+        self
+    }
 }
 
 #[async_trait]
 impl TransactionStream for ChannelTransactionStream {
     async fn start(&mut self) -> Result<Receiver<Transaction>, anyhow::Error> {
-        Ok(self.receiver.take().expect("Receiver already taken"))
+        // The channel can't go back to transactions it already handed out.
+        let mut receiver = self.receiver.take().ok_or_else(|| {
+            anyhow::anyhow!("ChannelTransactionStream can't be restarted")
+        })?;
+        if self.bounds == StreamBounds::default() {
+            return Ok(receiver);
+        }
+        // Forward transactions until the bounds are reached,
+        // and close the channel after that.
+        let bounds = self.bounds;
+        let (tx, rx) = tokio::sync::mpsc::channel(receiver.max_capacity());
+        self.handle = Some(tokio::spawn(async move {
+            while let Some(transaction) = receiver.recv().await {
+                if bounds.is_past(&transaction) {
+                    return StreamEnd::BoundReached;
+                }
+                let is_last = bounds.is_last(&transaction);
+                if tx.send(transaction).await.is_err() {
+                    break;
+                }
+                if is_last {
+                    return StreamEnd::BoundReached;
+                }
+            }
+            StreamEnd::Finished
+        }));
+        Ok(rx)
+    }
+
+    fn can_restart(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    async fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
+    }
+
+    async fn end(&mut self) -> Result<StreamEnd, anyhow::Error> {
+        // The channel is closed when the task ends, so this doesn't wait.
+        match self.handle.take() {
+            Some(handle) => Ok(handle.await?),
+            None => Ok(StreamEnd::Finished),
+        }
     }
-    // no task is spawned, so no need to do anything on stop
-    async fn stop(&mut self) {}
 }
@@ -1,8 +1,9 @@
 //! A transaction stream that fetches transactions from a Radix Gateway PostgreSQL database.
 
+use super::entities::{EntityCache, EntityDetails, RESOLVE_ATTEMPTS};
 use crate::{
     models::{Event, EventEmitter, Transaction},
-    stream::TransactionStream,
+    stream::{StreamBounds, StreamEnd, TransactionStream},
 };
 use async_trait::async_trait;
 use chrono::Utc;
@@ -22,12 +23,16 @@ use tokio::{sync::mpsc::Receiver, time::timeout};
 #[derive(Debug)]
 pub struct DatabaseTransactionStream {
     state_version: u64,
-    join_handle: Option<tokio::task::JoinHandle<()>>,
+    join_handle:
+        Option<tokio::task::JoinHandle<Result<StreamEnd, anyhow::Error>>>,
     limit_per_page: u32,
     buffer_capacity: u64,
     caught_up_timeout: Duration,
     query_timeout: Duration,
     database_url: String,
+    bounds: StreamBounds,
+    resolve_blueprints: bool,
+    resolve_vault_resources: bool,
 }
 
 impl Default for DatabaseTransactionStream {
@@ -40,6 +45,9 @@ impl Default for DatabaseTransactionStream {
             caught_up_timeout: Duration::from_millis(500),
             query_timeout: Duration::from_secs(30),
             database_url: "".to_string(),
+            bounds: StreamBounds::default(),
+            resolve_blueprints: false,
+            resolve_vault_resources: false,
         }
     }
 }
@@ -59,6 +67,25 @@ impl DatabaseTransactionStream {
         self
     }
 
+    /// Sets the last state version to fetch, which is inclusive.
+    /// The stream finishes at the first transaction past it, or once the
+    /// ledger got to it and there are no more transactions to fetch,
+    /// instead of polling forever.
+    pub fn until_state_version(mut self, until_state_version: u64) -> Self {
+        self.bounds.until_state_version = Some(until_state_version);
+        self
+    }
+
+    /// Sets the last confirmation time to fetch, which is inclusive.
+    /// The stream finishes at the first transaction confirmed after it.
+    pub fn until_confirmed_at(
+        mut self,
+        until_confirmed_at: chrono::DateTime<Utc>,
+    ) -> Self {
+        self.bounds.until_confirmed_at = Some(until_confirmed_at);
+        self
+    }
+
     /// Sets the max number of transactions to fetch per query.
     pub fn limit_per_page(mut self, limit_per_page: u32) -> Self {
         self.limit_per_page = limit_per_page;
@@ -86,6 +113,30 @@ impl DatabaseTransactionStream {
         self.query_timeout = timeout;
         self
     }
+
+    /// Sets whether to look up the package address and blueprint name
+    /// of components which emit events, so that handlers can be registered
+    /// per blueprint. Each component is looked up once, which is an extra
+    /// query for pages with new components. Disabled by default.
+    ///
+    /// If the lookup keeps failing, the stream fails with the error,
+    /// as the events can't be matched to their blueprint without it.
+    pub fn resolve_blueprints(mut self, resolve_blueprints: bool) -> Self {
+        self.resolve_blueprints = resolve_blueprints;
+        self
+    }
+
+    /// Sets whether to look up the resource of the vaults which emit events,
+    /// so that native handlers can be registered per resource. Each vault
+    /// is looked up once, but there are many vaults on ledger, so this is
+    /// disabled by default.
+    pub fn resolve_vault_resources(
+        mut self,
+        resolve_vault_resources: bool,
+    ) -> Self {
+        self.resolve_vault_resources = resolve_vault_resources;
+        self
+    }
 }
 
 /// A helper which is passed to the new task created by the stream.
@@ -98,6 +149,11 @@ struct DatabaseFetcher {
     state_version: u64,
     caught_up_timeout: Duration,
     query_timeout: Duration,
+    bounds: StreamBounds,
+    /// Whether the ledger got to the last state version of the bounds,
+    /// so that the next empty page ends the stream.
+    tip_reached_bound: bool,
+    entities: Option<EntityCache>,
     tx: tokio::sync::mpsc::Sender<Transaction>,
 }
 
@@ -108,6 +164,7 @@ impl DatabaseFetcher {
         state_version: u64,
         caught_up_timeout: Duration,
         query_timeout: Duration,
+        bounds: StreamBounds,
         tx: tokio::sync::mpsc::Sender<Transaction>,
     ) -> Result<Self, anyhow::Error> {
         let options = PgConnectOptions::from_str(&database_url)
@@ -120,6 +177,9 @@ impl DatabaseFetcher {
             state_version,
             caught_up_timeout,
             query_timeout,
+            bounds,
+            tip_reached_bound: false,
+            entities: None,
             tx,
         })
     }
@@ -191,7 +251,81 @@ impl DatabaseFetcher {
         Ok(transactions)
     }
 
-    async fn run(&mut self) {
+    /// Fills in the details of the entities which emitted events,
+    /// looking up the ones which aren't cached yet.
+    async fn resolve_entities(
+        &mut self,
+        transactions: &mut [Transaction],
+    ) -> Result<(), anyhow::Error> {
+        let Some(entities) = &mut self.entities else {
+            return Ok(());
+        };
+        let unknown = entities.unknown_entities(transactions);
+        if !unknown.is_empty() {
+            let query = sqlx::query_as::<
+                _,
+                (String, Option<String>, Option<String>, Option<String>),
+            >(
+                r#"
+                    SELECT
+                        e.address,
+                        p.address,
+                        e.blueprint_name,
+                        r.address
+                    FROM
+                        entities e
+                        left join entities p on p.id = e.package_id
+                        left join entities r on r.id = e.resource_entity_id
+                    WHERE
+                        e.address = ANY($1)
+                "#,
+            )
+            .bind(&unknown);
+            let rows =
+                timeout(self.query_timeout, query.fetch_all(&self.connection))
+                    .await??;
+            let resolved = rows.into_iter().map(
+                |(
+                    address,
+                    package_address,
+                    blueprint_name,
+                    resource_address,
+                )| {
+                    (
+                        address,
+                        EntityDetails {
+                            blueprint: package_address.zip(blueprint_name),
+                            resource_address,
+                        },
+                    )
+                },
+            );
+            entities.insert(unknown, resolved);
+        }
+        entities.fill_in(transactions);
+        Ok(())
+    }
+
+    /// Returns whether the ledger got to the last state version of the bounds,
+    /// after which there are no more transactions to fetch when a page is
+    /// empty. The transactions up to it may all be filtered out by the query,
+    /// so there may never be a row past the bound to end the stream.
+    async fn ledger_reached_bound(&self) -> Result<bool, anyhow::Error> {
+        let Some(until_state_version) = self.bounds.until_state_version else {
+            return Ok(false);
+        };
+        let query = sqlx::query_scalar::<_, Option<i64>>(
+            "SELECT MAX(state_version) FROM ledger_transactions",
+        );
+        let tip =
+            timeout(self.query_timeout, query.fetch_one(&self.connection))
+                .await??;
+        Ok(tip.is_some_and(|tip| tip as u64 >= until_state_version))
+    }
+
+    /// Fetches transactions from the database and sends them to the processor.
+    /// Fails if the entities which emitted events can't be resolved.
+    async fn run(&mut self) -> Result<StreamEnd, anyhow::Error> {
         loop {
             let mut response = self.next_batch().await;
             while let Err(err) = response {
@@ -201,14 +335,50 @@ impl DatabaseFetcher {
                 );
                 response = self.next_batch().await;
             }
-            let transactions = response.unwrap();
+            let mut transactions = response.unwrap();
+            let mut attempt = 1;
+            while let Err(err) = self.resolve_entities(&mut transactions).await
+            {
+                if attempt == RESOLVE_ATTEMPTS {
+                    return Err(err.context("Failed to resolve entities"));
+                }
+                log::warn!(
+                    "Error resolving entities: {:?}\n Trying again...",
+                    err
+                );
+                attempt += 1;
+                tokio::time::sleep(self.caught_up_timeout).await;
+            }
             if transactions.is_empty() {
+                if self.tip_reached_bound {
+                    return Ok(StreamEnd::BoundReached);
+                }
+                match self.ledger_reached_bound().await {
+                    // Transactions up to the bound may have been committed
+                    // after this page was fetched, so fetch one more.
+                    Ok(true) => {
+                        self.tip_reached_bound = true;
+                        continue;
+                    }
+                    Ok(false) => {}
+                    Err(err) => log::warn!(
+                        "Error fetching the ledger tip: {:?}\n Trying again...",
+                        err
+                    ),
+                }
                 tokio::time::sleep(self.caught_up_timeout).await;
             }
 
             for transaction in transactions {
+                if self.bounds.is_past(&transaction) {
+                    return Ok(StreamEnd::BoundReached);
+                }
+                let is_last = self.bounds.is_last(&transaction);
                 if self.tx.send(transaction).await.is_err() {
-                    return;
+                    return Ok(StreamEnd::Finished);
+                }
+                if is_last {
+                    return Ok(StreamEnd::BoundReached);
                 }
             }
         }
@@ -226,19 +396,42 @@ impl TransactionStream for DatabaseTransactionStream {
             self.state_version,
             self.caught_up_timeout,
             self.query_timeout,
+            self.bounds,
             tx,
         )
         .await?;
+        if self.resolve_blueprints || self.resolve_vault_resources {
+            fetcher.entities = Some(EntityCache::new(
+                self.resolve_blueprints,
+                self.resolve_vault_resources,
+            ));
+        }
         let handle = tokio::spawn(async move { fetcher.run().await });
         self.join_handle = Some(handle);
         Ok(rx)
     }
 
+    async fn start_from(
+        &mut self,
+        state_version: u64,
+    ) -> Result<Receiver<Transaction>, anyhow::Error> {
+        self.state_version = state_version;
+        self.start().await
+    }
+
     async fn stop(&mut self) {
         if let Some(handle) = self.join_handle.take() {
             handle.abort();
         }
     }
+
+    async fn end(&mut self) -> Result<StreamEnd, anyhow::Error> {
+        // The channel is closed when the task ends, so this doesn't wait.
+        match self.join_handle.take() {
+            Some(handle) => handle.await?,
+            None => Ok(StreamEnd::Finished),
+        }
+    }
 }
 
 #[derive(sqlx::FromRow, Debug)] // Ensure this derive to work with sqlx queries
@@ -282,6 +475,9 @@ impl From<EventEmitterIdentifier> for EventEmitter {
                 entity_type: entity.entity_type,
                 is_global: entity.is_global,
                 object_module_id,
+                package_address: None,
+                blueprint_name: None,
+                resource_address: None,
             },
             EventEmitterIdentifier::Function {
                 package_address,
@@ -0,0 +1,121 @@
+//! Resolves details of the entities which emit events, like the blueprints
+//! of components, for the transaction streams that can look them up.
+
+use crate::models::{EventEmitter, Transaction};
+use radix_client::gateway::models::EntityType;
+use std::collections::{HashMap, HashSet};
+
+/// How many times a stream tries to look up the details of entities
+/// before it fails, as events can't be matched to the handlers
+/// of their blueprint or resource without them.
+pub(crate) const RESOLVE_ATTEMPTS: u32 = 5;
+
+/// What a transaction stream found out about an entity.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EntityDetails {
+    /// The package address and blueprint name of a component.
+    pub blueprint: Option<(String, String)>,
+    /// The resource of a vault.
+    pub resource_address: Option<String>,
+}
+
+/// Caches the details of entities, which never change once
+/// an entity is created.
+#[derive(Debug, Default)]
+pub(crate) struct EntityCache {
+    entities: HashMap<String, EntityDetails>,
+    resolve_blueprints: bool,
+    resolve_vault_resources: bool,
+}
+
+impl EntityCache {
+    /// Creates a cache which looks up the blueprints of generic components,
+    /// the resources of vaults, or both.
+    pub fn new(
+        resolve_blueprints: bool,
+        resolve_vault_resources: bool,
+    ) -> Self {
+        Self {
+            entities: HashMap::new(),
+            resolve_blueprints,
+            resolve_vault_resources,
+        }
+    }
+
+    /// Returns the addresses of the entities which emitted events
+    /// in the transactions and which weren't looked up before.
+    /// Only generic components and vaults are included, depending on what
+    /// the cache resolves, as other native entities are handled by their
+    /// event type instead.
+    pub fn unknown_entities(
+        &self,
+        transactions: &[Transaction],
+    ) -> Vec<String> {
+        let mut unknown = HashSet::new();
+        for event in transactions.iter().flat_map(|t| &t.events) {
+            let EventEmitter::Method {
+                entity_address,
+                entity_type,
+                ..
+            } = &event.emitter
+            else {
+                continue;
+            };
+            let resolve = match entity_type {
+                EntityType::GlobalGenericComponent
+                | EntityType::InternalGenericComponent => {
+                    self.resolve_blueprints
+                }
+                EntityType::InternalFungibleVault
+                | EntityType::InternalNonFungibleVault => {
+                    self.resolve_vault_resources
+                }
+                _ => false,
+            };
+            if resolve && !self.entities.contains_key(entity_address) {
+                unknown.insert(entity_address.clone());
+            }
+        }
+        unknown.into_iter().collect()
+    }
+
+    /// Caches the result of looking up `entities`, where `resolved` holds
+    /// the address and details of the ones that were found. The others
+    /// are cached without details, so they are not looked up again.
+    pub fn insert(
+        &mut self,
+        entities: Vec<String>,
+        resolved: impl IntoIterator<Item = (String, EntityDetails)>,
+    ) {
+        for entity in entities {
+            self.entities.insert(entity, EntityDetails::default());
+        }
+        self.entities.extend(resolved);
+    }
+
+    /// Fills in the details of the cached entities
+    /// which emitted events in the transactions.
+    pub fn fill_in(&self, transactions: &mut [Transaction]) {
+        for event in transactions.iter_mut().flat_map(|t| &mut t.events) {
+            if let EventEmitter::Method {
+                entity_address,
+                package_address,
+                blueprint_name,
+                resource_address,
+                ..
+            } = &mut event.emitter
+            {
+                let Some(details) = self.entities.get(entity_address) else {
+                    continue;
+                };
+                if let Some((package, blueprint)) = &details.blueprint {
+                    *package_address = Some(package.clone());
+                    *blueprint_name = Some(blueprint.clone());
+                }
+                if details.resource_address.is_some() {
+                    resource_address.clone_from(&details.resource_address);
+                }
+            }
+        }
+    }
+}
@@ -3,10 +3,14 @@
 use std::{fs::File, path::Path};
 
 use async_trait::async_trait;
+use chrono::Utc;
 use serde::Deserialize;
 use tokio::sync::mpsc::Receiver;
 
-use crate::{models::Transaction, stream::TransactionStream};
+use crate::{
+    models::Transaction,
+    stream::{StreamBounds, TransactionStream},
+};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct FileTransaction {
@@ -36,6 +40,7 @@ impl From<FileTransaction> for Transaction {
 #[derive(Debug)]
 pub struct FileTransactionStream {
     transactions: Vec<FileTransaction>,
+    bounds: StreamBounds,
 }
 
 impl FileTransactionStream {
@@ -56,18 +61,51 @@ impl FileTransactionStream {
             _ => panic!("Unsupported file type"),
         };
 
-        Self { transactions }
+        Self {
+            transactions,
+            bounds: StreamBounds::default(),
+        }
+    }
+
+    /// Sets the last state version to read, which is inclusive.
+    pub fn until_state_version(mut self, until_state_version: u64) -> Self {
+        self.bounds.until_state_version = Some(until_state_version);
+        self
+    }
+
+    /// Sets the last confirmation time to read, which is inclusive.
+    pub fn until_confirmed_at(
+        mut self,
+        until_confirmed_at: chrono::DateTime<Utc>,
+    ) -> Self {
+        self.bounds.until_confirmed_at = Some(until_confirmed_at);
+        self
     }
 }
 
 #[async_trait]
 impl TransactionStream for FileTransactionStream {
     async fn start(&mut self) -> Result<Receiver<Transaction>, anyhow::Error> {
+        self.start_from(0).await
+    }
+
+    async fn start_from(
+        &mut self,
+        state_version: u64,
+    ) -> Result<Receiver<Transaction>, anyhow::Error> {
         let (tx, rx) = tokio::sync::mpsc::channel(32);
-        let transactions = self.transactions.clone();
+        let bounds = self.bounds;
+        let transactions: Vec<Transaction> = self
+            .transactions
+            .iter()
+            .filter(|transaction| transaction.state_version >= state_version)
+            .cloned()
+            .map(Transaction::from)
+            .take_while(|transaction| !bounds.is_past(transaction))
+            .collect();
         tokio::spawn(async move {
             for transaction in transactions.into_iter() {
-                if tx.send(transaction.into()).await.is_err() {
+                if tx.send(transaction).await.is_err() {
                     break;
                 }
             }
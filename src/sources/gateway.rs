@@ -1,11 +1,13 @@
 //! A transaction stream that fetches transactions from a Radix Gateway API.
 
+use super::entities::{EntityCache, EntityDetails, RESOLVE_ATTEMPTS};
 use crate::{
     encodings::programmatic_json_to_bytes,
     models::{Event, EventEmitter, Transaction},
-    stream::TransactionStream,
+    stream::{StreamBounds, StreamEnd, TransactionStream},
 };
 use async_trait::async_trait;
+use chrono::Utc;
 use radix_client::gateway::models::Event as GatewayEvent;
 use radix_client::{
     gateway::{
@@ -14,6 +16,8 @@ use radix_client::{
     },
     GatewayClientAsync,
 };
+use serde::Deserialize;
+use serde_json::json;
 use std::time::Duration;
 use tokio::{
     sync::mpsc::{Receiver, Sender},
@@ -31,6 +35,9 @@ impl From<GatewayEvent> for Event {
                 entity_type: entity.entity_type,
                 is_global: entity.is_global,
                 object_module_id,
+                package_address: None,
+                blueprint_name: None,
+                resource_address: None,
             },
             EventEmitterIdentifier::Function {
                 package_address,
@@ -79,7 +86,10 @@ pub struct GatewayTransactionStream {
     limit_per_page: u32,
     buffer_capacity: u64,
     caught_up_timeout: Duration,
-    handle: Option<tokio::task::JoinHandle<()>>,
+    bounds: StreamBounds,
+    resolve_blueprints: bool,
+    resolve_vault_resources: bool,
+    handle: Option<tokio::task::JoinHandle<Result<StreamEnd, anyhow::Error>>>,
 }
 
 impl Default for GatewayTransactionStream {
@@ -90,6 +100,9 @@ impl Default for GatewayTransactionStream {
             limit_per_page: 100,
             buffer_capacity: 10_000,
             caught_up_timeout: Duration::from_millis(500),
+            bounds: StreamBounds::default(),
+            resolve_blueprints: false,
+            resolve_vault_resources: false,
             handle: None,
         }
     }
@@ -108,6 +121,25 @@ impl GatewayTransactionStream {
         self
     }
 
+    /// Sets the last state version to fetch, which is inclusive.
+    /// The stream finishes at the first transaction past it, or once the
+    /// ledger got to it and there are no more transactions to fetch,
+    /// instead of polling forever.
+    pub fn until_state_version(mut self, until_state_version: u64) -> Self {
+        self.bounds.until_state_version = Some(until_state_version);
+        self
+    }
+
+    /// Sets the last confirmation time to fetch, which is inclusive.
+    /// The stream finishes at the first transaction confirmed after it.
+    pub fn until_confirmed_at(
+        mut self,
+        until_confirmed_at: chrono::DateTime<Utc>,
+    ) -> Self {
+        self.bounds.until_confirmed_at = Some(until_confirmed_at);
+        self
+    }
+
     /// Sets the URL of the Radix Gateway API to fetch transactions from.
     pub fn gateway_url(mut self, gateway_url: String) -> Self {
         self.gateway_url = gateway_url;
@@ -136,12 +168,134 @@ impl GatewayTransactionStream {
         self.caught_up_timeout = caught_up_timeout;
         self
     }
+
+    /// Sets whether to look up the package address and blueprint name
+    /// of components which emit events, so that handlers can be registered
+    /// per blueprint. Each component is looked up once, with the entity
+    /// details endpoint of the Gateway API, which is an extra request for
+    /// pages with new components. Disabled by default.
+    ///
+    /// If the lookup keeps failing, the stream fails with the error,
+    /// as the events can't be matched to their blueprint without it.
+    pub fn resolve_blueprints(mut self, resolve_blueprints: bool) -> Self {
+        self.resolve_blueprints = resolve_blueprints;
+        self
+    }
+
+    /// Sets whether to look up the resource of the vaults which emit events,
+    /// so that native handlers can be registered per resource. Each vault
+    /// is looked up once, but there are many vaults on ledger, so this is
+    /// disabled by default.
+    pub fn resolve_vault_resources(
+        mut self,
+        resolve_vault_resources: bool,
+    ) -> Self {
+        self.resolve_vault_resources = resolve_vault_resources;
+        self
+    }
+}
+
+/// Looks up the blueprints of components and the resources of vaults
+/// with the entity details endpoint of the Gateway API.
+struct EntityResolver {
+    client: reqwest::Client,
+    url: String,
+    cache: EntityCache,
+}
+
+#[derive(Deserialize)]
+struct EntityDetailsResponse {
+    items: Vec<EntityDetailsItem>,
+}
+
+#[derive(Deserialize)]
+struct EntityDetailsItem {
+    address: String,
+    details: Option<GatewayEntityDetails>,
+}
+
+/// The details of a component or a vault. Other entities
+/// have no package, blueprint or resource.
+#[derive(Deserialize)]
+struct GatewayEntityDetails {
+    package_address: Option<String>,
+    blueprint_name: Option<String>,
+    resource_address: Option<String>,
+}
+
+impl EntityResolver {
+    /// The maximum number of addresses the Gateway API accepts per request.
+    const ADDRESSES_PER_REQUEST: usize = 20;
+
+    fn new(cache: EntityCache, gateway_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: format!(
+                "{}/state/entity/details",
+                gateway_url.trim_end_matches('/')
+            ),
+            cache,
+        }
+    }
+
+    /// Fills in the details of the entities which emitted events,
+    /// looking up the ones which aren't cached yet.
+    async fn resolve(
+        &mut self,
+        transactions: &mut [Transaction],
+    ) -> Result<(), anyhow::Error> {
+        let entities = self.cache.unknown_entities(transactions);
+        let mut resolved = Vec::new();
+        for addresses in entities.chunks(Self::ADDRESSES_PER_REQUEST) {
+            let response: EntityDetailsResponse = self
+                .client
+                .post(&self.url)
+                .json(&json!({ "addresses": addresses }))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            resolved.extend(response.items.into_iter().filter_map(|item| {
+                let details = item.details?;
+                Some((
+                    item.address,
+                    EntityDetails {
+                        blueprint: details
+                            .package_address
+                            .zip(details.blueprint_name),
+                        resource_address: details.resource_address,
+                    },
+                ))
+            }));
+        }
+        self.cache.insert(entities, resolved);
+        self.cache.fill_in(transactions);
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct GatewayStatusResponse {
+    ledger_state: LedgerState,
+}
+
+#[derive(Deserialize)]
+struct LedgerState {
+    state_version: u64,
 }
 
 /// A fetcher which is passed to the new task created by the stream.
 struct GatewayFetcher {
     stream: TransactionStreamAsync,
+    client: reqwest::Client,
+    status_url: String,
     caught_up_timeout: Duration,
+    bounds: StreamBounds,
+    /// Whether the ledger got to the last state version of the bounds,
+    /// so that the next empty page ends the stream.
+    tip_reached_bound: bool,
+    entities: Option<EntityResolver>,
     tx: Sender<Transaction>,
 }
 
@@ -151,8 +305,16 @@ impl GatewayFetcher {
         from_state_version: u64,
         limit_per_page: u32,
         caught_up_timeout: Duration,
+        bounds: StreamBounds,
+        entities: Option<EntityCache>,
         tx: Sender<Transaction>,
     ) -> Self {
+        let entities =
+            entities.map(|cache| EntityResolver::new(cache, &gateway_url));
+        let status_url = format!(
+            "{}/status/gateway-status",
+            gateway_url.trim_end_matches('/')
+        );
         let client = GatewayClientAsync::new(gateway_url);
         let stream = TransactionStreamAsync::new(
             &client,
@@ -161,13 +323,39 @@ impl GatewayFetcher {
         );
         Self {
             stream,
+            client: reqwest::Client::new(),
+            status_url,
             tx,
             caught_up_timeout,
+            bounds,
+            tip_reached_bound: false,
+            entities,
         }
     }
 
+    /// Returns whether the ledger got to the last state version of the bounds,
+    /// after which there are no more transactions to fetch when a page is
+    /// empty. The transactions up to it may all be left out by the gateway,
+    /// so there may never be one past the bound to end the stream.
+    async fn ledger_reached_bound(&self) -> Result<bool, anyhow::Error> {
+        let Some(until_state_version) = self.bounds.until_state_version else {
+            return Ok(false);
+        };
+        let status: GatewayStatusResponse = self
+            .client
+            .post(&self.status_url)
+            .json(&json!({}))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(status.ledger_state.state_version >= until_state_version)
+    }
+
     /// Fetches transactions from the gateway and sends them to the transaction processor.
-    async fn run(&mut self) {
+    /// Fails if the entities which emitted events can't be resolved.
+    async fn run(&mut self) -> Result<StreamEnd, anyhow::Error> {
         loop {
             let mut response = self.stream.next().await;
             while let Err(err) = response {
@@ -179,14 +367,51 @@ impl GatewayFetcher {
             }
             let response = response.unwrap();
             if response.items.is_empty() {
+                if self.tip_reached_bound {
+                    return Ok(StreamEnd::BoundReached);
+                }
+                match self.ledger_reached_bound().await {
+                    // Transactions up to the bound may have been committed
+                    // after this page was fetched, so fetch one more.
+                    Ok(true) => {
+                        self.tip_reached_bound = true;
+                        continue;
+                    }
+                    Ok(false) => {}
+                    Err(err) => log::warn!(
+                        "Error fetching the ledger tip: {:?}\n Trying again...",
+                        err
+                    ),
+                }
                 sleep(self.caught_up_timeout).await;
             }
-            let transactions: Vec<Transaction> =
+            let mut transactions: Vec<Transaction> =
                 response.items.into_iter().map(|item| item.into()).collect();
+            if let Some(entities) = &mut self.entities {
+                let mut attempt = 1;
+                while let Err(err) = entities.resolve(&mut transactions).await {
+                    if attempt == RESOLVE_ATTEMPTS {
+                        return Err(err.context("Failed to resolve entities"));
+                    }
+                    log::warn!(
+                        "Error resolving entities: {:?}\n Trying again...",
+                        err
+                    );
+                    attempt += 1;
+                    sleep(self.caught_up_timeout).await;
+                }
+            }
             for transaction in transactions {
+                if self.bounds.is_past(&transaction) {
+                    return Ok(StreamEnd::BoundReached);
+                }
+                let is_last = self.bounds.is_last(&transaction);
                 // Stop fetching if the receiving end is closed
                 if self.tx.send(transaction).await.is_err() {
-                    return;
+                    return Ok(StreamEnd::Finished);
+                }
+                if is_last {
+                    return Ok(StreamEnd::BoundReached);
                 }
             }
         }
@@ -203,6 +428,15 @@ impl TransactionStream for GatewayTransactionStream {
             self.from_state_version,
             self.limit_per_page,
             self.caught_up_timeout,
+            self.bounds,
+            (self.resolve_blueprints || self.resolve_vault_resources).then(
+                || {
+                    EntityCache::new(
+                        self.resolve_blueprints,
+                        self.resolve_vault_resources,
+                    )
+                },
+            ),
             tx,
         );
         let handle = tokio::spawn(async move { fetcher.run().await });
@@ -210,9 +444,25 @@ impl TransactionStream for GatewayTransactionStream {
         Ok(rx)
     }
 
+    async fn start_from(
+        &mut self,
+        state_version: u64,
+    ) -> Result<Receiver<Transaction>, anyhow::Error> {
+        self.from_state_version = state_version;
+        self.start().await
+    }
+
     async fn stop(&mut self) {
         if let Some(handle) = self.handle.take() {
             handle.abort();
         }
     }
+
+    async fn end(&mut self) -> Result<StreamEnd, anyhow::Error> {
+        // The channel is closed when the task ends, so this doesn't wait.
+        match self.handle.take() {
+            Some(handle) => handle.await?,
+            None => Ok(StreamEnd::Finished),
+        }
+    }
 }
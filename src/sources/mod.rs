@@ -10,6 +10,8 @@
 pub mod channel;
 #[cfg(feature = "database")]
 pub mod database;
+#[cfg(any(feature = "gateway", feature = "database"))]
+mod entities;
 #[cfg(feature = "file")]
 pub mod file;
 #[cfg(feature = "gateway")]
@@ -3,6 +3,7 @@
 
 use crate::models::Transaction;
 use async_trait::async_trait;
+use chrono::Utc;
 use std::fmt::Debug;
 use tokio::sync::mpsc::Receiver;
 
@@ -31,12 +32,92 @@ use tokio::sync::mpsc::Receiver;
 /// use to detect that it no longer needs to fetch transactions.
 /// This is recommended to avoid leaking a fetching task.
 /// An explicit stop() method is still useful in more advanced cases.
+///
+/// A stream should support being started again after it was stopped,
+/// which the processor does to seek to another state version
+/// (see [`crate::handle::ProcessorHandle::seek`]). If that's not
+/// possible, it should return `false` from `can_restart`, and the
+/// processor ignores seeks.
 #[async_trait]
-pub trait TransactionStream: Debug {
+pub trait TransactionStream: Debug + Send {
     // Starts the stream. This may involve spawning a new task,
     // which pushes transactions to the channel that is returned.
     async fn start(&mut self) -> Result<Receiver<Transaction>, anyhow::Error>;
 
+    /// Starts the stream from the given state version, which is inclusive.
+    /// The processor calls this instead of `start` when it resumes
+    /// from a checkpoint (see [`crate::checkpoints`]).
+    ///
+    /// The default implementation ignores the state version and calls `start`.
+    /// This is still correct, because the processor skips transactions
+    /// it has already finished, but it's more efficient for a stream
+    /// to not fetch these transactions at all.
+    async fn start_from(
+        &mut self,
+        state_version: u64,
+    ) -> Result<Receiver<Transaction>, anyhow::Error> {
+        let _ = state_version;
+        self.start().await
+    }
+
     // Explicitly stop the stream
     async fn stop(&mut self);
+
+    /// Returns whether the stream can be started again, which the processor
+    /// checks before it seeks. Defaults to `true`.
+    fn can_restart(&self) -> bool {
+        true
+    }
+
+    /// Returns why the stream closed its channel, or the error it failed
+    /// with. The processor calls this once the channel is closed, and stops
+    /// with the matching [`StopReason`][crate::processor::StopReason] or
+    /// returns the error. Defaults to [`StreamEnd::Finished`].
+    async fn end(&mut self) -> Result<StreamEnd, anyhow::Error> {
+        Ok(StreamEnd::Finished)
+    }
+}
+
+/// Why a stream closed its channel, see [`TransactionStream::end`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEnd {
+    /// There are no more transactions, like at the end of a file.
+    Finished,
+    /// The stream got to the end of its [`StreamBounds`].
+    BoundReached,
+}
+
+/// Inclusive upper bounds for a stream of transactions, which make a stream
+/// or processor stop at a state version or timestamp instead of running forever.
+/// The sources provided by the framework close their channel once they
+/// get past the bounds, and so does the processor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamBounds {
+    /// The last state version to include.
+    pub until_state_version: Option<u64>,
+    /// The last confirmation time to include.
+    pub until_confirmed_at: Option<chrono::DateTime<Utc>>,
+}
+
+impl StreamBounds {
+    /// Returns whether the transaction is after the bounds,
+    /// which means it and all following transactions should be left out.
+    pub fn is_past(&self, transaction: &Transaction) -> bool {
+        self.until_state_version
+            .is_some_and(|until| transaction.state_version > until)
+            || self.until_confirmed_at.is_some_and(|until| {
+                transaction
+                    .confirmed_at
+                    .is_some_and(|confirmed_at| confirmed_at > until)
+            })
+    }
+
+    /// Returns whether the transaction is the last one within the bounds,
+    /// so that there is no need to wait for the next one. This can only be
+    /// known for the state version, because multiple transactions
+    /// can have the same timestamp.
+    pub fn is_last(&self, transaction: &Transaction) -> bool {
+        self.until_state_version
+            .is_some_and(|until| transaction.state_version >= until)
+    }
 }
@@ -17,7 +17,8 @@ To use this macro, the handler function must conform to a predefined
 signature.
 A transaction handler function must:
 - Be an async function
-- Take a single argument of type `TransactionHandlerContext<YOUR_STATE>`
+- Take a single argument of type `TransactionHandlerContext<YOUR_STATE, YOUR_TRANSACTION_CONTEXT>`,
+  where the transaction context is the one of the event handlers, and defaults to the unit type
 - Return a `Result<(), TransactionHandlerError>`
 
 You can use the following template to create a transaction handler:
@@ -28,9 +29,8 @@ async fn transaction_handler_name(
     // Context the handler will get from the framework.
     // This includes the current ledger transaction we're in
     // and the global state. It is parametrized by the
-    // app state and the transaction context type, but the context is optional,
-    // and defaults to the unit type.
-    context: TransactionHandlerContext<YOUR_STATE>,
+    // app state and the transaction context type of the event handlers.
+    context: TransactionHandlerContext<YOUR_STATE, TransactionContext>,
 ) -> Result<(), TransactionHandlerError> {
     // Do something like start a database transaction
     let mut transaction_context = TransactionContext { tx: start_transaction() }
@@ -61,7 +61,8 @@ async fn transaction_handler_name(
     Ok(())
 }
 ```
-Now, we can simply pass in the handler to the [`TransactionStreamProcessor`][crate::processor::TransactionStreamProcessor].
+Now, we can simply pass in the handler to
+[`TransactionStreamProcessor::with_transaction_handler`][crate::processor::TransactionStreamProcessor::with_transaction_handler].
 It is now secretly a struct that implements the [`TransactionHandler`] trait.
 */
 
@@ -72,26 +73,124 @@ It is now secretly a struct that implements the [`TransactionHandler`] trait.
 /// database transactions or other atomic operations at the
 /// transaction level.
 use crate::{
-    error::TransactionHandlerError, event_handler::HandlerRegistry,
-    models::Transaction, processor::EventProcessor,
+    error::TransactionHandlerError,
+    event_handler::HandlerRegistry,
+    handle::ProcessorHandle,
+    models::Transaction,
+    processor::{BatchEventProcessor, EventProcessor},
 };
 use async_trait::async_trait;
+use std::time::Duration;
 
 #[allow(non_camel_case_types)]
 #[async_trait]
-pub trait TransactionHandler<STATE>: 'static {
+pub trait TransactionHandler<STATE, TRANSACTION_CONTEXT = ()>:
+    Send + Sync + 'static
+{
     async fn handle(
         &self,
-        input: TransactionHandlerContext<'_, STATE>,
+        input: TransactionHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
     ) -> Result<(), TransactionHandlerError>;
+
+    /// Whether this handler writes the checkpoint itself when `store_checkpoint`
+    /// is set, like the handlers in [`crate::checkpoints`]. The processor then
+    /// doesn't save it to its [`CheckpointStore`][crate::checkpoints::CheckpointStore]
+    /// again after the handler succeeded. Defaults to `false`.
+    fn stores_checkpoint(&self) -> bool {
+        false
+    }
 }
 
 #[allow(non_camel_case_types)]
 /// A struct that holds the context for a transaction handler,
 /// which is passed to the handler when it is called.
-pub struct TransactionHandlerContext<'a, STATE> {
+///
+/// TRANSACTION_CONTEXT is the transaction context of the event handlers
+/// in the registry, which the transaction handler creates and passes to
+/// [`EventProcessor::process_events`]. It defaults to the unit type.
+pub struct TransactionHandlerContext<'a, STATE, TRANSACTION_CONTEXT = ()> {
     pub state: &'a mut STATE,
     pub transaction: &'a Transaction,
     pub event_processor: &'a mut EventProcessor<'a>,
-    pub handler_registry: &'a mut HandlerRegistry,
+    pub handler_registry: &'a mut HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+    /// The current attempt at handling this transaction, starting at 1.
+    /// It increases each time the transaction is retried.
+    pub attempt: u32,
+    /// Time since the first attempt at handling this transaction started.
+    pub elapsed: Duration,
+    /// Whether a transaction handler which writes checkpoints itself should
+    /// store this transaction's state version. This is false for replays of
+    /// dead letters, which are older than the checkpoint, and for the shards of a
+    /// [`PartitionedTransactionStreamProcessor`][crate::partitioned::PartitionedTransactionStreamProcessor],
+    /// which only process part of the events.
+    pub store_checkpoint: bool,
+    /// A handle to control the processor, for example to pause it.
+    pub handle: &'a ProcessorHandle,
+}
+
+/// Savepoints in a transaction context, like a database transaction.
+/// They let [`EventProcessor::process_events_with_savepoints`] undo the writes
+/// of an event handler attempt which failed, so that a dead-lettered event
+/// doesn't leave partial writes behind when the rest of the transaction commits.
+///
+/// It is implemented for the transaction contexts in [`crate::checkpoints`].
+#[async_trait]
+pub trait Savepoints: Send {
+    /// Sets a savepoint before an attempt of an event handler.
+    async fn savepoint(&mut self) -> Result<(), anyhow::Error>;
+    /// Undoes the writes since the last savepoint, after the attempt failed.
+    async fn rollback_to_savepoint(&mut self) -> Result<(), anyhow::Error>;
+    /// Keeps the writes since the last savepoint, after the attempt succeeded.
+    async fn release_savepoint(&mut self) -> Result<(), anyhow::Error>;
+}
+
+/// A trait that defines a batch transaction handler.
+/// Instead of a single transaction, it receives a batch of
+/// consecutive transactions, so that it can process their events
+/// inside one database transaction and commit once.
+/// This makes catching up with the ledger a lot faster.
+///
+/// Set it using [`TransactionStreamProcessor::batch_transaction_handler`][crate::processor::TransactionStreamProcessor::batch_transaction_handler].
+/// The batch size adapts to how far the processor is behind the ledger tip.
+///
+/// If a batch fails for good, its transactions are handled again one at a time.
+/// Only the writes to the transaction context are rolled back in between:
+/// changes to the in-memory state are not, so handlers which keep state should
+/// only change it in a way that can be applied again, or derive it from what
+/// was committed. Dead letters of failed events are only sent once the batch
+/// succeeded, so they are not sent twice.
+#[allow(non_camel_case_types)]
+#[async_trait]
+pub trait BatchTransactionHandler<STATE, TRANSACTION_CONTEXT = ()>:
+    Send + Sync + 'static
+{
+    async fn handle(
+        &self,
+        input: BatchTransactionHandlerContext<'_, STATE, TRANSACTION_CONTEXT>,
+    ) -> Result<(), TransactionHandlerError>;
+
+    /// Whether this handler writes the checkpoint of the last transaction
+    /// of the batch itself. See [`TransactionHandler::stores_checkpoint`].
+    fn stores_checkpoint(&self) -> bool {
+        false
+    }
+}
+
+#[allow(non_camel_case_types)]
+/// A struct that holds the context for a batch transaction handler,
+/// which is passed to the handler when it is called.
+pub struct BatchTransactionHandlerContext<'a, STATE, TRANSACTION_CONTEXT = ()> {
+    pub state: &'a mut STATE,
+    /// The consecutive transactions in this batch, ordered by state version.
+    /// Some of them may not have any events with a handler.
+    pub transactions: &'a [Transaction],
+    pub event_processor: &'a mut BatchEventProcessor<'a>,
+    pub handler_registry: &'a mut HandlerRegistry<STATE, TRANSACTION_CONTEXT>,
+    /// The current attempt at handling this batch, starting at 1.
+    /// It increases each time the batch is retried.
+    pub attempt: u32,
+    /// Time since the first attempt at handling this batch started.
+    pub elapsed: Duration,
+    /// A handle to control the processor, for example to pause it.
+    pub handle: &'a ProcessorHandle,
 }